@@ -0,0 +1,94 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{redis_type::RedisType, RedisWritable};
+
+/// Caps how many clients can be connected at once, like Redis's `maxclients`.
+#[derive(Debug, Clone)]
+pub struct ClientLimiter {
+    max_clients: usize,
+    connected: Arc<AtomicUsize>,
+}
+
+/// A connected client's place under the limit, given back when dropped.
+#[derive(Debug)]
+pub struct ClientSlot {
+    connected: Arc<AtomicUsize>,
+}
+
+impl ClientLimiter {
+    pub fn new(max_clients: usize) -> Self {
+        Self {
+            max_clients,
+            connected: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Takes a slot for a new client, or `None` if `max_clients` are already connected.
+    pub fn try_acquire(&self) -> Option<ClientSlot> {
+        self.connected
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |connected| {
+                (connected < self.max_clients).then_some(connected + 1)
+            })
+            .ok()
+            .map(|_| ClientSlot {
+                connected: Arc::clone(&self.connected),
+            })
+    }
+
+    pub fn connected(&self) -> usize {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    /// Tells a client over the limit why it's about to be disconnected.
+    pub async fn reject(stream: &mut (impl AsyncWrite + Unpin)) -> std::io::Result<()> {
+        let error = RedisType::simple_error("ERR max number of clients reached");
+        stream.write_all(&error.write_as_protocol()).await?;
+        stream.shutdown().await
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.connected.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[test]
+    fn test_slots_are_limited_and_released() {
+        let limiter = ClientLimiter::new(2);
+
+        let first = limiter.try_acquire().unwrap();
+        let _second = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+        assert_eq!(limiter.connected(), 2);
+
+        drop(first);
+        assert_eq!(limiter.connected(), 1);
+        assert!(limiter.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reject_extra_client() {
+        let limiter = ClientLimiter::new(1);
+        let _slot = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+
+        let (mut client, mut server) = tokio::io::duplex(64);
+        ClientLimiter::reject(&mut server).await.unwrap();
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(reply, "-ERR max number of clients reached\r\n");
+    }
+}
@@ -0,0 +1,1052 @@
+/// Static metadata about a command. This is the single source of truth for command flags, so
+/// every `RedisCommand` variant needs an entry here.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    // Documentation served by COMMAND DOCS, following the layout of Redis's command JSON files
+    pub summary: &'static str,
+    pub since: &'static str,
+    pub group: &'static str,
+    pub arguments: &'static [CommandArg],
+    // Modifies the dataset, so it's replicated and rejected on read only replicas
+    pub write: bool,
+    pub keys: Option<KeySpec>,
+}
+
+/// A top level argument as listed by COMMAND DOCS. `arg_type` is one of Redis's argument types,
+/// like `key`, `string`, `integer` or `oneof`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandArg {
+    pub name: &'static str,
+    pub arg_type: &'static str,
+    pub optional: bool,
+}
+
+/// Where the keys are in a command's arguments, counting the command name as argument 0, and
+/// how the command accesses them (`RO`, `RW`, `OW`, `access`, `update`, `insert`...).
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeySpec {
+    pub first: usize,
+    // Negative values count from the end, so -1 is the last argument
+    pub last: isize,
+    pub step: usize,
+    pub flags: &'static [&'static str],
+}
+
+impl KeySpec {
+    /// The keys found in `args`, or `None` if there aren't enough arguments to hold them.
+    pub fn extract_keys<'a>(&self, args: &'a [String]) -> Option<Vec<&'a String>> {
+        let last = if self.last < 0 {
+            args.len().checked_sub(self.last.unsigned_abs())?
+        } else {
+            self.last as usize
+        };
+
+        if self.first > last || last >= args.len() {
+            return None;
+        }
+
+        Some(args[self.first..=last].iter().step_by(self.step).collect())
+    }
+}
+
+pub const COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec {
+        name: "ping",
+        summary: "Returns the server's liveliness response.",
+        since: "1.0.0",
+        group: "connection",
+        arguments: &[
+            CommandArg {
+                name: "message",
+                arg_type: "string",
+                optional: true,
+            },
+        ],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "echo",
+        summary: "Returns the given string.",
+        since: "1.0.0",
+        group: "connection",
+        arguments: &[
+            CommandArg {
+                name: "message",
+                arg_type: "string",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "set",
+        summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+        since: "1.0.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "value",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "condition",
+                arg_type: "oneof",
+                optional: true,
+            },
+            CommandArg {
+                name: "expiration",
+                arg_type: "oneof",
+                optional: true,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["OW", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "get",
+        summary: "Returns the string value of a key.",
+        since: "1.0.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO", "access"],
+        }),
+    },
+    CommandSpec {
+        name: "append",
+        summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "value",
+                arg_type: "string",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "insert"],
+        }),
+    },
+    CommandSpec {
+        name: "getrange",
+        summary: "Returns a substring of the string stored at a key.",
+        since: "2.4.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "start",
+                arg_type: "integer",
+                optional: false,
+            },
+            CommandArg {
+                name: "end",
+                arg_type: "integer",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO", "access"],
+        }),
+    },
+    CommandSpec {
+        name: "incr",
+        summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "access", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "decr",
+        summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "access", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "incrby",
+        summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "increment",
+                arg_type: "integer",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "access", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "decrby",
+        summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        group: "string",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "decrement",
+                arg_type: "integer",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "access", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "info",
+        summary: "Returns information and statistics about the server.",
+        since: "1.0.0",
+        group: "server",
+        arguments: &[
+            CommandArg {
+                name: "section",
+                arg_type: "string",
+                optional: true,
+            },
+        ],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "replconf",
+        summary: "An internal command for configuring the replication stream.",
+        since: "3.0.0",
+        group: "server",
+        arguments: &[],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "psync",
+        summary: "An internal command used in replication.",
+        since: "2.8.0",
+        group: "server",
+        arguments: &[
+            CommandArg {
+                name: "replicationid",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "offset",
+                arg_type: "integer",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "debug",
+        summary: "A container for debugging commands.",
+        since: "1.0.0",
+        group: "server",
+        arguments: &[],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "replicaof",
+        summary: "Configures a server as replica of another, or promotes it to a master.",
+        since: "5.0.0",
+        group: "server",
+        arguments: &[
+            CommandArg {
+                name: "host",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "port",
+                arg_type: "integer",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "command",
+        summary: "Returns detailed information about all commands.",
+        since: "2.8.13",
+        group: "server",
+        arguments: &[],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "failover",
+        summary: "Starts a coordinated failover from a server to one of its replicas.",
+        since: "6.2.0",
+        group: "server",
+        arguments: &[
+            CommandArg {
+                name: "target",
+                arg_type: "block",
+                optional: true,
+            },
+            CommandArg {
+                name: "abort",
+                arg_type: "pure-token",
+                optional: true,
+            },
+            CommandArg {
+                name: "milliseconds",
+                arg_type: "integer",
+                optional: true,
+            },
+        ],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "memory",
+        summary: "A container for memory diagnostics commands.",
+        since: "4.0.0",
+        group: "server",
+        arguments: &[],
+        write: false,
+        // Only MEMORY USAGE takes a key, right after the subcommand
+        keys: Some(KeySpec {
+            first: 2,
+            last: 2,
+            step: 1,
+            flags: &["RO"],
+        }),
+    },
+    CommandSpec {
+        name: "dbsize",
+        summary: "Returns the number of keys in the database.",
+        since: "1.0.0",
+        group: "server",
+        arguments: &[],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "time",
+        summary: "Returns the server time.",
+        since: "2.6.0",
+        group: "server",
+        arguments: &[],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "config",
+        summary: "A container for server configuration commands.",
+        since: "2.0.0",
+        group: "server",
+        arguments: &[],
+        write: false,
+        keys: None,
+    },
+    CommandSpec {
+        name: "object",
+        summary: "A container for object introspection commands.",
+        since: "2.2.3",
+        group: "generic",
+        arguments: &[],
+        write: false,
+        // OBJECT ENCODING takes its key right after the subcommand
+        keys: Some(KeySpec {
+            first: 2,
+            last: 2,
+            step: 1,
+            flags: &["RO"],
+        }),
+    },
+    CommandSpec {
+        name: "type",
+        summary: "Determines the type of value stored at a key.",
+        since: "1.0.0",
+        group: "generic",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO"],
+        }),
+    },
+    CommandSpec {
+        name: "del",
+        summary: "Deletes one or more keys.",
+        since: "1.0.0",
+        group: "generic",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: -1,
+            step: 1,
+            flags: &["RM", "delete"],
+        }),
+    },
+    CommandSpec {
+        name: "exists",
+        summary: "Determines whether one or more keys exist.",
+        since: "1.0.0",
+        group: "generic",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: -1,
+            step: 1,
+            flags: &["RO"],
+        }),
+    },
+    CommandSpec {
+        name: "xadd",
+        summary: "Appends a new message to a stream. Creates the key if it doesn't exist.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "id-selector",
+                arg_type: "oneof",
+                optional: false,
+            },
+            CommandArg {
+                name: "data",
+                arg_type: "block",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "insert"],
+        }),
+    },
+    CommandSpec {
+        name: "xlen",
+        summary: "Return the number of messages in a stream.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO"],
+        }),
+    },
+    CommandSpec {
+        name: "xrange",
+        summary: "Returns the messages from a stream within a range of IDs.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "start",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "end",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "count",
+                arg_type: "integer",
+                optional: true,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO", "access"],
+        }),
+    },
+    CommandSpec {
+        name: "xread",
+        summary: "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            CommandArg {
+                name: "count",
+                arg_type: "integer",
+                optional: true,
+            },
+            CommandArg {
+                name: "milliseconds",
+                arg_type: "integer",
+                optional: true,
+            },
+            CommandArg {
+                name: "streams",
+                arg_type: "block",
+                optional: false,
+            },
+        ],
+        write: false,
+        // The keys follow the STREAMS keyword, so they can't be found by position
+        keys: None,
+    },
+    CommandSpec {
+        name: "xgroup",
+        summary: "A container for consumer groups commands.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[],
+        write: true,
+        // XGROUP CREATE takes its key right after the subcommand
+        keys: Some(KeySpec {
+            first: 2,
+            last: 2,
+            step: 1,
+            flags: &["RW", "insert"],
+        }),
+    },
+    CommandSpec {
+        name: "xreadgroup",
+        summary: "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            CommandArg {
+                name: "group-block",
+                arg_type: "block",
+                optional: false,
+            },
+            CommandArg {
+                name: "count",
+                arg_type: "integer",
+                optional: true,
+            },
+            CommandArg {
+                name: "streams",
+                arg_type: "block",
+                optional: false,
+            },
+        ],
+        // Delivering entries changes the group's state
+        write: true,
+        keys: None,
+    },
+    CommandSpec {
+        name: "xack",
+        summary: "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "group",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "id",
+                arg_type: "string",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "xinfo",
+        summary: "A container for stream introspection commands.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[],
+        write: false,
+        // XINFO STREAM and GROUPS take their key right after the subcommand
+        keys: Some(KeySpec {
+            first: 2,
+            last: 2,
+            step: 1,
+            flags: &["RO"],
+        }),
+    },
+    CommandSpec {
+        name: "pfadd",
+        summary: "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist.",
+        since: "2.8.9",
+        group: "hyperloglog",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "element",
+                arg_type: "string",
+                optional: true,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "insert"],
+        }),
+    },
+    CommandSpec {
+        name: "pfcount",
+        summary: "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s).",
+        since: "2.8.9",
+        group: "hyperloglog",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: -1,
+            step: 1,
+            flags: &["RO"],
+        }),
+    },
+    CommandSpec {
+        name: "pfmerge",
+        summary: "Merges one or more HyperLogLog values into a single key.",
+        since: "2.8.9",
+        group: "hyperloglog",
+        arguments: &[
+            CommandArg {
+                name: "destkey",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "sourcekey",
+                arg_type: "key",
+                optional: true,
+            },
+        ],
+        write: true,
+        // The destination is written and the sources only read, but one spec covers them all
+        keys: Some(KeySpec {
+            first: 1,
+            last: -1,
+            step: 1,
+            flags: &["RW", "insert"],
+        }),
+    },
+    CommandSpec {
+        name: "geoadd",
+        summary: "Adds one or more members to a geospatial index. The key is created if it doesn't exist.",
+        since: "3.2.0",
+        group: "geo",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "data",
+                arg_type: "block",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "geopos",
+        summary: "Returns the longitude and latitude of members from a geospatial index.",
+        since: "3.2.0",
+        group: "geo",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "member",
+                arg_type: "string",
+                optional: true,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO", "access"],
+        }),
+    },
+    CommandSpec {
+        name: "geodist",
+        summary: "Returns the distance between two members of a geospatial index.",
+        since: "3.2.0",
+        group: "geo",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "member1",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "member2",
+                arg_type: "string",
+                optional: false,
+            },
+            CommandArg {
+                name: "unit",
+                arg_type: "oneof",
+                optional: true,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO", "access"],
+        }),
+    },
+    CommandSpec {
+        name: "geosearch",
+        summary: "Queries a geospatial index for members inside an area of a box or a circle.",
+        since: "6.2.0",
+        group: "geo",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "from",
+                arg_type: "oneof",
+                optional: false,
+            },
+            CommandArg {
+                name: "by",
+                arg_type: "oneof",
+                optional: false,
+            },
+        ],
+        write: false,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RO", "access"],
+        }),
+    },
+    CommandSpec {
+        name: "zadd",
+        summary: "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.",
+        since: "1.2.0",
+        group: "sorted-set",
+        arguments: &[
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "condition",
+                arg_type: "oneof",
+                optional: true,
+            },
+            CommandArg {
+                name: "comparison",
+                arg_type: "oneof",
+                optional: true,
+            },
+            CommandArg {
+                name: "change",
+                arg_type: "pure-token",
+                optional: true,
+            },
+            CommandArg {
+                name: "increment",
+                arg_type: "pure-token",
+                optional: true,
+            },
+            CommandArg {
+                name: "data",
+                arg_type: "block",
+                optional: false,
+            },
+        ],
+        write: true,
+        keys: Some(KeySpec {
+            first: 1,
+            last: 1,
+            step: 1,
+            flags: &["RW", "update"],
+        }),
+    },
+    CommandSpec {
+        name: "zmpop",
+        summary: "Returns the highest- or lowest-scoring members from one or more sorted sets after removing them. Deletes the sorted set if the last member was popped.",
+        since: "7.0.0",
+        group: "sorted-set",
+        arguments: &[
+            CommandArg {
+                name: "numkeys",
+                arg_type: "integer",
+                optional: false,
+            },
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "where",
+                arg_type: "oneof",
+                optional: false,
+            },
+            CommandArg {
+                name: "count",
+                arg_type: "integer",
+                optional: true,
+            },
+        ],
+        write: true,
+        // The keys are counted by numkeys, so they can't be found by position
+        keys: None,
+    },
+    CommandSpec {
+        name: "bzmpop",
+        summary: "Removes and returns a member by score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+        since: "7.0.0",
+        group: "sorted-set",
+        arguments: &[
+            CommandArg {
+                name: "timeout",
+                arg_type: "double",
+                optional: false,
+            },
+            CommandArg {
+                name: "numkeys",
+                arg_type: "integer",
+                optional: false,
+            },
+            CommandArg {
+                name: "key",
+                arg_type: "key",
+                optional: false,
+            },
+            CommandArg {
+                name: "where",
+                arg_type: "oneof",
+                optional: false,
+            },
+            CommandArg {
+                name: "count",
+                arg_type: "integer",
+                optional: true,
+            },
+        ],
+        write: true,
+        // Like ZMPOP, the keys are counted by numkeys
+        keys: None,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_is_case_insensitive() {
+        assert_eq!(lookup("SET").map(|spec| spec.name), Some("set"));
+        assert_eq!(lookup("unknown"), None);
+    }
+
+    #[test]
+    fn test_names_are_unique() {
+        for (i, spec) in COMMAND_TABLE.iter().enumerate() {
+            assert!(
+                COMMAND_TABLE[i + 1..]
+                    .iter()
+                    .all(|other| other.name != spec.name),
+                "{} is in the command table more than once",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_extract_keys() {
+        let args: Vec<String> = ["mset", "a", "1", "b", "2"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let spec = KeySpec {
+            first: 1,
+            last: -1,
+            step: 2,
+            flags: &["OW"],
+        };
+        assert_eq!(spec.extract_keys(&args), Some(vec![&args[1], &args[3]]));
+
+        let get = lookup("get").unwrap().keys.as_ref().unwrap();
+        assert_eq!(get.extract_keys(&args[..2]), Some(vec![&args[1]]));
+        assert_eq!(get.extract_keys(&args[..1]), None);
+    }
+}
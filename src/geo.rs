@@ -0,0 +1,231 @@
+use std::fmt;
+
+use crate::numeric_arg::parse_float_arg;
+
+// The area a geohash covers. Latitudes are cut where the Web Mercator projection ends
+const LAT_MIN: f64 = -85.05112878;
+const LAT_MAX: f64 = 85.05112878;
+const LON_MIN: f64 = -180.0;
+const LON_MAX: f64 = 180.0;
+
+// Bits per coordinate, so a hash takes 52 bits and fits a sorted set score exactly
+const STEP: u32 = 26;
+
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+
+/// A longitude and latitude pair, in degrees. Parsing only accepts finite coordinates, so
+/// comparing points always gives an answer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub lon: f64,
+    pub lat: f64,
+}
+
+impl Eq for Point {}
+
+impl Point {
+    pub fn parse(lon: &str, lat: &str) -> Option<Self> {
+        Some(Self {
+            lon: parse_finite(lon)?,
+            lat: parse_finite(lat)?,
+        })
+    }
+
+    /// Whether the point can be geohashed. Errors are the reply Redis gives for one that can't.
+    pub fn validate(&self) -> Result<(), String> {
+        if (LON_MIN..=LON_MAX).contains(&self.lon) && (LAT_MIN..=LAT_MAX).contains(&self.lat) {
+            Ok(())
+        } else {
+            Err(format!(
+                "ERR invalid longitude,latitude pair {:.6},{:.6}",
+                self.lon, self.lat
+            ))
+        }
+    }
+
+    /// The 52-bit geohash of the point, latitude and longitude bits interleaved, with the
+    /// latitude in the even bits.
+    pub fn geohash(&self) -> u64 {
+        let cells = (1u64 << STEP) as f64;
+        let lat_cell = (self.lat - LAT_MIN) / (LAT_MAX - LAT_MIN) * cells;
+        let lon_cell = (self.lon - LON_MIN) / (LON_MAX - LON_MIN) * cells;
+
+        spread(lat_cell as u32) | spread(lon_cell as u32) << 1
+    }
+
+    /// The center of the cell `hash` stands for.
+    pub fn from_geohash(hash: u64) -> Self {
+        let cells = (1u64 << STEP) as f64;
+        let lat_cell = compact(hash) as f64;
+        let lon_cell = compact(hash >> 1) as f64;
+
+        let lat_scale = LAT_MAX - LAT_MIN;
+        let lon_scale = LON_MAX - LON_MIN;
+        let lat_min = LAT_MIN + (lat_cell / cells) * lat_scale;
+        let lat_max = LAT_MIN + ((lat_cell + 1.0) / cells) * lat_scale;
+        let lon_min = LON_MIN + (lon_cell / cells) * lon_scale;
+        let lon_max = LON_MIN + ((lon_cell + 1.0) / cells) * lon_scale;
+
+        Self {
+            lon: ((lon_min + lon_max) / 2.0).clamp(LON_MIN, LON_MAX),
+            lat: ((lat_min + lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX),
+        }
+    }
+
+    /// The great circle distance to `other` in meters, with the haversine formula.
+    pub fn distance(&self, other: &Point) -> f64 {
+        let lon1 = self.lon.to_radians();
+        let lon2 = other.lon.to_radians();
+        let v = ((lon2 - lon1) / 2.0).sin();
+        // On the same meridian the distance is just along it
+        if v == 0.0 {
+            return EARTH_RADIUS_IN_METERS * (other.lat.to_radians() - self.lat.to_radians()).abs();
+        }
+
+        let lat1 = self.lat.to_radians();
+        let lat2 = other.lat.to_radians();
+        let u = ((lat2 - lat1) / 2.0).sin();
+        let a = u * u + lat1.cos() * lat2.cos() * v * v;
+
+        2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+    }
+}
+
+/// The units GEO commands take distances in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoUnit {
+    Meters,
+    Kilometers,
+    Feet,
+    Miles,
+}
+
+impl GeoUnit {
+    pub fn parse(unit: &str) -> Option<Self> {
+        match unit.to_lowercase().as_str() {
+            "m" => Some(Self::Meters),
+            "km" => Some(Self::Kilometers),
+            "ft" => Some(Self::Feet),
+            "mi" => Some(Self::Miles),
+            _ => None,
+        }
+    }
+
+    pub fn meters(&self) -> f64 {
+        match self {
+            GeoUnit::Meters => 1.0,
+            GeoUnit::Kilometers => 1000.0,
+            GeoUnit::Feet => 0.3048,
+            GeoUnit::Miles => 1609.34,
+        }
+    }
+}
+
+impl fmt::Display for GeoUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoUnit::Meters => write!(f, "m"),
+            GeoUnit::Kilometers => write!(f, "km"),
+            GeoUnit::Feet => write!(f, "ft"),
+            GeoUnit::Miles => write!(f, "mi"),
+        }
+    }
+}
+
+/// A distance as given to a GEO command, like the radius of a search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoDistance {
+    pub amount: f64,
+    pub unit: GeoUnit,
+}
+
+// Parsing only accepts finite amounts, like for points
+impl Eq for GeoDistance {}
+
+impl GeoDistance {
+    pub fn parse(amount: &str, unit: &str) -> Option<Self> {
+        Some(Self {
+            amount: parse_finite(amount).filter(|amount| *amount >= 0.0)?,
+            unit: GeoUnit::parse(unit)?,
+        })
+    }
+
+    pub fn meters(&self) -> f64 {
+        self.amount * self.unit.meters()
+    }
+}
+
+fn parse_finite(value: &str) -> Option<f64> {
+    parse_float_arg(value).filter(|value| value.is_finite())
+}
+
+// Moves the bits of `value` to the even positions of the result
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+// The inverse of `spread`, gathering the even bits of `value`
+fn compact(value: u64) -> u32 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    ((x | (x >> 16)) & 0x0000_0000_ffff_ffff) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geohash_round_trip() {
+        let palermo = Point::parse("13.361389", "38.115556").unwrap();
+        let hash = palermo.geohash();
+        assert_eq!(hash, 3479099956230698);
+        assert!(hash < 1 << 52);
+
+        let decoded = Point::from_geohash(hash);
+        assert!((decoded.lon - palermo.lon).abs() < 1e-5);
+        assert!((decoded.lat - palermo.lat).abs() < 1e-5);
+
+        assert_eq!(compact(spread(0xdead_beef)), 0xdead_beef);
+    }
+
+    #[test]
+    fn test_distance() {
+        let palermo = Point::parse("13.361389", "38.115556").unwrap();
+        let catania = Point::parse("15.087269", "37.502669").unwrap();
+
+        let distance = Point::from_geohash(palermo.geohash())
+            .distance(&Point::from_geohash(catania.geohash()));
+        assert_eq!(format!("{:.4}", distance), "166274.1516");
+        assert_eq!(palermo.distance(&palermo), 0.0);
+    }
+
+    #[test]
+    fn test_parse_and_validate() {
+        assert_eq!(Point::parse("1", "nan"), None);
+        assert_eq!(Point::parse("inf", "1"), None);
+        assert_eq!(
+            Point::parse("181", "10").unwrap().validate(),
+            Err("ERR invalid longitude,latitude pair 181.000000,10.000000".to_string())
+        );
+        assert_eq!(
+            Point::parse("180", "-85.05112878").unwrap().validate(),
+            Ok(())
+        );
+
+        assert_eq!(
+            GeoDistance::parse("1.5", "KM").map(|distance| distance.meters()),
+            Some(1500.0)
+        );
+        assert_eq!(GeoDistance::parse("-1", "m"), None);
+        assert_eq!(GeoDistance::parse("1", "yd"), None);
+    }
+}
@@ -0,0 +1,204 @@
+/// Redis style glob matching (a port of `stringmatchlen`), supporting `*`, `?`, `[...]`
+/// classes with ranges and `^` negation, and `\` to escape metacharacters.
+pub fn string_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut skip_longer_matches = false;
+    matches(pattern, string, nocase, &mut skip_longer_matches, 0)
+}
+
+// Like Redis, deeply nested `*` give up instead of risking a stack overflow
+const MAX_NESTING: usize = 1000;
+
+fn matches(
+    pattern: &[u8],
+    string: &[u8],
+    nocase: bool,
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+    let (mut p, mut s) = (0, 0);
+
+    while p < pattern.len() && s < string.len() {
+        match pattern[p] {
+            b'*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+
+                while s < string.len() {
+                    if matches(
+                        &pattern[p + 1..],
+                        &string[s..],
+                        nocase,
+                        skip_longer_matches,
+                        nesting + 1,
+                    ) {
+                        return true;
+                    }
+                    // A failed tail match can't succeed by consuming more of the string
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                    s += 1;
+                }
+
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => s += 1,
+            b'[' => {
+                p += 1;
+                let negated = pattern.get(p) == Some(&b'^');
+                if negated {
+                    p += 1;
+                }
+
+                let mut matched = false;
+                loop {
+                    match pattern.get(p) {
+                        Some(b'\\') if p + 1 < pattern.len() => {
+                            p += 1;
+                            matched |= pattern[p] == string[s];
+                        }
+                        Some(b']') => break,
+                        // Unbalanced `[`: the class extends to the end of the pattern
+                        None => {
+                            p -= 1;
+                            break;
+                        }
+                        Some(&start) if p + 2 < pattern.len() && pattern[p + 1] == b'-' => {
+                            let end = pattern[p + 2];
+                            let (mut start, mut end) = (start.min(end), start.max(end));
+                            let mut c = string[s];
+                            if nocase {
+                                start = start.to_ascii_lowercase();
+                                end = end.to_ascii_lowercase();
+                                c = c.to_ascii_lowercase();
+                            }
+
+                            p += 2;
+                            matched |= start <= c && c <= end;
+                        }
+                        Some(&c) => matched |= eq(c, string[s]),
+                    }
+                    p += 1;
+                }
+
+                if matched == negated {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                let c = if c == b'\\' && p + 1 < pattern.len() {
+                    p += 1;
+                    pattern[p]
+                } else {
+                    c
+                };
+
+                if !eq(c, string[s]) {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+
+        p += 1;
+        if s == string.len() {
+            while p < pattern.len() && pattern[p] == b'*' {
+                p += 1;
+            }
+            break;
+        }
+    }
+
+    p == pattern.len() && s == string.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, string: &str) -> bool {
+        string_match(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(glob("*", "anything"));
+        assert!(glob("h?llo", "hello"));
+        assert!(!glob("h?llo", "hllo"));
+        assert!(glob("h*llo", "hllo"));
+        assert!(glob("h*llo", "heeeello"));
+        assert!(glob("a**b", "axxb"));
+        assert!(!glob("a*b", "axxc"));
+        assert!(!glob("", "a"));
+        assert!(glob("", ""));
+        // Same as Redis: an empty string never matches a non-empty pattern, not even `*`
+        assert!(!glob("*", ""));
+    }
+
+    #[test]
+    fn test_escaped_metacharacters() {
+        assert!(glob("\\*", "*"));
+        assert!(!glob("\\*", "a"));
+        assert!(glob("a\\?b", "a?b"));
+        assert!(!glob("a\\?b", "axb"));
+        assert!(glob("[\\]]", "]"));
+        assert!(glob("end\\", "end\\"));
+    }
+
+    #[test]
+    fn test_character_classes() {
+        assert!(glob("h[ae]llo", "hello"));
+        assert!(glob("h[ae]llo", "hallo"));
+        assert!(!glob("h[ae]llo", "hillo"));
+        assert!(glob("key:[0-9]", "key:7"));
+        assert!(!glob("key:[0-9]", "key:a"));
+        assert!(glob("[z-a]", "m"));
+    }
+
+    #[test]
+    fn test_negated_classes() {
+        assert!(glob("h[^e]llo", "hallo"));
+        assert!(!glob("h[^e]llo", "hello"));
+        assert!(!glob("[^0-9]", "5"));
+        assert!(glob("[^0-9]", "x"));
+    }
+
+    #[test]
+    fn test_unbalanced_bracket() {
+        assert!(glob("[abc", "a"));
+        assert!(glob("[abc", "c"));
+        assert!(!glob("[abc", "d"));
+        assert!(!glob("[", "a"));
+    }
+
+    #[test]
+    fn test_nocase() {
+        assert!(string_match(b"HeLLo", b"hello", true));
+        assert!(string_match(b"[A-C]x", b"bX", true));
+        assert!(!string_match(b"HeLLo", b"hello", false));
+    }
+
+    #[test]
+    fn test_many_stars_stay_fast() {
+        let pattern = "a*".repeat(30) + "b";
+        let string = "a".repeat(60);
+        assert!(!glob(&pattern, &string));
+    }
+}
@@ -0,0 +1,346 @@
+// Bits of the hash that pick a register, so there are 2^14 registers like in Redis
+const INDEX_BITS: u32 = 14;
+const REGISTERS: usize = 1 << INDEX_BITS;
+// Bits of the hash left to count the leading run of zeros in
+const COUNT_BITS: u32 = 64 - INDEX_BITS;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MASK: u16 = (1 << REGISTER_BITS) - 1;
+
+// The magic, the encoding, 3 unused bytes and the cached cardinality
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * REGISTER_BITS).div_ceil(8);
+const MAGIC: &[u8] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+// Set on the last byte of the cached cardinality when the registers changed after computing it
+const STALE_CACHE: u8 = 1 << 7;
+
+const HASH_SEED: u64 = 0xadc83b19;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+pub const INVALID_HLL_ERROR: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+pub const CORRUPTED_HLL_ERROR: &str = "INVALIDOBJ Corrupted HLL object detected";
+
+/// A HyperLogLog cardinality estimator, laid out byte for byte the way Redis stores one in a
+/// string, so values can move between servers with GET and SET. Registers are always kept
+/// densely packed, 6 bits each, but sparse values written by Redis can be read too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    bytes: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        let mut bytes = vec![0; DENSE_SIZE];
+        bytes[..MAGIC.len()].copy_from_slice(MAGIC);
+        bytes[MAGIC.len()] = DENSE;
+
+        Self { bytes }
+    }
+}
+
+impl HyperLogLog {
+    /// Reads the HyperLogLog in a string value. Errors are the replies Redis gives for strings
+    /// that aren't one.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(MAGIC) {
+            return Err(INVALID_HLL_ERROR);
+        }
+
+        match bytes[MAGIC.len()] {
+            DENSE if bytes.len() == DENSE_SIZE => Ok(Self {
+                bytes: bytes.to_vec(),
+            }),
+            SPARSE => Self::from_sparse(&bytes[HEADER_SIZE..]),
+            _ => Err(INVALID_HLL_ERROR),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Adds `element`, returning whether a register changed, and so maybe the estimate.
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmur_hash_64a(element, HASH_SEED);
+        let index = (hash & (REGISTERS as u64 - 1)) as usize;
+        // The sentinel bit caps the count when all the remaining bits are zero
+        let count = ((hash >> INDEX_BITS) | (1 << COUNT_BITS)).trailing_zeros() as u8 + 1;
+
+        if count > self.register(index) {
+            self.set_register(index, count);
+            self.invalidate_cache();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Makes this estimate the cardinality of the union with `other`.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for index in 0..REGISTERS {
+            let value = other.register(index);
+            if value > self.register(index) {
+                self.set_register(index, value);
+            }
+        }
+        self.invalidate_cache();
+    }
+
+    /// Estimates the number of distinct elements added, with the estimator Redis uses, from
+    /// Otmar Ertl's "New cardinality estimation algorithms for HyperLogLog sketches".
+    pub fn count(&self) -> u64 {
+        if self.bytes[HEADER_SIZE - 1] & STALE_CACHE == 0 {
+            let cached = self.bytes[HEADER_SIZE - 8..HEADER_SIZE].try_into().unwrap();
+            return u64::from_le_bytes(cached);
+        }
+
+        let mut histogram = [0u32; 64];
+        for index in 0..REGISTERS {
+            histogram[self.register(index) as usize] += 1;
+        }
+
+        let m = REGISTERS as f64;
+        let q = COUNT_BITS as usize;
+        let mut z = m * tau((m - histogram[q + 1] as f64) / m);
+        for registers in histogram[1..=q].iter().rev() {
+            z += *registers as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+
+        (ALPHA_INF * m * m / z).round() as u64
+    }
+
+    // Runs of registers: 00xxxxxx and 01xxxxxx yyyyyyyy are zeros, 1vvvvvxx holds a value
+    fn from_sparse(data: &[u8]) -> Result<Self, &'static str> {
+        let mut hll = Self::default();
+        let mut index = 0;
+
+        let mut data = data.iter();
+        while let Some(&opcode) = data.next() {
+            let (value, run) = match opcode >> 6 {
+                0b00 => (0, (opcode & 0x3f) as usize + 1),
+                0b01 => {
+                    let low = *data.next().ok_or(CORRUPTED_HLL_ERROR)?;
+                    (0, ((opcode as usize & 0x3f) << 8 | low as usize) + 1)
+                }
+                _ => (((opcode >> 2) & 0x1f) + 1, (opcode & 0x3) as usize + 1),
+            };
+
+            if index + run > REGISTERS {
+                return Err(CORRUPTED_HLL_ERROR);
+            }
+            if value > 0 {
+                for index in index..index + run {
+                    hll.set_register(index, value);
+                }
+            }
+            index += run;
+        }
+
+        if index != REGISTERS {
+            return Err(CORRUPTED_HLL_ERROR);
+        }
+        hll.invalidate_cache();
+        Ok(hll)
+    }
+
+    // Registers are packed from the least significant bit up, so one can span two bytes. The
+    // last register fits in the last byte
+    fn register(&self, index: usize) -> u8 {
+        let (byte, shift) = Self::register_position(index);
+        let word =
+            self.bytes[byte] as u16 | (self.bytes.get(byte + 1).copied().unwrap_or(0) as u16) << 8;
+
+        ((word >> shift) & REGISTER_MASK) as u8
+    }
+
+    fn set_register(&mut self, index: usize, value: u8) {
+        let (byte, shift) = Self::register_position(index);
+        let word =
+            self.bytes[byte] as u16 | (self.bytes.get(byte + 1).copied().unwrap_or(0) as u16) << 8;
+        let word = (word & !(REGISTER_MASK << shift)) | (value as u16) << shift;
+
+        self.bytes[byte] = word as u8;
+        if let Some(high) = self.bytes.get_mut(byte + 1) {
+            *high = (word >> 8) as u8;
+        }
+    }
+
+    fn register_position(index: usize) -> (usize, usize) {
+        let bit = index * REGISTER_BITS;
+        (HEADER_SIZE + bit / 8, bit % 8)
+    }
+
+    fn invalidate_cache(&mut self) {
+        self.bytes[HEADER_SIZE - 1] |= STALE_CACHE;
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash64A, the hash Redis registers elements with, so both place them the same way.
+fn murmur_hash_64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_is_close() {
+        let mut hll = HyperLogLog::default();
+        assert_eq!(hll.count(), 0);
+
+        for element in ["a", "b", "c", "d", "e", "f", "g"] {
+            hll.add(element.as_bytes());
+        }
+        assert!(!hll.add(b"a"));
+        assert_eq!(hll.count(), 7);
+
+        let mut hll = HyperLogLog::default();
+        for i in 0..1000 {
+            hll.add(format!("element:{}", i).as_bytes());
+        }
+        let count = hll.count() as f64;
+        // The standard error with 2^14 registers is 0.81%, so this is a few of them
+        assert!(
+            (count - 1000.0).abs() / 1000.0 < 0.03,
+            "Estimated {}",
+            count
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = HyperLogLog::default();
+        let mut second = HyperLogLog::default();
+        for i in 0..100 {
+            first.add(format!("{}", i).as_bytes());
+            second.add(format!("{}", i + 50).as_bytes());
+        }
+
+        first.merge(&second);
+        let count = first.count() as f64;
+        assert!((count - 150.0).abs() / 150.0 < 0.03, "Estimated {}", count);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut hll = HyperLogLog::default();
+        hll.add(b"foo");
+        hll.add(b"bar");
+
+        let bytes = hll.clone().into_bytes();
+        assert_eq!(bytes.len(), DENSE_SIZE);
+        assert!(bytes.starts_with(b"HYLL"));
+        assert_eq!(HyperLogLog::from_bytes(&bytes), Ok(hll));
+
+        assert_eq!(HyperLogLog::from_bytes(b"HYLL"), Err(INVALID_HLL_ERROR));
+        assert_eq!(
+            HyperLogLog::from_bytes(&bytes[..DENSE_SIZE - 1]),
+            Err(INVALID_HLL_ERROR)
+        );
+        assert_eq!(
+            HyperLogLog::from_bytes(b"not a hyperloglog"),
+            Err(INVALID_HLL_ERROR)
+        );
+    }
+
+    #[test]
+    fn test_read_sparse() {
+        let mut header = b"HYLL".to_vec();
+        header.extend([SPARSE, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, STALE_CACHE]);
+
+        // Register 2 set to 3 and the rest zero: ZERO(2), VAL(3, 1), XZERO(16381)
+        let mut sparse = header.clone();
+        sparse.extend([0b0000_0001, 0b1000_1000, 0b0111_1111, 0b1111_1100]);
+        let hll = HyperLogLog::from_bytes(&sparse).unwrap();
+        assert_eq!(hll.register(2), 3);
+        assert_eq!(hll.count(), 1);
+
+        // Runs not covering every register
+        let mut short = header;
+        short.push(0b0000_0001);
+        assert_eq!(HyperLogLog::from_bytes(&short), Err(CORRUPTED_HLL_ERROR));
+    }
+
+    #[test]
+    fn test_registers_pack_across_bytes() {
+        let mut hll = HyperLogLog::default();
+        for index in [0, 1, 2, 3, REGISTERS - 1] {
+            hll.set_register(index, 63);
+            assert_eq!(hll.register(index), 63);
+        }
+        hll.set_register(1, 5);
+
+        assert_eq!(hll.register(0), 63);
+        assert_eq!(hll.register(1), 5);
+        assert_eq!(hll.register(2), 63);
+        assert_eq!(hll.register(4), 0);
+        assert_eq!(hll.bytes[HEADER_SIZE..HEADER_SIZE + 2], [0x7f, 0xf1]);
+    }
+}
@@ -1,9 +1,19 @@
+pub mod client_limiter;
+pub mod command_table;
+pub mod geo;
+pub mod glob_pattern;
+pub mod hyperloglog;
+pub mod numeric_arg;
 pub mod rdb_file;
 pub mod redis_client;
 pub mod redis_command;
 pub mod redis_runtime;
+pub mod redis_stream;
 pub mod redis_type;
+pub mod replication_backlog;
 pub mod server_config;
+pub mod server_stats;
+pub mod sorted_set;
 
 pub trait RedisWritable {
     fn write_as_protocol(&self) -> Vec<u8>;
@@ -13,7 +23,7 @@ pub trait RedisWritable {
 pub mod tests {
     use std::{
         pin::Pin,
-        sync::{Arc, Mutex},
+        sync::Arc,
         task::{Context, Poll},
     };
 
@@ -26,10 +36,26 @@ pub mod tests {
         runtime::Handle,
     };
 
+    /// Feeds `script` through `RedisType::parse` until it runs out, returning every frame parsed
+    /// in order. A clean end of input yields no error, while the first protocol error stops the
+    /// parsing and is returned alongside the frames that preceded it.
+    pub async fn parse_frames(script: &[u8]) -> (Vec<RedisType>, Option<anyhow::Error>) {
+        let mut reader = BufReader::new(script);
+        let mut frames = Vec::new();
+
+        loop {
+            match RedisType::parse(&mut reader).await {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => return (frames, None),
+                Err(e) => return (frames, Some(e)),
+            }
+        }
+    }
+
     pub struct MockStream<'a> {
         pub read_data: Vec<u8>,
         write_data: &'a mut Vec<u8>,
-        runtime: Arc<Mutex<RedisRuntime>>,
+        runtime: Arc<RedisRuntime>,
     }
 
     impl<'a> MockStream<'a> {
@@ -37,7 +63,7 @@ pub mod tests {
             Self {
                 read_data: Vec::new(),
                 write_data,
-                runtime: Arc::new(Mutex::new(RedisRuntime::new(ServerConfig::default()))),
+                runtime: Arc::new(RedisRuntime::new(ServerConfig::default())),
             }
         }
     }
@@ -74,7 +100,7 @@ pub mod tests {
 
                     // Simulate server processing the command
                     let command = RedisCommand::parse(&argument).unwrap();
-                    let response = this.runtime.lock().unwrap().execute_no_conn(&command).await;
+                    let response = this.runtime.execute_no_conn(&command).await;
 
                     // Prepare response to be read by the client
                     this.read_data
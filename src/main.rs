@@ -1,5 +1,6 @@
 // use redis_starter_rust::redis_client::RedisClient;
-use redis_starter_rust::redis_command::RedisCommand;
+use redis_starter_rust::client_limiter::ClientLimiter;
+use redis_starter_rust::redis_command::{ParseError, RedisCommand};
 use redis_starter_rust::redis_runtime::RedisRuntime;
 use redis_starter_rust::redis_type::RedisType;
 use redis_starter_rust::server_config::ServerConfig;
@@ -14,29 +15,51 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{mpsc, Mutex};
 use tokio::task::JoinHandle;
 
-#[tokio::main]
-async fn main() {
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = ServerConfig::parse_command_line_args(&args);
+
+    let runtime = config
+        .build_runtime()
+        .expect("Failed to build the tokio runtime");
+    runtime.block_on(run_server(config));
+}
+
+async fn run_server(config: ServerConfig) {
     // let mut client = RedisClient::new(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 6379)).await.unwrap();
-    // let command = RedisCommand::SET { key: "foo".to_string(), val: RedisType::simple_string("bar"), ttl: None };
+    // let command = RedisCommand::SET { key: "foo".to_string(), val: RedisType::simple_string("bar"), expiry: None };
     // let result = client.send_command(&command).await;
     // println!("Result: {:?}", result);
-    let args: Vec<String> = env::args().collect();
-    let config = ServerConfig::parse_command_line_args(&args);
-
     let listen_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), config.port);
     let listener = TcpListener::bind(listen_addr).await.unwrap();
     println!("Listening on port {}", config.port);
 
+    let limiter = ClientLimiter::new(config.maxclients);
     let runtime = Arc::new(RedisRuntime::new(config));
     let runtime_clone = Arc::clone(&runtime);
     tokio::spawn(set_up_replica_loop(runtime_clone));
+    tokio::spawn(active_expire_loop(Arc::clone(&runtime)));
 
     loop {
         match listener.accept().await {
-            Ok((stream, _)) => {
+            Ok((mut stream, _)) => {
+                let Some(slot) = limiter.try_acquire() else {
+                    println!("Rejecting connection, max number of clients reached");
+                    tokio::spawn(async move { ClientLimiter::reject(&mut stream).await });
+                    continue;
+                };
+
                 println!("Accepted new connection");
                 let runtime_clone = Arc::clone(&runtime);
-                let _ = handle_connection(stream, runtime_clone, false);
+                if let Ok((read_handle, write_handle)) =
+                    handle_connection(stream, runtime_clone, false)
+                {
+                    // Hold the slot until the connection is done with
+                    tokio::spawn(async move {
+                        let _ = tokio::join!(read_handle, write_handle);
+                        drop(slot);
+                    });
+                }
             }
             Err(e) => println!("Error accepting connection: {}", e),
         }
@@ -53,15 +76,29 @@ async fn set_up_replica_loop(runtime: Arc<RedisRuntime>) {
                 backoff = Duration::from_secs(1);
 
                 let runtime_clone = Arc::clone(&runtime);
-                if let Ok((read_handle, write_handle)) =
+                if let Ok((mut read_handle, mut write_handle)) =
                     handle_connection(stream, runtime_clone, true)
                 {
                     // Join the read and write tasks. If either fails, we try to reconnect.
-                    let _ = tokio::join!(read_handle, write_handle);
-                    println!("Connection to master lost. Reconnecting in {:?}", backoff);
+                    // A REPLICAOF drops the current master and starts over right away.
+                    tokio::select! {
+                        _ = async { tokio::join!(&mut read_handle, &mut write_handle) } => {
+                            println!("Connection to master lost. Reconnecting in {:?}", backoff);
+                        }
+                        _ = runtime.replication_role_changed() => {
+                            println!("Replication role changed. Dropping connection to master");
+                            read_handle.abort();
+                            write_handle.abort();
+                            continue;
+                        }
+                    }
                 }
             }
-            Ok(None) => break, // Exit the loop since the instance is a master.
+            Ok(None) => {
+                // The instance is a master. Wait until a REPLICAOF makes it a replica.
+                runtime.replication_role_changed().await;
+                continue;
+            }
             Err(e) => {
                 println!("Error during handshake: {e}");
             }
@@ -69,11 +106,27 @@ async fn set_up_replica_loop(runtime: Arc<RedisRuntime>) {
 
         // If the instance is a replica and lost connection, or couldn't connect, retry with backoff
         println!("Retrying in {:?}", backoff);
-        tokio::time::sleep(backoff).await;
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = runtime.replication_role_changed() => {
+                backoff = Duration::from_secs(1);
+                continue;
+            }
+        }
         backoff = min(backoff * 2, Duration::from_secs(30)); // Exponential backoff capped at 30 seconds
     }
 }
 
+async fn active_expire_loop(runtime: Arc<RedisRuntime>) {
+    // Same frequency as Redis's default `hz 10`
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        interval.tick().await;
+        runtime.active_expire_cycle().await;
+    }
+}
+
 #[derive(Debug)]
 enum CommandOrError {
     Command(RedisCommand),
@@ -90,7 +143,7 @@ fn handle_connection(
     let (tx, rx) = mpsc::channel(32);
 
     // Spawn task to handle reading
-    let read_handle = tokio::spawn(handle_reading(read_half, tx));
+    let read_handle = tokio::spawn(handle_reading(read_half, tx, runtime.max_line_len()));
 
     // Spawn task to handle processing and writing
     let write_handle = tokio::spawn(handle_processing_writing(
@@ -104,45 +157,53 @@ fn handle_connection(
     Ok((read_handle, write_handle))
 }
 
-async fn handle_reading(read_half: ReadHalf<TcpStream>, tx: mpsc::Sender<CommandOrError>) {
+async fn handle_reading(
+    read_half: ReadHalf<TcpStream>,
+    tx: mpsc::Sender<Vec<CommandOrError>>,
+    max_line_len: usize,
+) {
     let mut buf = BufReader::new(read_half);
 
-    loop {
-        let command = RedisType::parse(&mut buf).await;
-
-        match command {
-            Ok(Some(input)) => {
-                println!("Input type: {:?}", input);
+    // Everything a pipelining client already sent goes through the channel as one batch
+    while let Some(batch) = RedisType::parse_batch(&mut buf, max_line_len).await {
+        let mut protocol_error = false;
+        let batch = batch
+            .into_iter()
+            .map(|input| match input {
+                Ok(input) => {
+                    println!("Input type: {:?}", input);
 
-                match RedisCommand::parse(&input) {
-                    Some(command) => {
-                        tx.send(CommandOrError::Command(command)).await.unwrap();
-                    }
-                    None => {
-                        tx.send(CommandOrError::Error(anyhow::anyhow!(
+                    match RedisCommand::parse(&input) {
+                        Ok(command) => CommandOrError::Command(command),
+                        Err(ParseError::Reply(message)) => {
+                            CommandOrError::Error(anyhow::anyhow!(message))
+                        }
+                        Err(ParseError::Invalid) => CommandOrError::Error(anyhow::anyhow!(
                             "Not a valid command: {:?}",
                             input
-                        )))
-                        .await
-                        .unwrap();
+                        )),
                     }
                 }
-            }
-            Ok(None) => break,
-            Err(err) => {
-                tx.send(CommandOrError::Error(anyhow::anyhow!(
-                    "Error parsing input type: {:?}",
-                    &err
-                )))
-                .await
-                .unwrap();
-            }
+                Err(err) => {
+                    protocol_error = true;
+                    CommandOrError::Error(anyhow::anyhow!("ERR Protocol error: {}", err))
+                }
+            })
+            .collect();
+
+        tx.send(batch).await.unwrap();
+
+        // The stream can't be trusted after a malformed frame. Stopping here closes the
+        // connection once the error has been written, which is what Redis does
+        if protocol_error {
+            println!("Closing connection after a protocol error");
+            break;
         }
     }
 }
 
 async fn handle_processing_writing(
-    mut rx: mpsc::Receiver<CommandOrError>,
+    mut rx: mpsc::Receiver<Vec<CommandOrError>>,
     write_half: WriteHalf<TcpStream>,
     runtime: Arc<RedisRuntime>,
     from_master: bool,
@@ -150,50 +211,57 @@ async fn handle_processing_writing(
 ) -> Result<(), anyhow::Error> {
     let write_half = Arc::new(Mutex::new(write_half));
 
-    while let Some(command_or_error) = rx.recv().await {
-        match command_or_error {
-            CommandOrError::Command(command) => {
-                let write_clone = Arc::clone(&write_half);
+    while let Some(batch) = rx.recv().await {
+        for command_or_error in batch {
+            match command_or_error {
+                CommandOrError::Command(command) => {
+                    let write_clone = Arc::clone(&write_half);
 
-                if !runtime.is_master() && command.is_write_command() && !from_master {
-                    let error_msg = "You can't write against a read only replica.";
-                    println!("{}", error_msg);
-                    let error = RedisType::simple_error(error_msg);
+                    let rejection = runtime
+                        .loading_error(&command, from_master)
+                        .or_else(|| runtime.read_only_error(&command, from_master));
+                    if let Some(error) = rejection {
+                        println!("Rejecting command: {:?}. {:?}", command, error);
+                        if let RedisType::SimpleError { message } = &error {
+                            runtime.stats().record_error(message);
+                        }
 
-                    write_half
-                        .lock()
-                        .await
-                        .write_all(&error.write_as_protocol())
-                        .await?;
-                    continue;
-                }
+                        write_half
+                            .lock()
+                            .await
+                            .write_all(&error.write_as_protocol())
+                            .await?;
+                        continue;
+                    }
 
-                println!("Executing command: {:?}", command);
-                let result = runtime
-                    .execute(&command, Some((peer_ip, write_clone)))
-                    .await;
-                println!("Command result: {:?}", result);
+                    println!("Executing command: {:?}", command);
+                    let result = runtime
+                        .execute_isolated(&command, Some((peer_ip, write_clone)), from_master)
+                        .await;
+                    println!("Command result: {:?}", result);
 
-                if runtime.is_master() || !command.is_write_command() {
+                    // Writes propagated by the master aren't acknowledged
+                    if !from_master || !command.is_write_command() {
+                        write_half
+                            .lock()
+                            .await
+                            .write_all(&result.write_as_protocol())
+                            .await?;
+                    }
+
+                    if let Err(e) = runtime.replicate_command(&command, &result).await {
+                        println!("Error replicating command: {}", e);
+                    }
+                }
+                CommandOrError::Error(error) => {
+                    println!("Recieved error from channel: {}. Sending error back", error);
+                    let error = RedisType::simple_error(&error.to_string());
                     write_half
                         .lock()
                         .await
-                        .write_all(&result.write_as_protocol())
+                        .write_all(&error.write_as_protocol())
                         .await?;
                 }
-
-                if let Err(e) = runtime.replicate_command(&command).await {
-                    println!("Error replicating command: {}", e);
-                }
-            }
-            CommandOrError::Error(error) => {
-                println!("Recieved error from channel: {}. Sending error back", error);
-                let error = RedisType::simple_error(&error.to_string());
-                write_half
-                    .lock()
-                    .await
-                    .write_all(&error.write_as_protocol())
-                    .await?;
             }
         }
     }
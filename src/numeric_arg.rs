@@ -0,0 +1,66 @@
+/// The reply Redis gives for an argument that should be an integer and isn't.
+pub const INTEGER_ERROR: &str = "ERR value is not an integer or out of range";
+/// The reply Redis gives for an argument that should be a float and isn't.
+pub const FLOAT_ERROR: &str = "ERR value is not a valid float";
+
+/// Parses an integer argument the way Redis does: digits with an optional minus sign, no plus
+/// sign, spaces or leading zeros, fitting in 64 bits. `None` also when it doesn't fit in `T`.
+pub fn parse_int_arg<T: TryFrom<i64>>(arg: &str) -> Option<T> {
+    let digits = arg.strip_prefix('-').unwrap_or(arg);
+    let canonical = match digits.as_bytes() {
+        [b'0'] => digits.len() == arg.len(),
+        [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+        _ => false,
+    };
+    if !canonical {
+        return None;
+    }
+
+    T::try_from(arg.parse::<i64>().ok()?).ok()
+}
+
+/// Parses a float argument. Infinities are accepted, as scores and the like can be infinite,
+/// but NaN never is.
+pub fn parse_float_arg(arg: &str) -> Option<f64> {
+    if arg.starts_with(|c: char| c.is_ascii_whitespace()) {
+        return None;
+    }
+
+    arg.parse().ok().filter(|value: &f64| !value.is_nan())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_int_arg() {
+        assert_eq!(parse_int_arg::<i64>("0"), Some(0));
+        assert_eq!(parse_int_arg::<i64>("-42"), Some(-42));
+        assert_eq!(parse_int_arg::<i64>("-9223372036854775808"), Some(i64::MIN));
+
+        for invalid in ["", "-", "-0", "+1", "01", " 1", "1 ", "1.5", "1e3", "abc"] {
+            assert_eq!(parse_int_arg::<i64>(invalid), None, "{:?}", invalid);
+        }
+        assert_eq!(parse_int_arg::<i64>("9223372036854775808"), None);
+
+        // In range for i64 but not for the target
+        assert_eq!(parse_int_arg::<u64>("-1"), None);
+        assert_eq!(parse_int_arg::<u16>("65536"), None);
+        assert_eq!(parse_int_arg::<u16>("6379"), Some(6379));
+    }
+
+    #[test]
+    fn test_parse_float_arg() {
+        assert_eq!(parse_float_arg("1.5"), Some(1.5));
+        assert_eq!(parse_float_arg("-3"), Some(-3.0));
+        assert_eq!(parse_float_arg("1e3"), Some(1000.0));
+        assert_eq!(parse_float_arg("inf"), Some(f64::INFINITY));
+        assert_eq!(parse_float_arg("+inf"), Some(f64::INFINITY));
+        assert_eq!(parse_float_arg("-inf"), Some(f64::NEG_INFINITY));
+
+        for invalid in ["nan", "NaN", "-nan", "", " 1", "1 ", "one"] {
+            assert_eq!(parse_float_arg(invalid), None, "{:?}", invalid);
+        }
+    }
+}
@@ -2,6 +2,115 @@ use base64::prelude::*;
 
 pub const EMPTY_RDB: &[u8] = b"UkVESVMwMDEx+glyZWRpcy12ZXIFNy4yLjD6CnJlZGlzLWJpdHPAQPoFY3RpbWXCbQi8ZfoIdXNlZC1tZW3CsMQQAPoIYW9mLWJhc2XAAP/wbjv+wP9aog==";
 
+// Newest format Redis writes, as of 7.4
+const MAX_RDB_VERSION: u32 = 12;
+
 pub fn get_empty_rdb_decoded() -> Vec<u8> {
     BASE64_STANDARD.decode(EMPTY_RDB).unwrap()
 }
+
+/// Checks the `REDIS` magic, the 4 digit version and, when `verify_checksum` is set, the trailing
+/// CRC64 of an RDB file. Returns the version. Like Redis, a stored checksum of zero is skipped,
+/// as that is what `rdbchecksum no` writes.
+pub fn validate(file: &[u8], verify_checksum: bool) -> anyhow::Result<u32> {
+    if !file.starts_with(b"REDIS") {
+        return Err(anyhow::anyhow!(
+            "Wrong signature trying to load the RDB file"
+        ));
+    }
+
+    let version = file
+        .get(5..9)
+        .and_then(|version| std::str::from_utf8(version).ok())
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or_else(|| anyhow::anyhow!("Missing or invalid RDB version"))?;
+    if !(1..=MAX_RDB_VERSION).contains(&version) {
+        return Err(anyhow::anyhow!(
+            "Can't handle RDB format version {}",
+            version
+        ));
+    }
+
+    // Checksums were added in version 5
+    if verify_checksum && version >= 5 {
+        let (content, checksum) = file
+            .len()
+            .checked_sub(8)
+            .filter(|&split| split >= 9)
+            .map(|split| file.split_at(split))
+            .ok_or_else(|| anyhow::anyhow!("RDB file is too short to hold a checksum"))?;
+
+        let expected = u64::from_le_bytes(checksum.try_into().unwrap());
+        let actual = crc64(content);
+        if expected != 0 && expected != actual {
+            return Err(anyhow::anyhow!(
+                "Wrong RDB checksum expected: ({:x}) got: ({:x})",
+                expected,
+                actual
+            ));
+        }
+    }
+
+    Ok(version)
+}
+
+/// The CRC-64/Jones variant Redis uses: reflected, with no initial value or final xor.
+pub fn crc64(data: &[u8]) -> u64 {
+    // 0xad93d23594c935a9 with its bits reversed
+    const POLY: u64 = 0x95ac9329ac4bc9b5;
+
+    data.iter().fold(0, |mut crc, &byte| {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc64_check_value() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+
+    #[test]
+    fn test_validate_empty_rdb() {
+        assert_eq!(validate(&get_empty_rdb_decoded(), true).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_validate_corrupted_checksum() {
+        let mut file = get_empty_rdb_decoded();
+        let last = file.len() - 1;
+        file[last] ^= 0xff;
+
+        let error = validate(&file, true).unwrap_err();
+        assert!(error.to_string().starts_with("Wrong RDB checksum"));
+        assert_eq!(validate(&file, false).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_validate_skips_zero_checksum() {
+        let mut file = get_empty_rdb_decoded();
+        let checksum_start = file.len() - 8;
+        file[checksum_start..].fill(0);
+
+        assert!(validate(&file, true).is_ok());
+    }
+
+    #[test]
+    fn test_validate_header() {
+        assert!(validate(b"NOTRDB0011", true).is_err());
+        assert!(validate(b"REDIS00a1", true).is_err());
+        assert!(validate(b"REDIS0099\xff", true).is_err());
+        assert!(validate(b"REDIS0011", true).is_err());
+    }
+}
@@ -1,11 +1,48 @@
-use std::net::SocketAddr;
+use std::{io, net::SocketAddr};
 
 use tokio::{
-    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
     net::TcpStream,
 };
 
-use crate::{redis_command::RedisCommand, redis_type::RedisType, RedisWritable};
+use crate::{
+    redis_command::RedisCommand,
+    redis_type::{RedisType, DEFAULT_MAX_LINE_LEN, MAX_BULK_LEN},
+    RedisWritable,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    // The server closed the connection, before or in the middle of a reply
+    #[error("Connection closed by the server")]
+    ConnectionClosed,
+    #[error("Protocol error: {0}")]
+    Protocol(anyhow::Error),
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl ClientError {
+    /// Sorts out a parsing error, where running out of input means the server went away.
+    fn from_parse(error: anyhow::Error) -> Self {
+        match error.downcast::<io::Error>() {
+            Ok(error) => error.into(),
+            Err(error) => Self::Protocol(error),
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => Self::ConnectionClosed,
+            _ => Self::Io(error),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct RedisClient<T: AsyncRead + AsyncWrite + Unpin + Send> {
@@ -22,38 +59,46 @@ where
         }
     }
 
-    pub async fn send_command(&mut self, command: &RedisCommand) -> anyhow::Result<RedisType> {
+    pub async fn send_command(&mut self, command: &RedisCommand) -> Result<RedisType, ClientError> {
         self.buffer.write_all(&command.write_as_protocol()).await?;
-
-        let response = RedisType::parse(&mut self.buffer).await?;
-        match response {
-            Some(response) => Ok(response),
-            None => Err(anyhow::anyhow!("Server did not respond")),
-        }
+        self.accept_adicional_data().await
     }
 
-    pub async fn accept_adicional_data(&mut self) -> anyhow::Result<RedisType> {
-        let response = RedisType::parse(&mut self.buffer).await?;
-        response.ok_or(anyhow::anyhow!(
-            "Server did not provide aditional information"
-        ))
+    pub async fn accept_adicional_data(&mut self) -> Result<RedisType, ClientError> {
+        RedisType::parse(&mut self.buffer)
+            .await
+            .map_err(ClientError::from_parse)?
+            .ok_or(ClientError::ConnectionClosed)
     }
 
-    pub async fn accept_rdb_file(&mut self) -> anyhow::Result<RedisType> {
+    pub async fn accept_rdb_file(&mut self) -> Result<RedisType, ClientError> {
         let first_byte = self.buffer.read_u8().await?;
 
-        if !first_byte == b'$' {
-            Err(anyhow::anyhow!(
+        if first_byte != b'$' {
+            Err(ClientError::Protocol(anyhow::anyhow!(
                 "Expected first byte of RDB encoding to be '$'"
-            ))
+            )))
         } else {
-            let mut line = String::new();
-            self.buffer.read_line(&mut line).await?;
-
-            let len: usize = line.trim().parse()?;
-
-            let mut buffer = vec![0; len]; // no CRLF
-            self.buffer.read_exact(&mut buffer).await?;
+            let header = RedisType::read_line(&mut self.buffer, DEFAULT_MAX_LINE_LEN)
+                .await
+                .map_err(ClientError::from_parse)?;
+            // Same bounds as a bulk string, which is what it looks like on the wire
+            let Some(Some(len)) = RedisType::parse_len(&header, MAX_BULK_LEN) else {
+                return Err(ClientError::Protocol(anyhow::anyhow!(
+                    "Invalid RDB length: {:?}",
+                    header
+                )));
+            };
+
+            // No CRLF after the file. The buffer grows with what actually arrives
+            let mut buffer = Vec::new();
+            (&mut self.buffer)
+                .take(len as u64)
+                .read_to_end(&mut buffer)
+                .await?;
+            if buffer.len() < len {
+                return Err(ClientError::ConnectionClosed);
+            }
 
             Ok(RedisType::RDBFile { file: buffer })
         }
@@ -61,7 +106,7 @@ where
 }
 
 impl RedisClient<TcpStream> {
-    pub async fn new(addr: SocketAddr) -> anyhow::Result<Self> {
+    pub async fn new(addr: SocketAddr) -> Result<Self, ClientError> {
         let stream = TcpStream::connect(&addr).await?;
         Ok(Self::new_raw(stream))
     }
@@ -110,7 +155,7 @@ mod tests {
 
         assert!(matches!(result, Ok(RedisType::SimpleString { .. })));
         let next_result = client.accept_rdb_file().await;
-        assert!(matches!(next_result, Ok(_)));
+        assert!(next_result.is_ok());
         assert_eq!(
             next_result.unwrap(),
             RedisType::RDBFile {
@@ -120,4 +165,68 @@ mod tests {
 
         assert_eq!(write_data, command.write_as_protocol());
     }
+
+    #[tokio::test]
+    async fn test_connection_closed_mid_response() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Starts answering each command, then hangs up halfway through
+        tokio::spawn(async move {
+            for partial in [&b"$10\r\nabc"[..], b"*2\r\n+one\r\n", b"", b"$5\r\nab"] {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut server = RedisClient::new_raw(stream);
+                server.accept_adicional_data().await.unwrap();
+                server.buffer.write_all(partial).await.unwrap();
+            }
+        });
+
+        for _ in 0..3 {
+            let mut client = RedisClient::new(addr).await.unwrap();
+            let result = client.send_command(&RedisCommand::PING).await;
+            assert!(
+                matches!(result, Err(ClientError::ConnectionClosed)),
+                "Unexpected result: {:?}",
+                result
+            );
+        }
+
+        let mut client = RedisClient::new(addr).await.unwrap();
+        client
+            .buffer
+            .write_all(&RedisCommand::psync_from_scrath().write_as_protocol())
+            .await
+            .unwrap();
+        let result = client.accept_rdb_file().await;
+        assert!(matches!(result, Err(ClientError::ConnectionClosed)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_protocol_error_in_response() {
+        let mut write_data = Vec::new();
+        let mut mock_stream = MockStream::new(&mut write_data);
+        mock_stream.read_data = b"$abc\r\n".to_vec();
+        let mut client = RedisClient::new_raw(mock_stream);
+
+        let result = client.accept_adicional_data().await;
+        assert!(matches!(result, Err(ClientError::Protocol(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_rdb_file_length_out_of_range() {
+        for header in [format!("${}\r\n", MAX_BULK_LEN + 1), "$-1\r\n".to_string()] {
+            let mut write_data = Vec::new();
+            let mut mock_stream = MockStream::new(&mut write_data);
+            mock_stream.read_data = header.into_bytes();
+            let mut client = RedisClient::new_raw(mock_stream);
+
+            // Fails on the header alone, without making room for the file
+            let result = client.accept_rdb_file().await;
+            assert!(
+                matches!(result, Err(ClientError::Protocol(_))),
+                "Unexpected result: {:?}",
+                result
+            );
+        }
+    }
 }
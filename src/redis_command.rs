@@ -1,6 +1,14 @@
-use std::time::Duration;
-
-use crate::{redis_type::RedisType, RedisWritable};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    command_table::{self, CommandSpec},
+    geo::{GeoDistance, GeoUnit, Point},
+    numeric_arg::{parse_float_arg, parse_int_arg, FLOAT_ERROR, INTEGER_ERROR},
+    redis_stream::{StreamFields, StreamId, XAddId, XReadGroupId, XReadId},
+    redis_type::RedisType,
+    sorted_set::{PopEnd, Score},
+    RedisWritable,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RedisCommand {
@@ -9,13 +17,37 @@ pub enum RedisCommand {
     SET {
         key: String,
         val: RedisType,
-        ttl: Option<Duration>,
+        expiry: Option<ExpiryOption>,
+        condition: Option<Condition>,
     },
     GET {
         key: String,
     },
+    APPEND {
+        key: String,
+        value: Vec<u8>,
+    },
+    GETRANGE {
+        key: String,
+        start: i64,
+        end: i64,
+    },
+    INCR {
+        key: String,
+    },
+    DECR {
+        key: String,
+    },
+    INCRBY {
+        key: String,
+        delta: i64,
+    },
+    DECRBY {
+        key: String,
+        delta: i64,
+    },
     INFO {
-        arg: String,
+        sections: Vec<String>,
     },
     REPLCONF {
         arg: ReplConfArgs,
@@ -24,14 +56,148 @@ pub enum RedisCommand {
         master_id: String,
         master_offset: i64,
     },
+    DEBUG {
+        arg: DebugArgs,
+    },
+    REPLICAOF {
+        // None stands for `REPLICAOF NO ONE`
+        master: Option<(String, u16)>,
+    },
+    COMMAND {
+        arg: CommandArgs,
+    },
+    FAILOVER {
+        target: Option<(String, u16)>,
+        force: bool,
+        abort: bool,
+        timeout: Option<u64>,
+    },
+    MEMORY {
+        arg: MemoryArgs,
+    },
+    DBSIZE,
+    TIME,
+    CONFIG {
+        arg: ConfigArgs,
+    },
+    OBJECT {
+        arg: ObjectArgs,
+    },
+    TYPE {
+        key: String,
+    },
+    DEL {
+        keys: Vec<String>,
+    },
+    EXISTS {
+        keys: Vec<String>,
+    },
+    XADD {
+        key: String,
+        id: XAddId,
+        fields: StreamFields,
+    },
+    XLEN {
+        key: String,
+    },
+    XRANGE {
+        key: String,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    },
+    XREAD {
+        count: Option<usize>,
+        // Wait up to this long for new entries, forever if zero
+        block: Option<Duration>,
+        streams: Vec<(String, XReadId)>,
+    },
+    XGROUP {
+        arg: XGroupArgs,
+    },
+    XREADGROUP {
+        group: String,
+        consumer: String,
+        count: Option<usize>,
+        streams: Vec<(String, XReadGroupId)>,
+    },
+    XACK {
+        key: String,
+        group: String,
+        ids: Vec<StreamId>,
+    },
+    XINFO {
+        arg: XInfoArgs,
+    },
+    PFADD {
+        key: String,
+        elements: Vec<String>,
+    },
+    PFCOUNT {
+        keys: Vec<String>,
+    },
+    PFMERGE {
+        dest: String,
+        sources: Vec<String>,
+    },
+    GEOADD {
+        key: String,
+        members: Vec<(Point, String)>,
+    },
+    GEOPOS {
+        key: String,
+        members: Vec<String>,
+    },
+    GEODIST {
+        key: String,
+        from: String,
+        to: String,
+        unit: GeoUnit,
+    },
+    GEOSEARCH {
+        key: String,
+        from: GeoSearchFrom,
+        radius: GeoDistance,
+    },
+    ZADD {
+        key: String,
+        options: ZAddOptions,
+        members: Vec<(Score, String)>,
+    },
+    ZMPOP {
+        keys: Vec<String>,
+        end: PopEnd,
+        count: usize,
+    },
+    BZMPOP {
+        // Zero waits for good
+        timeout: Duration,
+        keys: Vec<String>,
+        end: PopEnd,
+        count: usize,
+    },
+}
+
+const SYNTAX_ERROR: &str = "ERR syntax error";
+
+/// Why a request can't be turned into a command.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    // Not a command we know, or not arguments it takes
+    #[error("Not a valid command")]
+    Invalid,
+    // Arguments Redis has a specific error reply for, sent back as is
+    #[error("{0}")]
+    Reply(&'static str),
 }
 
 impl RedisCommand {
-    pub fn parse(data: &RedisType) -> Option<RedisCommand> {
-        match data {
+    pub fn parse(data: &RedisType) -> Result<RedisCommand, ParseError> {
+        // Commands returning early can fail with a specific error rather than just not parse
+        let parsed = match data {
             RedisType::List { data } if !data.is_empty() => {
                 if data.len() == 1 {
-                    Self::parse(&data[0])
+                    return Self::parse(&data[0]);
                 } else {
                     let rest = &data[1..];
 
@@ -39,10 +205,45 @@ impl RedisCommand {
                         Some(command) => match command.to_lowercase().as_str() {
                             "echo" => Self::parse_echo(rest),
                             "get" => Self::parse_get(rest),
-                            "set" => Self::parse_set(rest),
+                            "set" => return Self::parse_set(rest),
+                            "append" => Self::parse_append(rest),
+                            // SUBSTR is the deprecated name of GETRANGE
+                            "getrange" | "substr" => return Self::parse_getrange(rest),
+                            "incr" => Self::parse_incr(rest),
+                            "decr" => Self::parse_decr(rest),
+                            "incrby" => return Self::parse_incrby(rest),
+                            "decrby" => return Self::parse_decrby(rest),
                             "info" => Self::parse_info(rest),
-                            "replconf" => Self::parse_replconf(rest),
-                            "psync" => Self::parse_psync(rest),
+                            "replconf" => return Self::parse_replconf(rest),
+                            "psync" => return Self::parse_psync(rest),
+                            "debug" => return Self::parse_debug(rest),
+                            "replicaof" | "slaveof" => return Self::parse_replicaof(rest),
+                            "command" => Self::parse_command(rest),
+                            "failover" => return Self::parse_failover(rest),
+                            "memory" => return Self::parse_memory(rest),
+                            "config" => Self::parse_config(rest),
+                            "object" => Self::parse_object(rest),
+                            "type" => Self::parse_type(rest),
+                            "del" => Self::parse_del(rest),
+                            "exists" => Self::parse_exists(rest),
+                            "xadd" => Self::parse_xadd(rest),
+                            "xlen" => Self::parse_xlen(rest),
+                            "xrange" => return Self::parse_xrange(rest),
+                            "xread" => return Self::parse_xread(rest),
+                            "xgroup" => Self::parse_xgroup(rest),
+                            "xreadgroup" => return Self::parse_xreadgroup(rest),
+                            "xack" => Self::parse_xack(rest),
+                            "xinfo" => Self::parse_xinfo(rest),
+                            "pfadd" => Self::parse_pfadd(rest),
+                            "pfcount" => Self::parse_pfcount(rest),
+                            "pfmerge" => Self::parse_pfmerge(rest),
+                            "geoadd" => Self::parse_geoadd(rest),
+                            "geopos" => Self::parse_geopos(rest),
+                            "geodist" => Self::parse_geodist(rest),
+                            "geosearch" => Self::parse_geosearch(rest),
+                            "zadd" => return Self::parse_zadd(rest),
+                            "zmpop" => return Self::parse_zmpop(rest),
+                            "bzmpop" => return Self::parse_bzmpop(rest),
                             _ => None,
                         },
                         None => None,
@@ -52,11 +253,17 @@ impl RedisCommand {
             RedisType::BulkString { data, .. } | RedisType::SimpleString { data, .. } => {
                 match data.to_lowercase().as_str() {
                     "ping" => Some(RedisCommand::PING),
+                    "dbsize" => Some(RedisCommand::DBSIZE),
+                    "failover" => return Self::parse_failover(&[]),
+                    "time" => Some(RedisCommand::TIME),
+                    "info" => Self::parse_info(&[]),
                     _ => None,
                 }
             }
             _ => None,
-        }
+        };
+
+        parsed.ok_or(ParseError::Invalid)
     }
 
     pub fn default_capabilities() -> Self {
@@ -72,432 +279,3230 @@ impl RedisCommand {
         }
     }
 
-    pub fn is_write_command(&self) -> bool {
+    pub fn name(&self) -> &'static str {
         match self {
-            RedisCommand::SET { .. } => true,
-            _ => false,
+            Self::PING => "ping",
+            Self::ECHO(_) => "echo",
+            Self::SET { .. } => "set",
+            Self::GET { .. } => "get",
+            Self::APPEND { .. } => "append",
+            Self::GETRANGE { .. } => "getrange",
+            Self::INCR { .. } => "incr",
+            Self::DECR { .. } => "decr",
+            Self::INCRBY { .. } => "incrby",
+            Self::DECRBY { .. } => "decrby",
+            Self::INFO { .. } => "info",
+            Self::REPLCONF { .. } => "replconf",
+            Self::PSYNC { .. } => "psync",
+            Self::DEBUG { .. } => "debug",
+            Self::REPLICAOF { .. } => "replicaof",
+            Self::COMMAND { .. } => "command",
+            Self::FAILOVER { .. } => "failover",
+            Self::MEMORY { .. } => "memory",
+            Self::DBSIZE => "dbsize",
+            Self::TIME => "time",
+            Self::CONFIG { .. } => "config",
+            Self::OBJECT { .. } => "object",
+            Self::TYPE { .. } => "type",
+            Self::DEL { .. } => "del",
+            Self::EXISTS { .. } => "exists",
+            Self::XADD { .. } => "xadd",
+            Self::XLEN { .. } => "xlen",
+            Self::XRANGE { .. } => "xrange",
+            Self::XREAD { .. } => "xread",
+            Self::XGROUP { .. } => "xgroup",
+            Self::XREADGROUP { .. } => "xreadgroup",
+            Self::XACK { .. } => "xack",
+            Self::XINFO { .. } => "xinfo",
+            Self::PFADD { .. } => "pfadd",
+            Self::PFCOUNT { .. } => "pfcount",
+            Self::PFMERGE { .. } => "pfmerge",
+            Self::GEOADD { .. } => "geoadd",
+            Self::GEOPOS { .. } => "geopos",
+            Self::GEODIST { .. } => "geodist",
+            Self::GEOSEARCH { .. } => "geosearch",
+            Self::ZADD { .. } => "zadd",
+            Self::ZMPOP { .. } => "zmpop",
+            Self::BZMPOP { .. } => "bzmpop",
         }
     }
 
+    pub fn spec(&self) -> &'static CommandSpec {
+        command_table::lookup(self.name())
+            .unwrap_or_else(|| panic!("{} is missing from the command table", self.name()))
+    }
+
+    pub fn is_write_command(&self) -> bool {
+        self.spec().write
+    }
+
+    /// The command as replicas should run it, given the `result` it had here. Relative expiries
+    /// become absolute, so a key expires at the same time everywhere however late the command
+    /// reaches a replica. Generated stream ids are replaced by the one added, so entries get the
+    /// same id everywhere. `None` when replicas have nothing to run, like a SET that NX or XX
+    /// held back, a command that failed, or a PFADD that left the HyperLogLog as it was.
+    pub fn for_replication(&self, result: &RedisType) -> Option<RedisCommand> {
+        let command = match self {
+            _ if matches!(result, RedisType::SimpleError { .. }) => return None,
+            Self::PFADD { .. } if *result == RedisType::integer(0) => return None,
+            Self::XADD { key, id, fields } if !matches!(id, XAddId::Explicit(_)) => {
+                let added = result
+                    .extract_string()
+                    .and_then(|added| StreamId::parse(added, 0));
+
+                Self::XADD {
+                    key: key.clone(),
+                    id: added.map_or(*id, XAddId::Explicit),
+                    fields: fields.clone(),
+                }
+            }
+            Self::SET {
+                condition: Some(_), ..
+            } if *result == RedisType::NullBulkString => return None,
+            // The master decided the SET goes ahead, the condition could only make a replica
+            // decide otherwise with its own view of the key
+            Self::SET {
+                key, val, expiry, ..
+            } => Self::SET {
+                key: key.clone(),
+                val: val.clone(),
+                expiry: match expiry {
+                    Some(ExpiryOption::After(ttl)) => {
+                        Some(ExpiryOption::At(SystemTime::now() + *ttl))
+                    }
+                    other => other.clone(),
+                },
+                condition: None,
+            },
+            // The master already waited, and replicas must pop the same members right away
+            Self::BZMPOP {
+                keys, end, count, ..
+            } => Self::ZMPOP {
+                keys: keys.clone(),
+                end: *end,
+                count: *count,
+            },
+            other => other.clone(),
+        };
+
+        Some(command)
+    }
+
     fn parse_echo(data: &[Box<RedisType>]) -> Option<RedisCommand> {
-        data.get(0)
+        data.first()
             .and_then(|argument| argument.extract_string())
             .map(|argument| RedisCommand::ECHO(argument.to_string()))
     }
 
     fn parse_get(data: &[Box<RedisType>]) -> Option<RedisCommand> {
-        data.get(0)
+        data.first()
             .and_then(|key| key.extract_string())
             .map(|key| RedisCommand::GET {
                 key: key.to_string(),
             })
     }
 
-    fn parse_set(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+    fn parse_set(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
         if data.len() < 2 {
-            return None;
+            return Err(ParseError::Invalid);
         }
 
-        let key = data[0].extract_string()?.to_string();
+        let key = data[0]
+            .extract_string()
+            .ok_or(ParseError::Invalid)?
+            .to_string();
         let value = data[1].as_ref().clone();
-        let mut ttl: Option<Duration> = None;
+        let mut expiry: Option<ExpiryOption> = None;
+        let mut condition = None;
 
-        // Process optional parameters
+        // Process optional parameters. Each kind can only be given once, as the options of a
+        // kind contradict each other
         let mut i = 2;
         while i < data.len() {
-            if let Some(arg) = data[i].extract_string() {
-                match arg.to_uppercase().as_str() {
-                    "PX" => {
-                        ttl = data.get(i + 1).and_then(|val| {
-                            val.extract_string()
-                                .and_then(|v| v.parse::<u64>().ok())
-                                .map(Duration::from_millis)
-                        });
-                        i += 2; // Skip the next item since it's part of this option
-                    }
-                    _ => i += 1,
+            let name = data[i]
+                .extract_string()
+                .ok_or(ParseError::Reply(SYNTAX_ERROR))?
+                .to_lowercase();
+            match name.as_str() {
+                "nx" | "xx" if condition.is_some() => return Err(ParseError::Reply(SYNTAX_ERROR)),
+                "nx" => {
+                    condition = Some(Condition::Nx);
+                    i += 1;
                 }
-            } else {
-                i += 1;
+                "xx" => {
+                    condition = Some(Condition::Xx);
+                    i += 1;
+                }
+                _ => match ExpiryOption::parse(&data[i..]) {
+                    // PERSIST is only valid for GETEX
+                    Ok((option, consumed))
+                        if expiry.is_none() && option != ExpiryOption::Persist =>
+                    {
+                        expiry = Some(option);
+                        i += consumed;
+                    }
+                    Err(ParseError::Reply(message)) => return Err(ParseError::Reply(message)),
+                    _ => return Err(ParseError::Reply(SYNTAX_ERROR)),
+                },
             }
         }
 
-        Some(RedisCommand::SET {
+        Ok(RedisCommand::SET {
             key,
             val: value,
-            ttl,
+            expiry,
+            condition,
         })
     }
 
-    fn parse_info(data: &[Box<RedisType>]) -> Option<RedisCommand> {
-        data.get(0)
-            .and_then(|arg| arg.extract_string())
-            .map(|arg| RedisCommand::INFO {
-                arg: arg.to_string(),
-            })
+    fn parse_append(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.len() != 2 {
+            return None;
+        }
+
+        Some(RedisCommand::APPEND {
+            key: data[0].extract_string()?.to_string(),
+            value: data[1].extract_bytes()?.to_vec(),
+        })
     }
 
-    fn parse_replconf(data: &[Box<RedisType>]) -> Option<RedisCommand> {
-        if data.len() < 2 {
-            return None;
+    fn parse_getrange(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        if data.len() != 3 {
+            return Err(ParseError::Invalid);
         }
 
-        match data[0].extract_string() {
-            Some("listening-port") => {
-                let port: u16 = data[1].extract_string().and_then(|raw| raw.parse().ok())?;
+        Ok(RedisCommand::GETRANGE {
+            key: Self::string_arg(data, 0)?.to_string(),
+            start: Self::int_arg(data, 1)?,
+            end: Self::int_arg(data, 2)?,
+        })
+    }
 
-                Some(RedisCommand::REPLCONF {
-                    arg: ReplConfArgs::Port(port),
-                })
-            }
-            Some("capa") => {
-                let mut caps = Vec::new();
-                for element in data[1..].iter() {
-                    let cap = element.extract_string()?;
-                    caps.push(cap.to_string());
-                }
+    fn parse_incr(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.len() != 1 {
+            return None;
+        }
 
-                Some(RedisCommand::REPLCONF {
-                    arg: ReplConfArgs::Capabilities(caps),
-                })
-            }
-            Some("GETACK") => {
-                let arg = data[1].extract_string()?.to_string();
+        Some(RedisCommand::INCR {
+            key: data[0].extract_string()?.to_string(),
+        })
+    }
 
-                Some(RedisCommand::REPLCONF {
-                    arg: ReplConfArgs::GetAck(arg),
-                })
-            }
-            Some("ACK") => {
-                let offset: i64 = data[1].extract_string().and_then(|raw| raw.parse().ok())?;
+    fn parse_decr(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.len() != 1 {
+            return None;
+        }
 
-                Some(RedisCommand::REPLCONF {
-                    arg: ReplConfArgs::Ack(offset),
-                })
-            }
-            _ => None,
+        Some(RedisCommand::DECR {
+            key: data[0].extract_string()?.to_string(),
+        })
+    }
+
+    fn parse_incrby(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        if data.len() != 2 {
+            return Err(ParseError::Invalid);
         }
+
+        Ok(RedisCommand::INCRBY {
+            key: Self::string_arg(data, 0)?.to_string(),
+            delta: Self::int_arg(data, 1)?,
+        })
     }
 
-    fn parse_psync(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+    fn parse_decrby(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
         if data.len() != 2 {
-            return None;
+            return Err(ParseError::Invalid);
+        }
+
+        Ok(RedisCommand::DECRBY {
+            key: Self::string_arg(data, 0)?.to_string(),
+            delta: Self::int_arg(data, 1)?,
+        })
+    }
+
+    fn parse_info(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let sections = data
+            .iter()
+            .map(|arg| arg.extract_string().map(str::to_string))
+            .collect::<Option<_>>()?;
+
+        Some(RedisCommand::INFO { sections })
+    }
+
+    fn parse_replconf(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        if data.len() < 2 {
+            return Err(ParseError::Invalid);
         }
 
-        let master_id = data[0].extract_string()?.to_string();
-        let master_offset = data[1].extract_string()?.parse().ok()?;
+        // Option names are case insensitive, as in Redis
+        let arg = match Self::string_arg(data, 0)?.to_lowercase().as_str() {
+            "listening-port" => ReplConfArgs::Port(Self::int_arg(data, 1)?),
+            "capa" => ReplConfArgs::Capabilities(
+                Self::parse_strings(&data[1..]).ok_or(ParseError::Invalid)?,
+            ),
+            "getack" => ReplConfArgs::GetAck(Self::string_arg(data, 1)?.to_string()),
+            "ack" => ReplConfArgs::Ack(Self::int_arg(data, 1)?),
+            _ => ReplConfArgs::Other(Self::parse_strings(data).ok_or(ParseError::Invalid)?),
+        };
+
+        Ok(RedisCommand::REPLCONF { arg })
+    }
+
+    fn parse_psync(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        if data.len() != 2 {
+            return Err(ParseError::Invalid);
+        }
 
-        Some(RedisCommand::PSYNC {
-            master_id,
-            master_offset,
+        Ok(RedisCommand::PSYNC {
+            master_id: Self::string_arg(data, 0)?.to_string(),
+            master_offset: Self::int_arg(data, 1)?,
         })
     }
-}
 
-impl RedisWritable for RedisCommand {
-    fn write_as_protocol(&self) -> Vec<u8> {
-        let parts = match self {
-            Self::PING => vec![RedisType::bulk_string("PING")],
-            Self::ECHO(value) => vec![
-                RedisType::bulk_string("ECHO"),
-                RedisType::bulk_string(value),
-            ],
-            Self::SET { key, val, ttl } => {
-                let mut command = vec![
-                    RedisType::bulk_string("SET"),
-                    RedisType::bulk_string(key),
-                    val.clone(),
-                ];
+    fn parse_replicaof(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        if data.len() != 2 {
+            return Err(ParseError::Invalid);
+        }
+
+        let host = Self::string_arg(data, 0)?;
+        let port = Self::string_arg(data, 1)?;
+
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            Ok(RedisCommand::REPLICAOF { master: None })
+        } else {
+            Ok(RedisCommand::REPLICAOF {
+                master: Some((host.to_string(), Self::int_arg(data, 1)?)),
+            })
+        }
+    }
 
-                if let Some(ttl) = ttl {
-                    command.push(RedisType::bulk_string("px"));
-                    command.push(RedisType::bulk_string(&ttl.as_millis().to_string()));
+    fn parse_command(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let subcommand = data.first()?.extract_string()?;
+
+        match subcommand.to_lowercase().as_str() {
+            "getkeysandflags" if data.len() > 1 => {
+                let mut args = Vec::new();
+                for arg in data[1..].iter() {
+                    args.push(arg.extract_string()?.to_string());
                 }
 
-                command
+                Some(RedisCommand::COMMAND {
+                    arg: CommandArgs::GetKeysAndFlags(args),
+                })
             }
-            Self::GET { key } => vec![RedisType::bulk_string("GET"), RedisType::bulk_string(key)],
-            Self::INFO { arg } => vec![RedisType::bulk_string("INFO"), RedisType::bulk_string(arg)],
-            Self::REPLCONF { arg } => {
-                let mut command = vec![RedisType::bulk_string("REPLCONF")];
+            "docs" => {
+                let mut names = Vec::new();
+                for name in data[1..].iter() {
+                    names.push(name.extract_string()?.to_string());
+                }
 
-                match arg {
-                    ReplConfArgs::Port(port) => {
-                        command.push(RedisType::bulk_string("listening-port"));
-                        command.push(RedisType::bulk_string(&port.to_string()))
-                    }
-                    ReplConfArgs::Capabilities(caps) => {
-                        command.push(RedisType::bulk_string("capa"));
-                        for cap in caps {
-                            command.push(RedisType::bulk_string(cap))
-                        }
-                    }
-                    ReplConfArgs::GetAck(arg) => {
-                        command.push(RedisType::bulk_string("GETACK"));
-                        command.push(RedisType::bulk_string(arg))
+                Some(RedisCommand::COMMAND {
+                    arg: CommandArgs::Docs(names),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_failover(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let (mut target, mut force, mut abort, mut timeout) = (None, false, false, None);
+
+        let mut i = 0;
+        while i < data.len() {
+            match Self::string_arg(data, i)?.to_lowercase().as_str() {
+                "to" if target.is_none() => {
+                    let host = Self::string_arg(data, i + 1)?.to_string();
+                    let port = Self::int_arg(data, i + 2)?;
+                    target = Some((host, port));
+                    i += 3;
+                }
+                "timeout" if timeout.is_none() => {
+                    let ms: u64 = Self::int_arg(data, i + 1)?;
+                    if ms == 0 {
+                        return Err(ParseError::Invalid);
                     }
-                    ReplConfArgs::Ack(offset) => {
-                        command.push(RedisType::bulk_string("ACK"));
-                        command.push(RedisType::bulk_string(&offset.to_string()))
+                    timeout = Some(ms);
+                    i += 2;
+                }
+                "force" if !force => {
+                    force = true;
+                    i += 1;
+                }
+                "abort" if !abort => {
+                    abort = true;
+                    i += 1;
+                }
+                _ => return Err(ParseError::Invalid),
+            }
+        }
+
+        Ok(RedisCommand::FAILOVER {
+            target,
+            force,
+            abort,
+            timeout,
+        })
+    }
+
+    fn parse_memory(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let subcommand = Self::string_arg(data, 0)?;
+
+        let arg = match subcommand.to_lowercase().as_str() {
+            "usage" if data.len() == 2 || data.len() == 4 => {
+                let samples = match data.get(2) {
+                    Some(option) if Self::is_option(option, "samples") => {
+                        Some(Self::int_arg(data, 3)?)
                     }
+                    Some(_) => return Err(ParseError::Invalid),
+                    None => None,
                 };
 
-                command
+                MemoryArgs::Usage {
+                    key: Self::string_arg(data, 1)?.to_string(),
+                    samples,
+                }
             }
-            Self::PSYNC {
-                master_id,
-                master_offset,
-            } => vec![
-                RedisType::bulk_string("PSYNC"),
-                RedisType::bulk_string(master_id),
-                RedisType::bulk_string(&master_offset.to_string()),
-            ],
+            "stats" if data.len() == 1 => MemoryArgs::Stats,
+            "doctor" if data.len() == 1 => MemoryArgs::Doctor,
+            _ => return Err(ParseError::Invalid),
         };
 
-        RedisType::list(parts).write_as_protocol()
+        Ok(RedisCommand::MEMORY { arg })
     }
-}
-
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub enum ReplConfArgs {
-    Port(u16),
-    Capabilities(Vec<String>),
-    GetAck(String),
-    Ack(i64),
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn parse_config(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let subcommand = data.first()?.extract_string()?;
 
-    #[test]
-    fn test_parse_ping() {
-        let data = RedisType::SimpleString {
-            data: "PING".to_string(),
-        };
+        match subcommand.to_lowercase().as_str() {
+            "resetstat" if data.len() == 1 => Some(RedisCommand::CONFIG {
+                arg: ConfigArgs::ResetStat,
+            }),
+            _ => None,
+        }
+    }
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, Some(RedisCommand::PING));
+    fn parse_xadd(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        // The key, the id and at least one field-value pair
+        if data.len() < 4 || !data.len().is_multiple_of(2) {
+            return None;
+        }
 
-        let data = RedisType::list(vec![RedisType::bulk_string("Ping")]);
+        let fields = data[2..]
+            .chunks(2)
+            .map(|pair| {
+                Some((
+                    pair[0].extract_string()?.to_string(),
+                    pair[1].extract_string()?.to_string(),
+                ))
+            })
+            .collect::<Option<_>>()?;
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, Some(RedisCommand::PING));
+        Some(RedisCommand::XADD {
+            key: data[0].extract_string()?.to_string(),
+            id: XAddId::parse(data[1].extract_string()?)?,
+            fields,
+        })
     }
 
-    #[test]
-    fn test_parse_echo() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("echo"),
-            RedisType::bulk_string("hello"),
-        ]);
+    fn parse_xlen(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.len() != 1 {
+            return None;
+        }
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, Some(RedisCommand::ECHO("hello".to_string())));
+        Some(RedisCommand::XLEN {
+            key: data[0].extract_string()?.to_string(),
+        })
     }
 
-    #[test]
-    fn test_parse_invalid() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("invalid"),
-            RedisType::bulk_string("world"),
-        ]);
+    fn parse_xrange(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let count = match data.len() {
+            3 => None,
+            5 if Self::is_option(&data[3], "count") => Some(Self::int_arg(data, 4)?),
+            _ => return Err(ParseError::Invalid),
+        };
+        let bound = |index, start| {
+            StreamId::parse_range_bound(Self::string_arg(data, index)?, start)
+                .ok_or(ParseError::Invalid)
+        };
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, None);
+        Ok(RedisCommand::XRANGE {
+            key: Self::string_arg(data, 0)?.to_string(),
+            start: bound(1, true)?,
+            end: bound(2, false)?,
+            count,
+        })
     }
 
-    #[test]
-    fn test_parse_empty() {
-        let data = RedisType::SimpleString {
-            data: "".to_string(),
-        };
+    fn parse_xread(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let mut count = None;
+        let mut block = None;
+
+        let mut i = 0;
+        loop {
+            let option = Self::string_arg(data, i)?.to_lowercase();
+            match option.as_str() {
+                "count" => count = Some(Self::int_arg(data, i + 1)?),
+                "block" => block = Some(Duration::from_millis(Self::int_arg(data, i + 1)?)),
+                "streams" => break,
+                _ => return Err(ParseError::Invalid),
+            }
+            i += 2;
+        }
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, None);
+        Ok(RedisCommand::XREAD {
+            count,
+            block,
+            streams: Self::parse_streams(&data[i + 1..], XReadId::parse)
+                .ok_or(ParseError::Invalid)?,
+        })
+    }
+
+    fn parse_xgroup(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let subcommand = data.first()?.extract_string()?;
+
+        match subcommand.to_lowercase().as_str() {
+            "create" if data.len() == 4 || data.len() == 5 => {
+                let mkstream = match data.get(4) {
+                    Some(option) => option.extract_string()?.eq_ignore_ascii_case("mkstream"),
+                    None => false,
+                };
+                if data.len() == 5 && !mkstream {
+                    return None;
+                }
+
+                Some(RedisCommand::XGROUP {
+                    arg: XGroupArgs::Create {
+                        key: data[1].extract_string()?.to_string(),
+                        group: data[2].extract_string()?.to_string(),
+                        id: XReadId::parse(data[3].extract_string()?)?,
+                        mkstream,
+                    },
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_xreadgroup(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        if !data
+            .first()
+            .is_some_and(|option| Self::is_option(option, "group"))
+        {
+            return Err(ParseError::Invalid);
+        }
+        let group = Self::string_arg(data, 1)?.to_string();
+        let consumer = Self::string_arg(data, 2)?.to_string();
+
+        let mut count = None;
+        let mut i = 3;
+        loop {
+            let option = Self::string_arg(data, i)?.to_lowercase();
+            match option.as_str() {
+                "count" => count = Some(Self::int_arg(data, i + 1)?),
+                "streams" => break,
+                _ => return Err(ParseError::Invalid),
+            }
+            i += 2;
+        }
+
+        Ok(RedisCommand::XREADGROUP {
+            group,
+            consumer,
+            count,
+            streams: Self::parse_streams(&data[i + 1..], XReadGroupId::parse)
+                .ok_or(ParseError::Invalid)?,
+        })
+    }
+
+    fn parse_xack(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.len() < 3 {
+            return None;
+        }
+
+        let ids = data[2..]
+            .iter()
+            .map(|id| StreamId::parse(id.extract_string()?, 0))
+            .collect::<Option<_>>()?;
+
+        Some(RedisCommand::XACK {
+            key: data[0].extract_string()?.to_string(),
+            group: data[1].extract_string()?.to_string(),
+            ids,
+        })
+    }
+
+    fn parse_xinfo(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.len() != 2 {
+            return None;
+        }
+
+        let key = data[1].extract_string()?.to_string();
+        let arg = match data[0].extract_string()?.to_lowercase().as_str() {
+            "stream" => XInfoArgs::Stream { key },
+            "groups" => XInfoArgs::Groups { key },
+            _ => return None,
+        };
+
+        Some(RedisCommand::XINFO { arg })
+    }
+
+    fn parse_pfadd(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let (key, elements) = data.split_first()?;
+
+        Some(RedisCommand::PFADD {
+            key: key.extract_string()?.to_string(),
+            elements: Self::parse_strings(elements)?,
+        })
+    }
+
+    fn parse_pfcount(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(RedisCommand::PFCOUNT {
+            keys: Self::parse_strings(data)?,
+        })
+    }
+
+    fn parse_pfmerge(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let (dest, sources) = data.split_first()?;
+
+        Some(RedisCommand::PFMERGE {
+            dest: dest.extract_string()?.to_string(),
+            sources: Self::parse_strings(sources)?,
+        })
+    }
+
+    fn parse_geoadd(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let (key, rest) = data.split_first()?;
+        // Longitude, latitude and member triples
+        if rest.is_empty() || !rest.len().is_multiple_of(3) {
+            return None;
+        }
+
+        let members = rest
+            .chunks(3)
+            .map(|triple| {
+                Some((
+                    Point::parse(triple[0].extract_string()?, triple[1].extract_string()?)?,
+                    triple[2].extract_string()?.to_string(),
+                ))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(RedisCommand::GEOADD {
+            key: key.extract_string()?.to_string(),
+            members,
+        })
+    }
+
+    fn parse_geopos(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let (key, members) = data.split_first()?;
+
+        Some(RedisCommand::GEOPOS {
+            key: key.extract_string()?.to_string(),
+            members: Self::parse_strings(members)?,
+        })
+    }
+
+    fn parse_geodist(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let unit = match data.len() {
+            3 => GeoUnit::Meters,
+            4 => GeoUnit::parse(data[3].extract_string()?)?,
+            _ => return None,
+        };
+
+        Some(RedisCommand::GEODIST {
+            key: data[0].extract_string()?.to_string(),
+            from: data[1].extract_string()?.to_string(),
+            to: data[2].extract_string()?.to_string(),
+            unit,
+        })
+    }
+
+    fn parse_geosearch(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let key = data.first()?.extract_string()?.to_string();
+        let mut from = None;
+        let mut radius = None;
+
+        let mut i = 1;
+        while i < data.len() {
+            let option = data[i].extract_string()?.to_lowercase();
+            match option.as_str() {
+                "frommember" if from.is_none() => {
+                    let member = data.get(i + 1)?.extract_string()?.to_string();
+                    from = Some(GeoSearchFrom::Member(member));
+                    i += 2;
+                }
+                "fromlonlat" if from.is_none() => {
+                    let point = Point::parse(
+                        data.get(i + 1)?.extract_string()?,
+                        data.get(i + 2)?.extract_string()?,
+                    )?;
+                    from = Some(GeoSearchFrom::Point(point));
+                    i += 3;
+                }
+                "byradius" if radius.is_none() => {
+                    radius = Some(GeoDistance::parse(
+                        data.get(i + 1)?.extract_string()?,
+                        data.get(i + 2)?.extract_string()?,
+                    )?);
+                    i += 3;
+                }
+                _ => return None,
+            }
+        }
+
+        Some(RedisCommand::GEOSEARCH {
+            key,
+            from: from?,
+            radius: radius?,
+        })
+    }
+
+    fn parse_zadd(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let (key, rest) = data.split_first().ok_or(ParseError::Invalid)?;
+        let mut options = ZAddOptions::default();
+
+        // Options come first, the score of the first pair ends them. Like in Redis, they can be
+        // repeated, and contradicting ones are only told apart once they're all read
+        let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+        let mut i = 0;
+        while let Some(option) = rest.get(i).and_then(|arg| arg.extract_string()) {
+            match option.to_lowercase().as_str() {
+                "nx" => nx = true,
+                "xx" => xx = true,
+                "gt" => gt = true,
+                "lt" => lt = true,
+                "ch" => options.changed = true,
+                "incr" => options.incr = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let pairs = &rest[i..];
+        if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+            return Err(ParseError::Invalid);
+        }
+        if nx && xx {
+            return Err(ParseError::Reply(
+                "ERR XX and NX options at the same time are not compatible",
+            ));
+        }
+        // NX only adds members, so there's no score to compare against
+        if (nx && (gt || lt)) || (gt && lt) {
+            return Err(ParseError::Reply(
+                "ERR GT, LT, and/or NX options at the same time are not compatible",
+            ));
+        }
+        if options.incr && pairs.len() > 2 {
+            return Err(ParseError::Reply(
+                "ERR INCR option supports a single increment-element pair",
+            ));
+        }
+        options.condition = match (nx, xx) {
+            (true, _) => Some(Condition::Nx),
+            (_, true) => Some(Condition::Xx),
+            _ => None,
+        };
+        options.comparison = match (gt, lt) {
+            (true, _) => Some(ScoreComparison::Gt),
+            (_, true) => Some(ScoreComparison::Lt),
+            _ => None,
+        };
+
+        let members = pairs
+            .chunks(2)
+            .map(|pair| {
+                let score = pair[0]
+                    .extract_string()
+                    .and_then(parse_float_arg)
+                    .ok_or(ParseError::Reply(FLOAT_ERROR))?;
+                let member = pair[1].extract_string().ok_or(ParseError::Invalid)?;
+
+                Ok((Score(score), member.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(RedisCommand::ZADD {
+            key: key.extract_string().ok_or(ParseError::Invalid)?.to_string(),
+            options,
+            members,
+        })
+    }
+
+    fn parse_zmpop(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let numkeys: usize = Self::int_arg(data, 0)?;
+        if numkeys == 0 {
+            return Err(ParseError::Invalid);
+        }
+        let keys = data
+            .get(1..=numkeys)
+            .and_then(Self::parse_strings)
+            .ok_or(ParseError::Invalid)?;
+
+        let rest = &data[numkeys + 1..];
+        let end = match Self::string_arg(rest, 0)?.to_lowercase().as_str() {
+            "min" => PopEnd::Min,
+            "max" => PopEnd::Max,
+            _ => return Err(ParseError::Invalid),
+        };
+        let count = match rest.len() {
+            1 => 1,
+            3 if Self::is_option(&rest[1], "count") => match Self::int_arg(rest, 2)? {
+                0 => return Err(ParseError::Invalid),
+                count => count,
+            },
+            _ => return Err(ParseError::Invalid),
+        };
+
+        Ok(RedisCommand::ZMPOP { keys, end, count })
+    }
+
+    fn parse_bzmpop(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let seconds = data
+            .first()
+            .and_then(|timeout| timeout.extract_string())
+            .ok_or(ParseError::Invalid)?;
+        let seconds = parse_float_arg(seconds).ok_or(ParseError::Reply(
+            "ERR timeout is not a float or out of range",
+        ))?;
+        if seconds < 0.0 {
+            return Err(ParseError::Reply("ERR timeout is negative"));
+        }
+        // Like Redis, the timeout in milliseconds has to fit in 64 bits, which rules out infinity
+        if seconds * 1000.0 > i64::MAX as f64 {
+            return Err(ParseError::Reply("ERR timeout is out of range"));
+        }
+        let timeout = Duration::from_secs_f64(seconds);
+
+        match Self::parse_zmpop(&data[1..])? {
+            RedisCommand::ZMPOP { keys, end, count } => Ok(RedisCommand::BZMPOP {
+                timeout,
+                keys,
+                end,
+                count,
+            }),
+            _ => Err(ParseError::Invalid),
+        }
+    }
+
+    /// The argument at `index` as a string, `Invalid` if it's missing.
+    fn string_arg(data: &[Box<RedisType>], index: usize) -> Result<&str, ParseError> {
+        data.get(index)
+            .and_then(|arg| arg.extract_string())
+            .ok_or(ParseError::Invalid)
+    }
+
+    /// The argument at `index` as an integer. Like in Redis, one that doesn't parse as an integer
+    /// gets its own error.
+    fn int_arg<T: TryFrom<i64>>(data: &[Box<RedisType>], index: usize) -> Result<T, ParseError> {
+        parse_int_arg(Self::string_arg(data, index)?).ok_or(ParseError::Reply(INTEGER_ERROR))
+    }
+
+    /// Whether `arg` is the option `name`, in any case.
+    fn is_option(arg: &RedisType, name: &str) -> bool {
+        arg.extract_string()
+            .is_some_and(|arg| arg.eq_ignore_ascii_case(name))
+    }
+
+    fn parse_strings(data: &[Box<RedisType>]) -> Option<Vec<String>> {
+        data.iter()
+            .map(|arg| arg.extract_string().map(str::to_string))
+            .collect()
+    }
+
+    /// Parses what follows STREAMS in XREAD and XREADGROUP: the keys, then an id for each of them.
+    fn parse_streams<T>(
+        data: &[Box<RedisType>],
+        parse_id: impl Fn(&str) -> Option<T>,
+    ) -> Option<Vec<(String, T)>> {
+        if data.is_empty() || !data.len().is_multiple_of(2) {
+            return None;
+        }
+        let (keys, ids) = data.split_at(data.len() / 2);
+
+        keys.iter()
+            .zip(ids)
+            .map(|(key, id)| {
+                Some((
+                    key.extract_string()?.to_string(),
+                    parse_id(id.extract_string()?)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn parse_object(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        let subcommand = data.first()?.extract_string()?;
+
+        match subcommand.to_lowercase().as_str() {
+            "encoding" if data.len() == 2 => Some(RedisCommand::OBJECT {
+                arg: ObjectArgs::Encoding {
+                    key: data[1].extract_string()?.to_string(),
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    fn parse_type(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.len() != 1 {
+            return None;
+        }
+
+        Some(RedisCommand::TYPE {
+            key: data[0].extract_string()?.to_string(),
+        })
+    }
+
+    fn parse_del(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(RedisCommand::DEL {
+            keys: Self::parse_strings(data)?,
+        })
+    }
+
+    fn parse_exists(data: &[Box<RedisType>]) -> Option<RedisCommand> {
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(RedisCommand::EXISTS {
+            keys: Self::parse_strings(data)?,
+        })
+    }
+
+    fn parse_debug(data: &[Box<RedisType>]) -> Result<RedisCommand, ParseError> {
+        let subcommand = Self::string_arg(data, 0)?;
+
+        let arg = match subcommand.to_lowercase().as_str() {
+            "stringmatch-len" if data.len() == 3 => DebugArgs::StringMatchLen {
+                pattern: Self::string_arg(data, 1)?.to_string(),
+                string: Self::string_arg(data, 2)?.to_string(),
+            },
+            // Any integer goes, like in Redis, with 0 turning active expiry off
+            "set-active-expire" if data.len() == 2 => {
+                DebugArgs::SetActiveExpire(Self::int_arg::<i64>(data, 1)? != 0)
+            }
+            _ => return Err(ParseError::Invalid),
+        };
+
+        Ok(RedisCommand::DEBUG { arg })
+    }
+}
+
+impl RedisCommand {
+    // What ZMPOP and BZMPOP share, from numkeys on
+    fn write_zmpop_args(keys: &[String], end: PopEnd, count: usize) -> Vec<RedisType> {
+        let mut args = vec![RedisType::bulk_string(&keys.len().to_string())];
+        args.extend(keys.iter().map(|key| RedisType::bulk_string(key)));
+        args.push(RedisType::bulk_string(match end {
+            PopEnd::Min => "MIN",
+            PopEnd::Max => "MAX",
+        }));
+        args.push(RedisType::bulk_string("COUNT"));
+        args.push(RedisType::bulk_string(&count.to_string()));
+
+        args
+    }
+}
+
+impl RedisWritable for RedisCommand {
+    fn write_as_protocol(&self) -> Vec<u8> {
+        let parts = match self {
+            Self::PING => vec![RedisType::bulk_string("PING")],
+            Self::DBSIZE => vec![RedisType::bulk_string("DBSIZE")],
+            Self::TIME => vec![RedisType::bulk_string("TIME")],
+            Self::ECHO(value) => vec![
+                RedisType::bulk_string("ECHO"),
+                RedisType::bulk_string(value),
+            ],
+            Self::SET {
+                key,
+                val,
+                expiry,
+                condition,
+            } => {
+                let mut command = vec![
+                    RedisType::bulk_string("SET"),
+                    RedisType::bulk_string(key),
+                    val.clone(),
+                ];
+
+                if let Some(expiry) = expiry {
+                    command.extend(expiry.write_args());
+                }
+                match condition {
+                    Some(Condition::Nx) => command.push(RedisType::bulk_string("NX")),
+                    Some(Condition::Xx) => command.push(RedisType::bulk_string("XX")),
+                    None => {}
+                }
+
+                command
+            }
+            Self::GET { key } => vec![RedisType::bulk_string("GET"), RedisType::bulk_string(key)],
+            Self::APPEND { key, value } => vec![
+                RedisType::bulk_string("APPEND"),
+                RedisType::bulk_string(key),
+                RedisType::bulk_bytes(value),
+            ],
+            Self::GETRANGE { key, start, end } => vec![
+                RedisType::bulk_string("GETRANGE"),
+                RedisType::bulk_string(key),
+                RedisType::bulk_string(&start.to_string()),
+                RedisType::bulk_string(&end.to_string()),
+            ],
+            Self::INCR { key } => vec![RedisType::bulk_string("INCR"), RedisType::bulk_string(key)],
+            Self::DECR { key } => vec![RedisType::bulk_string("DECR"), RedisType::bulk_string(key)],
+            Self::INCRBY { key, delta } => vec![
+                RedisType::bulk_string("INCRBY"),
+                RedisType::bulk_string(key),
+                RedisType::bulk_string(&delta.to_string()),
+            ],
+            Self::DECRBY { key, delta } => vec![
+                RedisType::bulk_string("DECRBY"),
+                RedisType::bulk_string(key),
+                RedisType::bulk_string(&delta.to_string()),
+            ],
+            Self::INFO { sections } => {
+                let mut command = vec![RedisType::bulk_string("INFO")];
+                command.extend(
+                    sections
+                        .iter()
+                        .map(|section| RedisType::bulk_string(section)),
+                );
+                command
+            }
+            Self::REPLCONF { arg } => {
+                let mut command = vec![RedisType::bulk_string("REPLCONF")];
+
+                match arg {
+                    ReplConfArgs::Port(port) => {
+                        command.push(RedisType::bulk_string("listening-port"));
+                        command.push(RedisType::bulk_string(&port.to_string()))
+                    }
+                    ReplConfArgs::Capabilities(caps) => {
+                        command.push(RedisType::bulk_string("capa"));
+                        for cap in caps {
+                            command.push(RedisType::bulk_string(cap))
+                        }
+                    }
+                    ReplConfArgs::GetAck(arg) => {
+                        command.push(RedisType::bulk_string("GETACK"));
+                        command.push(RedisType::bulk_string(arg))
+                    }
+                    ReplConfArgs::Ack(offset) => {
+                        command.push(RedisType::bulk_string("ACK"));
+                        command.push(RedisType::bulk_string(&offset.to_string()))
+                    }
+                    ReplConfArgs::Other(args) => {
+                        command.extend(args.iter().map(|arg| RedisType::bulk_string(arg)))
+                    }
+                };
+
+                command
+            }
+            Self::PSYNC {
+                master_id,
+                master_offset,
+            } => vec![
+                RedisType::bulk_string("PSYNC"),
+                RedisType::bulk_string(master_id),
+                RedisType::bulk_string(&master_offset.to_string()),
+            ],
+            Self::DEBUG { arg } => {
+                let mut command = vec![RedisType::bulk_string("DEBUG")];
+
+                match arg {
+                    DebugArgs::StringMatchLen { pattern, string } => {
+                        command.push(RedisType::bulk_string("STRINGMATCH-LEN"));
+                        command.push(RedisType::bulk_string(pattern));
+                        command.push(RedisType::bulk_string(string));
+                    }
+                    DebugArgs::SetActiveExpire(enabled) => {
+                        command.push(RedisType::bulk_string("SET-ACTIVE-EXPIRE"));
+                        command.push(RedisType::bulk_string(if *enabled { "1" } else { "0" }));
+                    }
+                };
+
+                command
+            }
+            Self::COMMAND { arg } => {
+                let mut command = vec![RedisType::bulk_string("COMMAND")];
+
+                match arg {
+                    CommandArgs::GetKeysAndFlags(args) => {
+                        command.push(RedisType::bulk_string("GETKEYSANDFLAGS"));
+                        command.extend(args.iter().map(|arg| RedisType::bulk_string(arg)));
+                    }
+                    CommandArgs::Docs(names) => {
+                        command.push(RedisType::bulk_string("DOCS"));
+                        command.extend(names.iter().map(|name| RedisType::bulk_string(name)));
+                    }
+                };
+
+                command
+            }
+            Self::FAILOVER {
+                target,
+                force,
+                abort,
+                timeout,
+            } => {
+                let mut command = vec![RedisType::bulk_string("FAILOVER")];
+
+                if let Some((host, port)) = target {
+                    command.push(RedisType::bulk_string("TO"));
+                    command.push(RedisType::bulk_string(host));
+                    command.push(RedisType::bulk_string(&port.to_string()));
+                }
+                if *force {
+                    command.push(RedisType::bulk_string("FORCE"));
+                }
+                if *abort {
+                    command.push(RedisType::bulk_string("ABORT"));
+                }
+                if let Some(timeout) = timeout {
+                    command.push(RedisType::bulk_string("TIMEOUT"));
+                    command.push(RedisType::bulk_string(&timeout.to_string()));
+                }
+
+                command
+            }
+            Self::MEMORY { arg } => {
+                let mut command = vec![RedisType::bulk_string("MEMORY")];
+
+                match arg {
+                    MemoryArgs::Usage { key, samples } => {
+                        command.push(RedisType::bulk_string("USAGE"));
+                        command.push(RedisType::bulk_string(key));
+                        if let Some(samples) = samples {
+                            command.push(RedisType::bulk_string("SAMPLES"));
+                            command.push(RedisType::bulk_string(&samples.to_string()));
+                        }
+                    }
+                    MemoryArgs::Stats => command.push(RedisType::bulk_string("STATS")),
+                    MemoryArgs::Doctor => command.push(RedisType::bulk_string("DOCTOR")),
+                };
+
+                command
+            }
+            Self::CONFIG { arg } => match arg {
+                ConfigArgs::ResetStat => vec![
+                    RedisType::bulk_string("CONFIG"),
+                    RedisType::bulk_string("RESETSTAT"),
+                ],
+            },
+            Self::XADD { key, id, fields } => {
+                let mut command = vec![
+                    RedisType::bulk_string("XADD"),
+                    RedisType::bulk_string(key),
+                    RedisType::bulk_string(&id.to_string()),
+                ];
+                for (field, value) in fields {
+                    command.push(RedisType::bulk_string(field));
+                    command.push(RedisType::bulk_string(value));
+                }
+
+                command
+            }
+            Self::XLEN { key } => vec![RedisType::bulk_string("XLEN"), RedisType::bulk_string(key)],
+            Self::XRANGE {
+                key,
+                start,
+                end,
+                count,
+            } => {
+                let mut command = vec![
+                    RedisType::bulk_string("XRANGE"),
+                    RedisType::bulk_string(key),
+                    RedisType::bulk_string(&start.to_string()),
+                    RedisType::bulk_string(&end.to_string()),
+                ];
+                if let Some(count) = count {
+                    command.push(RedisType::bulk_string("COUNT"));
+                    command.push(RedisType::bulk_string(&count.to_string()));
+                }
+
+                command
+            }
+            Self::XREAD {
+                count,
+                block,
+                streams,
+            } => {
+                let mut command = vec![RedisType::bulk_string("XREAD")];
+                if let Some(count) = count {
+                    command.push(RedisType::bulk_string("COUNT"));
+                    command.push(RedisType::bulk_string(&count.to_string()));
+                }
+                if let Some(block) = block {
+                    command.push(RedisType::bulk_string("BLOCK"));
+                    command.push(RedisType::bulk_string(&block.as_millis().to_string()));
+                }
+                command.push(RedisType::bulk_string("STREAMS"));
+                command.extend(streams.iter().map(|(key, _)| RedisType::bulk_string(key)));
+                command.extend(
+                    streams
+                        .iter()
+                        .map(|(_, id)| RedisType::bulk_string(&id.to_string())),
+                );
+
+                command
+            }
+            Self::XGROUP { arg } => match arg {
+                XGroupArgs::Create {
+                    key,
+                    group,
+                    id,
+                    mkstream,
+                } => {
+                    let mut command = vec![
+                        RedisType::bulk_string("XGROUP"),
+                        RedisType::bulk_string("CREATE"),
+                        RedisType::bulk_string(key),
+                        RedisType::bulk_string(group),
+                        RedisType::bulk_string(&id.to_string()),
+                    ];
+                    if *mkstream {
+                        command.push(RedisType::bulk_string("MKSTREAM"));
+                    }
+
+                    command
+                }
+            },
+            Self::XREADGROUP {
+                group,
+                consumer,
+                count,
+                streams,
+            } => {
+                let mut command = vec![
+                    RedisType::bulk_string("XREADGROUP"),
+                    RedisType::bulk_string("GROUP"),
+                    RedisType::bulk_string(group),
+                    RedisType::bulk_string(consumer),
+                ];
+                if let Some(count) = count {
+                    command.push(RedisType::bulk_string("COUNT"));
+                    command.push(RedisType::bulk_string(&count.to_string()));
+                }
+                command.push(RedisType::bulk_string("STREAMS"));
+                command.extend(streams.iter().map(|(key, _)| RedisType::bulk_string(key)));
+                command.extend(
+                    streams
+                        .iter()
+                        .map(|(_, id)| RedisType::bulk_string(&id.to_string())),
+                );
+
+                command
+            }
+            Self::XACK { key, group, ids } => {
+                let mut command = vec![
+                    RedisType::bulk_string("XACK"),
+                    RedisType::bulk_string(key),
+                    RedisType::bulk_string(group),
+                ];
+                command.extend(ids.iter().map(|id| RedisType::bulk_string(&id.to_string())));
+
+                command
+            }
+            Self::XINFO { arg } => {
+                let (subcommand, key) = match arg {
+                    XInfoArgs::Stream { key } => ("STREAM", key),
+                    XInfoArgs::Groups { key } => ("GROUPS", key),
+                };
+
+                vec![
+                    RedisType::bulk_string("XINFO"),
+                    RedisType::bulk_string(subcommand),
+                    RedisType::bulk_string(key),
+                ]
+            }
+            Self::PFADD { key, elements } => {
+                let mut command =
+                    vec![RedisType::bulk_string("PFADD"), RedisType::bulk_string(key)];
+                command.extend(
+                    elements
+                        .iter()
+                        .map(|element| RedisType::bulk_string(element)),
+                );
+
+                command
+            }
+            Self::PFCOUNT { keys } => {
+                let mut command = vec![RedisType::bulk_string("PFCOUNT")];
+                command.extend(keys.iter().map(|key| RedisType::bulk_string(key)));
+
+                command
+            }
+            Self::PFMERGE { dest, sources } => {
+                let mut command = vec![
+                    RedisType::bulk_string("PFMERGE"),
+                    RedisType::bulk_string(dest),
+                ];
+                command.extend(sources.iter().map(|source| RedisType::bulk_string(source)));
+
+                command
+            }
+            Self::GEOADD { key, members } => {
+                let mut command = vec![
+                    RedisType::bulk_string("GEOADD"),
+                    RedisType::bulk_string(key),
+                ];
+                for (point, member) in members {
+                    command.push(RedisType::bulk_string(&point.lon.to_string()));
+                    command.push(RedisType::bulk_string(&point.lat.to_string()));
+                    command.push(RedisType::bulk_string(member));
+                }
+
+                command
+            }
+            Self::GEOPOS { key, members } => {
+                let mut command = vec![
+                    RedisType::bulk_string("GEOPOS"),
+                    RedisType::bulk_string(key),
+                ];
+                command.extend(members.iter().map(|member| RedisType::bulk_string(member)));
+
+                command
+            }
+            Self::GEODIST {
+                key,
+                from,
+                to,
+                unit,
+            } => vec![
+                RedisType::bulk_string("GEODIST"),
+                RedisType::bulk_string(key),
+                RedisType::bulk_string(from),
+                RedisType::bulk_string(to),
+                RedisType::bulk_string(&unit.to_string()),
+            ],
+            Self::GEOSEARCH { key, from, radius } => {
+                let mut command = vec![
+                    RedisType::bulk_string("GEOSEARCH"),
+                    RedisType::bulk_string(key),
+                ];
+                match from {
+                    GeoSearchFrom::Member(member) => {
+                        command.push(RedisType::bulk_string("FROMMEMBER"));
+                        command.push(RedisType::bulk_string(member));
+                    }
+                    GeoSearchFrom::Point(point) => {
+                        command.push(RedisType::bulk_string("FROMLONLAT"));
+                        command.push(RedisType::bulk_string(&point.lon.to_string()));
+                        command.push(RedisType::bulk_string(&point.lat.to_string()));
+                    }
+                }
+                command.push(RedisType::bulk_string("BYRADIUS"));
+                command.push(RedisType::bulk_string(&radius.amount.to_string()));
+                command.push(RedisType::bulk_string(&radius.unit.to_string()));
+
+                command
+            }
+            Self::ZADD {
+                key,
+                options,
+                members,
+            } => {
+                let mut command = vec![RedisType::bulk_string("ZADD"), RedisType::bulk_string(key)];
+                match options.condition {
+                    Some(Condition::Nx) => command.push(RedisType::bulk_string("NX")),
+                    Some(Condition::Xx) => command.push(RedisType::bulk_string("XX")),
+                    None => {}
+                }
+                match options.comparison {
+                    Some(ScoreComparison::Gt) => command.push(RedisType::bulk_string("GT")),
+                    Some(ScoreComparison::Lt) => command.push(RedisType::bulk_string("LT")),
+                    None => {}
+                }
+                if options.changed {
+                    command.push(RedisType::bulk_string("CH"));
+                }
+                if options.incr {
+                    command.push(RedisType::bulk_string("INCR"));
+                }
+                for (score, member) in members {
+                    command.push(RedisType::bulk_string(&score.0.to_string()));
+                    command.push(RedisType::bulk_string(member));
+                }
+
+                command
+            }
+            Self::ZMPOP { keys, end, count } => {
+                let mut command = vec![RedisType::bulk_string("ZMPOP")];
+                command.extend(Self::write_zmpop_args(keys, *end, *count));
+
+                command
+            }
+            Self::BZMPOP {
+                timeout,
+                keys,
+                end,
+                count,
+            } => {
+                let mut command = vec![
+                    RedisType::bulk_string("BZMPOP"),
+                    RedisType::bulk_string(&timeout.as_secs_f64().to_string()),
+                ];
+                command.extend(Self::write_zmpop_args(keys, *end, *count));
+
+                command
+            }
+            Self::OBJECT { arg } => match arg {
+                ObjectArgs::Encoding { key } => vec![
+                    RedisType::bulk_string("OBJECT"),
+                    RedisType::bulk_string("ENCODING"),
+                    RedisType::bulk_string(key),
+                ],
+            },
+            Self::TYPE { key } => vec![RedisType::bulk_string("TYPE"), RedisType::bulk_string(key)],
+            Self::DEL { keys } => {
+                let mut command = vec![RedisType::bulk_string("DEL")];
+                command.extend(keys.iter().map(|key| RedisType::bulk_string(key)));
+
+                command
+            }
+            Self::EXISTS { keys } => {
+                let mut command = vec![RedisType::bulk_string("EXISTS")];
+                command.extend(keys.iter().map(|key| RedisType::bulk_string(key)));
+
+                command
+            }
+            Self::REPLICAOF { master } => match master {
+                Some((host, port)) => vec![
+                    RedisType::bulk_string("REPLICAOF"),
+                    RedisType::bulk_string(host),
+                    RedisType::bulk_string(&port.to_string()),
+                ],
+                None => vec![
+                    RedisType::bulk_string("REPLICAOF"),
+                    RedisType::bulk_string("NO"),
+                    RedisType::bulk_string("ONE"),
+                ],
+            },
+        };
+
+        RedisType::list(parts).write_as_protocol()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ReplConfArgs {
+    Port(u16),
+    Capabilities(Vec<String>),
+    GetAck(String),
+    Ack(i64),
+    // Options we don't act on, name first, which are acknowledged so newer peers still work
+    Other(Vec<String>),
+}
+
+/// Expiry options shared by every command that can set or clear a key's TTL.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExpiryOption {
+    // EX and PX
+    After(Duration),
+    // EXAT and PXAT
+    At(SystemTime),
+    Persist,
+    KeepTtl,
+}
+
+impl ExpiryOption {
+    /// Parses the option starting at `data[0]`, returning it along with how many arguments it
+    /// took. `Invalid` when it isn't an expiry option, and the reply Redis gives when its
+    /// argument is missing, not an integer, or not a time that can be set. SET is the only
+    /// command taking these so far, so the reply names it.
+    pub fn parse(data: &[Box<RedisType>]) -> Result<(Self, usize), ParseError> {
+        let name = RedisCommand::string_arg(data, 0)?.to_uppercase();
+        // In milliseconds, which like in Redis have to fit in 64 bits, even once added to now
+        let amount = |unit: i64| -> Result<Duration, ParseError> {
+            if data.len() < 2 {
+                return Err(ParseError::Reply(SYNTAX_ERROR));
+            }
+            let amount: i64 = RedisCommand::int_arg(data, 1)?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64;
+
+            match amount.checked_mul(unit) {
+                Some(ms) if ms > 0 && ms.checked_add(now).is_some() => {
+                    Ok(Duration::from_millis(ms as u64))
+                }
+                _ => Err(ParseError::Reply(
+                    "ERR invalid expire time in 'set' command",
+                )),
+            }
+        };
+
+        let option = match name.as_str() {
+            "EX" => (Self::After(amount(1000)?), 2),
+            "PX" => (Self::After(amount(1)?), 2),
+            "EXAT" => (Self::At(UNIX_EPOCH + amount(1000)?), 2),
+            "PXAT" => (Self::At(UNIX_EPOCH + amount(1)?), 2),
+            "PERSIST" => (Self::Persist, 1),
+            "KEEPTTL" => (Self::KeepTtl, 1),
+            _ => return Err(ParseError::Invalid),
+        };
+
+        Ok(option)
+    }
+
+    fn write_args(&self) -> Vec<RedisType> {
+        match self {
+            Self::After(ttl) => vec![
+                RedisType::bulk_string("PX"),
+                RedisType::bulk_string(&ttl.as_millis().to_string()),
+            ],
+            Self::At(time) => vec![
+                RedisType::bulk_string("PXAT"),
+                RedisType::bulk_string(
+                    &time
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis()
+                        .to_string(),
+                ),
+            ],
+            Self::Persist => vec![RedisType::bulk_string("PERSIST")],
+            Self::KeepTtl => vec![RedisType::bulk_string("KEEPTTL")],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CommandArgs {
+    // The command to inspect, name included
+    GetKeysAndFlags(Vec<String>),
+    // No names means every command
+    Docs(Vec<String>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MemoryArgs {
+    // SAMPLES is how many elements of a container to look at, 0 meaning all of them
+    Usage { key: String, samples: Option<usize> },
+    Stats,
+    Doctor,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConfigArgs {
+    ResetStat,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ObjectArgs {
+    Encoding { key: String },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum XGroupArgs {
+    Create {
+        key: String,
+        group: String,
+        id: XReadId,
+        // Create an empty stream if the key doesn't exist
+        mkstream: bool,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum XInfoArgs {
+    Stream { key: String },
+    Groups { key: String },
+}
+
+/// NX and XX: only acting on what doesn't exist yet, or only on what already does.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Condition {
+    Nx,
+    Xx,
+}
+
+/// GT and LT: only updating a score to a greater one, or to a lesser one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ScoreComparison {
+    Gt,
+    Lt,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ZAddOptions {
+    pub condition: Option<Condition>,
+    pub comparison: Option<ScoreComparison>,
+    // Count updated members in the reply, not only added ones
+    pub changed: bool,
+    // Add the score to the member's instead, replying with the new score
+    pub incr: bool,
+}
+
+/// Where a GEOSEARCH is centered: on a member of the set, or on given coordinates.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum GeoSearchFrom {
+    Member(String),
+    Point(Point),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DebugArgs {
+    StringMatchLen { pattern: String, string: String },
+    // Turned off, keys are only deleted once a command runs into them expired
+    SetActiveExpire(bool),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::parse_frames;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_ping() {
+        let data = RedisType::SimpleString {
+            data: "PING".to_string(),
+        };
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Ok(RedisCommand::PING));
+
+        let data = RedisType::list(vec![RedisType::bulk_string("Ping")]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Ok(RedisCommand::PING));
+    }
+
+    #[test]
+    fn test_parse_echo() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("echo"),
+            RedisType::bulk_string("hello"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Ok(RedisCommand::ECHO("hello".to_string())));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("invalid"),
+            RedisType::bulk_string("world"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let data = RedisType::SimpleString {
+            data: "".to_string(),
+        };
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_set_command() {
+        let set = RedisType::list(vec![
+            RedisType::bulk_string("SET"),
+            RedisType::bulk_string("mykey"),
+            RedisType::bulk_string("myvalue"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&set),
+            Ok(RedisCommand::SET {
+                key: "mykey".to_string(),
+                val: RedisType::bulk_string("myvalue"),
+                expiry: None,
+                condition: None,
+            })
+        );
+
+        let set_with_expiry = RedisType::list(vec![
+            RedisType::bulk_string("SET"),
+            RedisType::bulk_string("MyKeyTwo"),
+            RedisType::bulk_string("OtherValue"),
+            RedisType::bulk_string("px"),
+            RedisType::bulk_string("200"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&set_with_expiry),
+            Ok(RedisCommand::SET {
+                key: "MyKeyTwo".to_string(),
+                val: RedisType::bulk_string("OtherValue"),
+
+                expiry: Some(ExpiryOption::After(Duration::from_millis(200))),
+                condition: None,
+            })
+        );
+
+        let set_if_missing = RedisType::list(vec![
+            RedisType::bulk_string("SET"),
+            RedisType::bulk_string("mykey"),
+            RedisType::bulk_string("myvalue"),
+            RedisType::bulk_string("ex"),
+            RedisType::bulk_string("10"),
+            RedisType::bulk_string("nx"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&set_if_missing),
+            Ok(RedisCommand::SET {
+                key: "mykey".to_string(),
+                val: RedisType::bulk_string("myvalue"),
+                expiry: Some(ExpiryOption::After(Duration::from_secs(10))),
+                condition: Some(Condition::Nx),
+            })
+        );
+    }
+
+    #[test]
+    fn test_get_command() {
+        let get = RedisType::list(vec![
+            RedisType::bulk_string("GET"),
+            RedisType::bulk_string("mykey"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&get),
+            Ok(RedisCommand::GET {
+                key: "mykey".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_append_command() {
+        let append = RedisType::list(vec![
+            RedisType::bulk_string("APPEND"),
+            RedisType::bulk_string("mykey"),
+            RedisType::bulk_string(" world"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&append),
+            Ok(RedisCommand::APPEND {
+                key: "mykey".to_string(),
+                value: b" world".to_vec()
+            })
+        );
+
+        // Values don't have to be UTF-8, and go back out as they came in
+        let binary = RedisType::list(vec![
+            RedisType::bulk_string("APPEND"),
+            RedisType::bulk_string("mykey"),
+            RedisType::bulk_bytes(&[0xff, 0x00, 0xfe]),
+        ]);
+        let parsed = RedisCommand::parse(&binary).unwrap();
+        assert_eq!(
+            parsed,
+            RedisCommand::APPEND {
+                key: "mykey".to_string(),
+                value: vec![0xff, 0x00, 0xfe]
+            }
+        );
+        assert_eq!(parsed.write_as_protocol(), binary.write_as_protocol());
+
+        let missing_value = RedisType::list(vec![
+            RedisType::bulk_string("APPEND"),
+            RedisType::bulk_string("mykey"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&missing_value),
+            Err(ParseError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_getrange_command() {
+        let getrange = |name: &str, start: &str, end: &str| {
+            RedisCommand::parse(&RedisType::list(vec![
+                RedisType::bulk_string(name),
+                RedisType::bulk_string("mykey"),
+                RedisType::bulk_string(start),
+                RedisType::bulk_string(end),
+            ]))
+        };
+        let expected = Ok(RedisCommand::GETRANGE {
+            key: "mykey".to_string(),
+            start: -3,
+            end: -1,
+        });
+
+        assert_eq!(getrange("GETRANGE", "-3", "-1"), expected);
+        assert_eq!(getrange("substr", "-3", "-1"), expected);
+        assert_eq!(
+            getrange("GETRANGE", "a", "-1"),
+            Err(ParseError::Reply(INTEGER_ERROR))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incr_and_decr_commands() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
+
+        let incr = parse(&["INCR", "counter"]).unwrap();
+        assert_eq!(
+            incr,
+            RedisCommand::INCR {
+                key: "counter".to_string()
+            }
+        );
+        let decr = parse(&["decr", "counter"]).unwrap();
+        assert_eq!(
+            decr,
+            RedisCommand::DECR {
+                key: "counter".to_string()
+            }
+        );
+        assert_eq!(parse(&["INCR"]), Err(ParseError::Invalid));
+        assert_eq!(parse(&["DECR", "a", "b"]), Err(ParseError::Invalid));
+
+        let incrby = parse(&["INCRBY", "counter", "5"]).unwrap();
+        assert_eq!(
+            incrby,
+            RedisCommand::INCRBY {
+                key: "counter".to_string(),
+                delta: 5
+            }
+        );
+        let decrby = parse(&["decrby", "counter", "-9223372036854775808"]).unwrap();
+        assert_eq!(
+            decrby,
+            RedisCommand::DECRBY {
+                key: "counter".to_string(),
+                delta: i64::MIN
+            }
+        );
+        for delta in ["1.5", "abc", "+5", "9223372036854775808"] {
+            assert_eq!(
+                parse(&["INCRBY", "counter", delta]),
+                Err(ParseError::Reply(INTEGER_ERROR))
+            );
+            assert_eq!(
+                parse(&["DECRBY", "counter", delta]),
+                Err(ParseError::Reply(INTEGER_ERROR))
+            );
+        }
+        assert_eq!(parse(&["DECRBY", "counter"]), Err(ParseError::Invalid));
+
+        for command in [incr, decr, incrby, decrby] {
+            let (frames, _) = parse_frames(&command.write_as_protocol()).await;
+            assert_eq!(RedisCommand::parse(&frames[0]), Ok(command));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_failover() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
+
+        let failover = RedisCommand::FAILOVER {
+            target: Some(("127.0.0.1".to_string(), 6380)),
+            force: true,
+            abort: false,
+            timeout: Some(500),
+        };
+        assert_eq!(
+            parse(&[
+                "FAILOVER",
+                "to",
+                "127.0.0.1",
+                "6380",
+                "timeout",
+                "500",
+                "FORCE"
+            ]),
+            Ok(failover.clone())
+        );
+        let (frames, _) = parse_frames(&failover.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(failover));
+
+        assert_eq!(
+            parse(&["FAILOVER", "ABORT"]),
+            Ok(RedisCommand::FAILOVER {
+                target: None,
+                force: false,
+                abort: true,
+                timeout: None
+            })
+        );
+        assert_eq!(
+            RedisCommand::parse(&RedisType::bulk_string("failover")),
+            Ok(RedisCommand::FAILOVER {
+                target: None,
+                force: false,
+                abort: false,
+                timeout: None
+            })
+        );
+
+        assert_eq!(
+            parse(&["FAILOVER", "TO", "127.0.0.1"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(
+            parse(&["FAILOVER", "TIMEOUT", "0"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(
+            parse(&["FAILOVER", "ABORT", "ABORT"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(parse(&["FAILOVER", "NOW"]), Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_parse_config_resetstat() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("CONFIG"),
+            RedisType::bulk_string("resetstat"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&data),
+            Ok(RedisCommand::CONFIG {
+                arg: ConfigArgs::ResetStat
+            })
+        );
+
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("CONFIG"),
+            RedisType::bulk_string("get"),
+            RedisType::bulk_string("port"),
+        ]);
+        assert_eq!(RedisCommand::parse(&data), Err(ParseError::Invalid));
     }
 
     #[test]
-    fn test_set_command() {
-        let set = RedisType::list(vec![
-            RedisType::bulk_string("SET"),
-            RedisType::bulk_string("mykey"),
-            RedisType::bulk_string("myvalue"),
+    fn test_info_command() {
+        let get = RedisType::list(vec![
+            RedisType::bulk_string("info"),
+            RedisType::bulk_string("replication"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&get),
+            Ok(RedisCommand::INFO {
+                sections: vec!["replication".to_string()]
+            })
+        );
+
+        let several = RedisType::list(vec![
+            RedisType::bulk_string("INFO"),
+            RedisType::bulk_string("replication"),
+            RedisType::bulk_string("server"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&several),
+            Ok(RedisCommand::INFO {
+                sections: vec!["replication".to_string(), "server".to_string()]
+            })
+        );
+
+        let bare = RedisType::list(vec![RedisType::bulk_string("INFO")]);
+        assert_eq!(
+            RedisCommand::parse(&bare),
+            Ok(RedisCommand::INFO { sections: vec![] })
+        );
+    }
+
+    #[test]
+    fn test_invalid_command() {
+        let invalid = RedisType::SimpleString {
+            data: "INVALID".to_string(),
+        };
+        assert_eq!(RedisCommand::parse(&invalid), Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_empty_list() {
+        let empty = RedisType::list(vec![]);
+        assert_eq!(RedisCommand::parse(&empty), Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_parse_replconf_port() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("replconf"),
+            RedisType::bulk_string("listening-port"),
+            RedisType::bulk_string("6379"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(
+            result,
+            Ok(RedisCommand::REPLCONF {
+                arg: ReplConfArgs::Port(6379)
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_replconf_capa() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("replconf"),
+            RedisType::bulk_string("capa"),
+            RedisType::bulk_string("psync2"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(
+            result,
+            Ok(RedisCommand::REPLCONF {
+                arg: ReplConfArgs::Capabilities(vec!["psync2".to_string()])
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_replconf_getack() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("REPLCONF"),
+            RedisType::bulk_string("GETACK"),
+            RedisType::bulk_string("*"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(
+            result,
+            Ok(RedisCommand::REPLCONF {
+                arg: ReplConfArgs::GetAck("*".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_replconf_ack() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("REPLCONF"),
+            RedisType::bulk_string("ACK"),
+            RedisType::bulk_string("31"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(
+            result,
+            Ok(RedisCommand::REPLCONF {
+                arg: ReplConfArgs::Ack(31)
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_replconf_unknown_option() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("REPLCONF"),
+            RedisType::bulk_string("ip-address"),
+            RedisType::bulk_string("10.0.0.1"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(
+            result,
+            Ok(RedisCommand::REPLCONF {
+                arg: ReplConfArgs::Other(vec!["ip-address".to_string(), "10.0.0.1".to_string()])
+            })
+        );
+        assert_eq!(
+            result.unwrap().write_as_protocol(),
+            data.write_as_protocol()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_replconf() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("replconf"),
+            RedisType::bulk_string("unknown"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Err(ParseError::Invalid));
+
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("replconf"),
+            RedisType::bulk_string("listening-port"),
+            RedisType::bulk_string("invalid-port"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Err(ParseError::Reply(INTEGER_ERROR)));
+    }
+
+    #[test]
+    fn test_parse_psync() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("PSYNC"),
+            RedisType::bulk_string("?"),
+            RedisType::bulk_string("-1"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Ok(RedisCommand::psync_from_scrath()));
+
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("PSYNC"),
+            RedisType::bulk_string("4324fdsgbfdh235"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(result, Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_parse_debug_stringmatch_len() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("DEBUG"),
+            RedisType::bulk_string("stringmatch-len"),
+            RedisType::bulk_string("h*o"),
+            RedisType::bulk_string("hello"),
+        ]);
+
+        let result = RedisCommand::parse(&data);
+        assert_eq!(
+            result,
+            Ok(RedisCommand::DEBUG {
+                arg: DebugArgs::StringMatchLen {
+                    pattern: "h*o".to_string(),
+                    string: "hello".to_string()
+                }
+            })
+        );
+
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("DEBUG"),
+            RedisType::bulk_string("stringmatch-len"),
+            RedisType::bulk_string("h*o"),
+        ]);
+        assert_eq!(RedisCommand::parse(&data), Err(ParseError::Invalid));
+    }
+
+    #[tokio::test]
+    async fn test_parse_debug_set_active_expire() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
+
+        let disable = parse(&["DEBUG", "set-active-expire", "0"]).unwrap();
+        assert_eq!(
+            disable,
+            RedisCommand::DEBUG {
+                arg: DebugArgs::SetActiveExpire(false)
+            }
+        );
+        assert_eq!(
+            parse(&["debug", "SET-ACTIVE-EXPIRE", "2"]),
+            Ok(RedisCommand::DEBUG {
+                arg: DebugArgs::SetActiveExpire(true)
+            })
+        );
+        assert_eq!(
+            parse(&["DEBUG", "set-active-expire", "no"]),
+            Err(ParseError::Reply(INTEGER_ERROR))
+        );
+        assert_eq!(
+            parse(&["DEBUG", "set-active-expire"]),
+            Err(ParseError::Invalid)
+        );
+
+        let (frames, _) = parse_frames(&disable.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(disable));
+    }
+
+    #[test]
+    fn test_parse_replicaof() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("REPLICAOF"),
+            RedisType::bulk_string("localhost"),
+            RedisType::bulk_string("6380"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&data),
+            Ok(RedisCommand::REPLICAOF {
+                master: Some(("localhost".to_string(), 6380))
+            })
+        );
+
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("slaveof"),
+            RedisType::bulk_string("no"),
+            RedisType::bulk_string("one"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&data),
+            Ok(RedisCommand::REPLICAOF { master: None })
+        );
+
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("REPLICAOF"),
+            RedisType::bulk_string("localhost"),
+            RedisType::bulk_string("port"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&data),
+            Err(ParseError::Reply(INTEGER_ERROR))
+        );
+    }
+
+    #[test]
+    fn test_parse_expiry_options() {
+        let parse = |args: &[&str]| {
+            let args: Vec<_> = args
+                .iter()
+                .map(|arg| Box::new(RedisType::bulk_string(arg)))
+                .collect();
+            ExpiryOption::parse(&args)
+        };
+
+        assert_eq!(
+            parse(&["ex", "10"]),
+            Ok((ExpiryOption::After(Duration::from_secs(10)), 2))
+        );
+        assert_eq!(
+            parse(&["PX", "1500", "NX"]),
+            Ok((ExpiryOption::After(Duration::from_millis(1500)), 2))
+        );
+        assert_eq!(
+            parse(&["EXAT", "1700000000"]),
+            Ok((
+                ExpiryOption::At(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+                2
+            ))
+        );
+        assert_eq!(
+            parse(&["pxat", "1700000000123"]),
+            Ok((
+                ExpiryOption::At(UNIX_EPOCH + Duration::from_millis(1_700_000_000_123)),
+                2
+            ))
+        );
+        assert_eq!(parse(&["PERSIST"]), Ok((ExpiryOption::Persist, 1)));
+        assert_eq!(parse(&["KEEPTTL"]), Ok((ExpiryOption::KeepTtl, 1)));
+
+        let invalid_time = Err(ParseError::Reply(
+            "ERR invalid expire time in 'set' command",
+        ));
+        assert_eq!(parse(&["EX"]), Err(ParseError::Reply(SYNTAX_ERROR)));
+        assert_eq!(parse(&["EX", "0"]), invalid_time);
+        assert_eq!(parse(&["PX", "-5"]), invalid_time);
+        assert_eq!(parse(&["EX", "9223372036854775807"]), invalid_time);
+        assert_eq!(parse(&["PXAT", "9223372036854775807"]), invalid_time);
+        assert_eq!(
+            parse(&["EXAT", "soon"]),
+            Err(ParseError::Reply(INTEGER_ERROR))
+        );
+        assert_eq!(parse(&["EX", "1.5"]), Err(ParseError::Reply(INTEGER_ERROR)));
+        assert_eq!(
+            parse(&["PX", "+100"]),
+            Err(ParseError::Reply(INTEGER_ERROR))
+        );
+        assert_eq!(parse(&["NX"]), Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_set_command_invalid_options() {
+        for options in [
+            vec!["px"],
+            vec!["persist"],
+            vec!["bogus"],
+            // Contradicting options
+            vec!["nx", "xx"],
+            vec!["XX", "EX", "10", "NX"],
+            vec!["NX", "nx"],
+            vec!["ex", "10", "px", "100"],
+            vec!["ex", "10", "keepttl"],
+            vec!["keepttl", "pxat", "100"],
+        ] {
+            let mut set = vec![
+                RedisType::bulk_string("SET"),
+                RedisType::bulk_string("key"),
+                RedisType::bulk_string("value"),
+            ];
+            set.extend(options.iter().map(|option| RedisType::bulk_string(option)));
+
+            assert_eq!(
+                RedisCommand::parse(&RedisType::list(set)),
+                Err(ParseError::Reply("ERR syntax error")),
+                "{:?}",
+                options
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_command_invalid_expire_time() {
+        for (options, error) in [
+            (vec!["ex", "1.5"], INTEGER_ERROR),
+            (vec!["px", "abc"], INTEGER_ERROR),
+            (vec!["ex", "0"], "ERR invalid expire time in 'set' command"),
+            (vec!["EX", "-5"], "ERR invalid expire time in 'set' command"),
+        ] {
+            let mut set = vec![
+                RedisType::bulk_string("SET"),
+                RedisType::bulk_string("key"),
+                RedisType::bulk_string("value"),
+            ];
+            set.extend(options.iter().map(|option| RedisType::bulk_string(option)));
+
+            assert_eq!(
+                RedisCommand::parse(&RedisType::list(set)),
+                Err(ParseError::Reply(error)),
+                "{:?}",
+                options
+            );
+        }
+    }
+
+    #[test]
+    fn test_numeric_arguments_reply_with_the_integer_error() {
+        for args in [
+            vec!["GETRANGE", "key", "a", "5"],
+            vec!["GETRANGE", "key", "0", "1.5"],
+            vec!["XRANGE", "stream", "-", "+", "COUNT", "many"],
+            vec!["XREAD", "COUNT", "x", "STREAMS", "stream", "0"],
+            vec!["XREAD", "BLOCK", "-1", "STREAMS", "stream", "0"],
+            vec!["ZMPOP", "1", "zset", "MIN", "COUNT", "x"],
+            vec!["ZMPOP", "one", "zset", "MIN"],
+            vec!["MEMORY", "USAGE", "key", "SAMPLES", "all"],
+        ] {
+            let data =
+                RedisType::list(args.iter().map(|arg| RedisType::bulk_string(arg)).collect());
+
+            assert_eq!(
+                RedisCommand::parse(&data),
+                Err(ParseError::Reply(INTEGER_ERROR)),
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_command_writes_expiry() {
+        let set = RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry: Some(ExpiryOption::At(UNIX_EPOCH + Duration::from_millis(1234))),
+            condition: None,
+        };
+
+        let (frames, _) = parse_frames(&set.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(set));
+
+        let set = RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry: Some(ExpiryOption::KeepTtl),
+            condition: Some(Condition::Xx),
+        };
+        let (frames, _) = parse_frames(&set.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(set));
+    }
+
+    #[test]
+    fn test_command_table_covers_every_command() {
+        let commands = vec![
+            RedisCommand::PING,
+            RedisCommand::ECHO("hi".to_string()),
+            RedisCommand::SET {
+                key: "key".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: None,
+                condition: None,
+            },
+            RedisCommand::GET {
+                key: "key".to_string(),
+            },
+            RedisCommand::APPEND {
+                key: "key".to_string(),
+                value: b"value".to_vec(),
+            },
+            RedisCommand::GETRANGE {
+                key: "key".to_string(),
+                start: 0,
+                end: -1,
+            },
+            RedisCommand::INCR {
+                key: "key".to_string(),
+            },
+            RedisCommand::DECR {
+                key: "key".to_string(),
+            },
+            RedisCommand::INCRBY {
+                key: "key".to_string(),
+                delta: 5,
+            },
+            RedisCommand::DECRBY {
+                key: "key".to_string(),
+                delta: 5,
+            },
+            RedisCommand::INFO {
+                sections: vec!["replication".to_string()],
+            },
+            RedisCommand::default_capabilities(),
+            RedisCommand::psync_from_scrath(),
+            RedisCommand::DEBUG {
+                arg: DebugArgs::StringMatchLen {
+                    pattern: "*".to_string(),
+                    string: "a".to_string(),
+                },
+            },
+            RedisCommand::REPLICAOF { master: None },
+            RedisCommand::COMMAND {
+                arg: CommandArgs::GetKeysAndFlags(vec!["get".to_string(), "key".to_string()]),
+            },
+            RedisCommand::MEMORY {
+                arg: MemoryArgs::Usage {
+                    key: "key".to_string(),
+                    samples: None,
+                },
+            },
+            RedisCommand::DBSIZE,
+            RedisCommand::TIME,
+            RedisCommand::FAILOVER {
+                target: None,
+                force: false,
+                abort: true,
+                timeout: None,
+            },
+            RedisCommand::CONFIG {
+                arg: ConfigArgs::ResetStat,
+            },
+            RedisCommand::OBJECT {
+                arg: ObjectArgs::Encoding {
+                    key: "key".to_string(),
+                },
+            },
+            RedisCommand::TYPE {
+                key: "key".to_string(),
+            },
+            RedisCommand::DEL {
+                keys: vec!["key".to_string()],
+            },
+            RedisCommand::EXISTS {
+                keys: vec!["key".to_string()],
+            },
+            RedisCommand::XADD {
+                key: "stream".to_string(),
+                id: XAddId::Auto,
+                fields: vec![("field".to_string(), "value".to_string())],
+            },
+            RedisCommand::XLEN {
+                key: "stream".to_string(),
+            },
+            RedisCommand::XRANGE {
+                key: "stream".to_string(),
+                start: StreamId::MIN,
+                end: StreamId::MAX,
+                count: Some(10),
+            },
+            RedisCommand::XREAD {
+                count: None,
+                block: None,
+                streams: vec![("stream".to_string(), XReadId::Last)],
+            },
+            RedisCommand::XGROUP {
+                arg: XGroupArgs::Create {
+                    key: "stream".to_string(),
+                    group: "group".to_string(),
+                    id: XReadId::Last,
+                    mkstream: true,
+                },
+            },
+            RedisCommand::XREADGROUP {
+                group: "group".to_string(),
+                consumer: "consumer".to_string(),
+                count: None,
+                streams: vec![("stream".to_string(), XReadGroupId::New)],
+            },
+            RedisCommand::XACK {
+                key: "stream".to_string(),
+                group: "group".to_string(),
+                ids: vec![StreamId::new(1, 0)],
+            },
+            RedisCommand::XINFO {
+                arg: XInfoArgs::Stream {
+                    key: "stream".to_string(),
+                },
+            },
+            RedisCommand::PFADD {
+                key: "hll".to_string(),
+                elements: vec!["a".to_string()],
+            },
+            RedisCommand::PFCOUNT {
+                keys: vec!["hll".to_string()],
+            },
+            RedisCommand::PFMERGE {
+                dest: "hll".to_string(),
+                sources: vec!["other".to_string()],
+            },
+            RedisCommand::GEOADD {
+                key: "geo".to_string(),
+                members: vec![(Point { lon: 1.0, lat: 2.0 }, "member".to_string())],
+            },
+            RedisCommand::GEOPOS {
+                key: "geo".to_string(),
+                members: vec!["member".to_string()],
+            },
+            RedisCommand::GEODIST {
+                key: "geo".to_string(),
+                from: "a".to_string(),
+                to: "b".to_string(),
+                unit: GeoUnit::Meters,
+            },
+            RedisCommand::GEOSEARCH {
+                key: "geo".to_string(),
+                from: GeoSearchFrom::Member("member".to_string()),
+                radius: GeoDistance {
+                    amount: 1.0,
+                    unit: GeoUnit::Kilometers,
+                },
+            },
+            RedisCommand::ZADD {
+                key: "zset".to_string(),
+                options: ZAddOptions::default(),
+                members: vec![(Score(1.0), "member".to_string())],
+            },
+            RedisCommand::ZMPOP {
+                keys: vec!["zset".to_string()],
+                end: PopEnd::Min,
+                count: 1,
+            },
+            RedisCommand::BZMPOP {
+                timeout: Duration::ZERO,
+                keys: vec!["zset".to_string()],
+                end: PopEnd::Min,
+                count: 1,
+            },
+        ];
+
+        for spec in command_table::COMMAND_TABLE {
+            assert!(
+                commands.iter().any(|command| command.name() == spec.name),
+                "No sample command for {}",
+                spec.name
+            );
+        }
+
+        for command in &commands {
+            assert_eq!(command.is_write_command(), command.spec().write);
+        }
+
+        let writes: Vec<_> = commands
+            .iter()
+            .filter(|command| command.is_write_command())
+            .map(|command| command.name())
+            .collect();
+        assert_eq!(
+            writes,
+            vec![
+                "set",
+                "append",
+                "incr",
+                "decr",
+                "incrby",
+                "decrby",
+                "del",
+                "xadd",
+                "xgroup",
+                "xreadgroup",
+                "xack",
+                "pfadd",
+                "pfmerge",
+                "geoadd",
+                "zadd",
+                "zmpop",
+                "bzmpop"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_command_getkeysandflags() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("COMMAND"),
+            RedisType::bulk_string("getkeysandflags"),
+            RedisType::bulk_string("set"),
+            RedisType::bulk_string("key"),
+            RedisType::bulk_string("value"),
+        ]);
+        assert_eq!(
+            RedisCommand::parse(&data),
+            Ok(RedisCommand::COMMAND {
+                arg: CommandArgs::GetKeysAndFlags(vec![
+                    "set".to_string(),
+                    "key".to_string(),
+                    "value".to_string()
+                ])
+            })
+        );
+
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("COMMAND"),
+            RedisType::bulk_string("getkeysandflags"),
+        ]);
+        assert_eq!(RedisCommand::parse(&data), Err(ParseError::Invalid));
+    }
+
+    #[test]
+    fn test_parse_command_docs() {
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("COMMAND"),
+            RedisType::bulk_string("DOCS"),
+            RedisType::bulk_string("set"),
+            RedisType::bulk_string("get"),
         ]);
         assert_eq!(
-            RedisCommand::parse(&set),
-            Some(RedisCommand::SET {
-                key: "mykey".to_string(),
-                val: RedisType::bulk_string("myvalue"),
-                ttl: None
+            RedisCommand::parse(&data),
+            Ok(RedisCommand::COMMAND {
+                arg: CommandArgs::Docs(vec!["set".to_string(), "get".to_string()])
             })
         );
 
-        let set_with_expiry = RedisType::list(vec![
-            RedisType::bulk_string("SET"),
-            RedisType::bulk_string("MyKeyTwo"),
-            RedisType::bulk_string("OtherValue"),
-            RedisType::bulk_string("px"),
-            RedisType::bulk_string("200"),
+        let data = RedisType::list(vec![
+            RedisType::bulk_string("command"),
+            RedisType::bulk_string("docs"),
         ]);
         assert_eq!(
-            RedisCommand::parse(&set_with_expiry),
-            Some(RedisCommand::SET {
-                key: "MyKeyTwo".to_string(),
-                val: RedisType::bulk_string("OtherValue"),
-
-                ttl: Some(Duration::from_millis(200))
+            RedisCommand::parse(&data),
+            Ok(RedisCommand::COMMAND {
+                arg: CommandArgs::Docs(vec![])
             })
         );
     }
 
-    #[test]
-    fn test_get_command() {
-        let get = RedisType::list(vec![
-            RedisType::bulk_string("GET"),
-            RedisType::bulk_string("mykey"),
-        ]);
+    #[tokio::test]
+    async fn test_parse_stream_commands() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
+
         assert_eq!(
-            RedisCommand::parse(&get),
-            Some(RedisCommand::GET {
-                key: "mykey".to_string()
+            parse(&["XADD", "stream", "1-*", "a", "1", "b", "2"]),
+            Ok(RedisCommand::XADD {
+                key: "stream".to_string(),
+                id: XAddId::AutoSeq(1),
+                fields: vec![
+                    ("a".to_string(), "1".to_string()),
+                    ("b".to_string(), "2".to_string())
+                ],
             })
         );
-    }
+        assert_eq!(
+            parse(&["XADD", "stream", "*", "a"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(parse(&["XADD", "stream", "*"]), Err(ParseError::Invalid));
+        assert_eq!(
+            parse(&["XADD", "stream", "bad", "a", "1"]),
+            Err(ParseError::Invalid)
+        );
 
-    #[test]
-    fn test_info_command() {
-        let get = RedisType::list(vec![
-            RedisType::bulk_string("info"),
-            RedisType::bulk_string("replication"),
-        ]);
         assert_eq!(
-            RedisCommand::parse(&get),
-            Some(RedisCommand::INFO {
-                arg: "replication".to_string()
+            parse(&["xrange", "stream", "-", "5", "count", "2"]),
+            Ok(RedisCommand::XRANGE {
+                key: "stream".to_string(),
+                start: StreamId::MIN,
+                end: StreamId::new(5, u64::MAX),
+                count: Some(2),
+            })
+        );
+        assert_eq!(parse(&["XRANGE", "stream", "-"]), Err(ParseError::Invalid));
+        assert_eq!(
+            parse(&["XRANGE", "stream", "-", "+", "LIMIT", "2"]),
+            Err(ParseError::Invalid)
+        );
+
+        assert_eq!(
+            parse(&["XREAD", "COUNT", "2", "block", "100", "STREAMS", "a", "b", "$", "5"]),
+            Ok(RedisCommand::XREAD {
+                count: Some(2),
+                block: Some(Duration::from_millis(100)),
+                streams: vec![
+                    ("a".to_string(), XReadId::Last),
+                    ("b".to_string(), XReadId::After(StreamId::new(5, 0))),
+                ],
+            })
+        );
+        assert_eq!(
+            parse(&["XREAD", "STREAMS", "a", "b", "0"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(parse(&["XREAD", "STREAMS"]), Err(ParseError::Invalid));
+        assert_eq!(
+            parse(&["XREAD", "COUNT", "2", "a", "0"]),
+            Err(ParseError::Invalid)
+        );
+
+        assert_eq!(
+            parse(&["XGROUP", "create", "stream", "group", "$", "mkstream"]),
+            Ok(RedisCommand::XGROUP {
+                arg: XGroupArgs::Create {
+                    key: "stream".to_string(),
+                    group: "group".to_string(),
+                    id: XReadId::Last,
+                    mkstream: true,
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["XGROUP", "CREATE", "stream", "group"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(
+            parse(&["XGROUP", "CREATE", "stream", "group", "0", "NOPE"]),
+            Err(ParseError::Invalid)
+        );
+
+        assert_eq!(
+            parse(&[
+                "XREADGROUP",
+                "GROUP",
+                "g",
+                "c",
+                "COUNT",
+                "1",
+                "STREAMS",
+                "a",
+                "b",
+                ">",
+                "0"
+            ]),
+            Ok(RedisCommand::XREADGROUP {
+                group: "g".to_string(),
+                consumer: "c".to_string(),
+                count: Some(1),
+                streams: vec![
+                    ("a".to_string(), XReadGroupId::New),
+                    ("b".to_string(), XReadGroupId::Pending(StreamId::MIN)),
+                ],
+            })
+        );
+        assert_eq!(
+            parse(&["XREADGROUP", "g", "c", "STREAMS", "a", ">"]),
+            Err(ParseError::Invalid)
+        );
+
+        assert_eq!(
+            parse(&["XACK", "stream", "group", "1-1", "2"]),
+            Ok(RedisCommand::XACK {
+                key: "stream".to_string(),
+                group: "group".to_string(),
+                ids: vec![StreamId::new(1, 1), StreamId::new(2, 0)],
+            })
+        );
+        assert_eq!(
+            parse(&["XACK", "stream", "group"]),
+            Err(ParseError::Invalid)
+        );
+
+        assert_eq!(
+            parse(&["XINFO", "groups", "stream"]),
+            Ok(RedisCommand::XINFO {
+                arg: XInfoArgs::Groups {
+                    key: "stream".to_string(),
+                }
             })
         );
+        assert_eq!(
+            parse(&["XINFO", "STREAM", "stream", "FULL"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(
+            parse(&["XINFO", "CONSUMERS", "stream"]),
+            Err(ParseError::Invalid)
+        );
+
+        // Written commands parse back to the same thing
+        for command in [
+            parse(&["XADD", "stream", "*", "a", "1"]).unwrap(),
+            parse(&["XRANGE", "stream", "(1-1", "+"]).unwrap(),
+            parse(&["XREAD", "BLOCK", "0", "STREAMS", "a", "b", "$", "1-1"]).unwrap(),
+            parse(&["XGROUP", "CREATE", "stream", "group", "0", "MKSTREAM"]).unwrap(),
+            parse(&["XREADGROUP", "GROUP", "g", "c", "STREAMS", "a", ">"]).unwrap(),
+            parse(&["XACK", "stream", "group", "1-1"]).unwrap(),
+            parse(&["XINFO", "STREAM", "stream"]).unwrap(),
+            parse(&["XINFO", "GROUPS", "stream"]).unwrap(),
+        ] {
+            let (frames, _) = parse_frames(&command.write_as_protocol()).await;
+            assert_eq!(RedisCommand::parse(&frames[0]), Ok(command));
+        }
     }
 
-    #[test]
-    fn test_invalid_command() {
-        let invalid = RedisType::SimpleString {
-            data: "INVALID".to_string(),
+    #[tokio::test]
+    async fn test_parse_hyperloglog_commands() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
         };
-        assert_eq!(RedisCommand::parse(&invalid), None);
-    }
 
-    #[test]
-    fn test_empty_list() {
-        let empty = RedisType::list(vec![]);
-        assert_eq!(RedisCommand::parse(&empty), None);
+        assert_eq!(
+            parse(&["PFADD", "hll", "a", "b"]),
+            Ok(RedisCommand::PFADD {
+                key: "hll".to_string(),
+                elements: vec!["a".to_string(), "b".to_string()],
+            })
+        );
+        assert_eq!(
+            parse(&["pfadd", "hll"]),
+            Ok(RedisCommand::PFADD {
+                key: "hll".to_string(),
+                elements: vec![],
+            })
+        );
+        assert_eq!(
+            parse(&["PFCOUNT", "a", "b"]),
+            Ok(RedisCommand::PFCOUNT {
+                keys: vec!["a".to_string(), "b".to_string()],
+            })
+        );
+        assert_eq!(
+            parse(&["PFMERGE", "dest", "a", "b"]),
+            Ok(RedisCommand::PFMERGE {
+                dest: "dest".to_string(),
+                sources: vec!["a".to_string(), "b".to_string()],
+            })
+        );
+
+        for command in [
+            parse(&["PFADD", "hll", "a", "b"]).unwrap(),
+            parse(&["PFCOUNT", "a", "b"]).unwrap(),
+            parse(&["PFMERGE", "dest"]).unwrap(),
+        ] {
+            let (frames, _) = parse_frames(&command.write_as_protocol()).await;
+            assert_eq!(RedisCommand::parse(&frames[0]), Ok(command));
+        }
     }
 
-    #[test]
-    fn test_parse_replconf_port() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("replconf"),
-            RedisType::bulk_string("listening-port"),
-            RedisType::bulk_string("6379"),
-        ]);
+    #[tokio::test]
+    async fn test_parse_zadd() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
 
-        let result = RedisCommand::parse(&data);
         assert_eq!(
-            result,
-            Some(RedisCommand::REPLCONF {
-                arg: ReplConfArgs::Port(6379)
+            parse(&["ZADD", "zset", "xx", "GT", "ch", "1.5", "a", "+inf", "b"]),
+            Ok(RedisCommand::ZADD {
+                key: "zset".to_string(),
+                options: ZAddOptions {
+                    condition: Some(Condition::Xx),
+                    comparison: Some(ScoreComparison::Gt),
+                    changed: true,
+                    incr: false,
+                },
+                members: vec![
+                    (Score(1.5), "a".to_string()),
+                    (Score(f64::INFINITY), "b".to_string()),
+                ],
+            })
+        );
+        assert_eq!(
+            parse(&["ZADD", "zset", "INCR", "NX", "2", "nx"]),
+            Ok(RedisCommand::ZADD {
+                key: "zset".to_string(),
+                options: ZAddOptions {
+                    condition: Some(Condition::Nx),
+                    incr: true,
+                    ..Default::default()
+                },
+                // A member named like an option, after the score
+                members: vec![(Score(2.0), "nx".to_string())],
             })
         );
+
+        for score in ["nan", "one", "1,5"] {
+            assert_eq!(
+                parse(&["ZADD", "zset", "1", "a", score, "b"]),
+                Err(ParseError::Reply(FLOAT_ERROR)),
+                "{:?}",
+                score
+            );
+        }
+
+        for invalid in [
+            &["ZADD", "zset", "1"][..],
+            &["ZADD", "zset", "NX"],
+            &["ZADD", "zset", "NX", "XX"],
+        ] {
+            assert_eq!(parse(invalid), Err(ParseError::Invalid), "{:?}", invalid);
+        }
+        for (invalid, error) in [
+            (
+                &["ZADD", "zset", "NX", "XX", "1", "a"][..],
+                "ERR XX and NX options at the same time are not compatible",
+            ),
+            (
+                &["ZADD", "zset", "GT", "LT", "1", "a"],
+                "ERR GT, LT, and/or NX options at the same time are not compatible",
+            ),
+            (
+                &["ZADD", "zset", "NX", "GT", "1", "a"],
+                "ERR GT, LT, and/or NX options at the same time are not compatible",
+            ),
+            (
+                &["ZADD", "zset", "lt", "nx", "1", "a"],
+                "ERR GT, LT, and/or NX options at the same time are not compatible",
+            ),
+            (
+                &["ZADD", "zset", "INCR", "1", "a", "2", "b"],
+                "ERR INCR option supports a single increment-element pair",
+            ),
+        ] {
+            assert_eq!(
+                parse(invalid),
+                Err(ParseError::Reply(error)),
+                "{:?}",
+                invalid
+            );
+        }
+        // Repeating an option is fine
+        assert!(parse(&["ZADD", "zset", "nx", "NX", "1", "a"]).is_ok());
+
+        let zadd = parse(&["ZADD", "zset", "XX", "LT", "CH", "INCR", "-0.25", "a"]).unwrap();
+        let (frames, _) = parse_frames(&zadd.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(zadd));
     }
 
-    #[test]
-    fn test_parse_replconf_capa() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("replconf"),
-            RedisType::bulk_string("capa"),
-            RedisType::bulk_string("psync2"),
-        ]);
+    #[tokio::test]
+    async fn test_parse_zmpop() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
 
-        let result = RedisCommand::parse(&data);
         assert_eq!(
-            result,
-            Some(RedisCommand::REPLCONF {
-                arg: ReplConfArgs::Capabilities(vec!["psync2".to_string()])
+            parse(&["ZMPOP", "2", "a", "b", "max", "COUNT", "3"]),
+            Ok(RedisCommand::ZMPOP {
+                keys: vec!["a".to_string(), "b".to_string()],
+                end: PopEnd::Max,
+                count: 3,
+            })
+        );
+        assert_eq!(
+            parse(&["zmpop", "1", "a", "MIN"]),
+            Ok(RedisCommand::ZMPOP {
+                keys: vec!["a".to_string()],
+                end: PopEnd::Min,
+                count: 1,
             })
         );
+        for invalid in [
+            &["ZMPOP", "0", "MIN"][..],
+            &["ZMPOP", "2", "a", "MIN"],
+            &["ZMPOP", "1", "a", "LEFT"],
+            &["ZMPOP", "1", "a", "MIN", "COUNT", "0"],
+            &["ZMPOP", "1", "a", "MIN", "COUNT"],
+        ] {
+            assert_eq!(parse(invalid), Err(ParseError::Invalid), "{:?}", invalid);
+        }
+
+        assert_eq!(
+            parse(&["BZMPOP", "0.5", "1", "a", "MIN"]),
+            Ok(RedisCommand::BZMPOP {
+                timeout: Duration::from_millis(500),
+                keys: vec!["a".to_string()],
+                end: PopEnd::Min,
+                count: 1,
+            })
+        );
+        assert_eq!(
+            parse(&["BZMPOP", "-1", "1", "a", "MIN"]),
+            Err(ParseError::Reply("ERR timeout is negative"))
+        );
+        assert_eq!(
+            parse(&["BZMPOP", "soon", "1", "a", "MIN"]),
+            Err(ParseError::Reply(
+                "ERR timeout is not a float or out of range"
+            ))
+        );
+        for timeout in ["inf", "1e300", "1e19", "1e18"] {
+            assert_eq!(
+                parse(&["BZMPOP", timeout, "1", "a", "MIN"]),
+                Err(ParseError::Reply("ERR timeout is out of range")),
+                "{:?}",
+                timeout
+            );
+        }
+        // The longest timeout there is still parses
+        assert!(parse(&["BZMPOP", "9e15", "1", "a", "MIN"]).is_ok());
+        assert_eq!(
+            parse(&["BZMPOP", "1", "a", "MIN"]),
+            Err(ParseError::Reply(INTEGER_ERROR))
+        );
+
+        for command in [
+            parse(&["ZMPOP", "2", "a", "b", "MAX"]).unwrap(),
+            parse(&["BZMPOP", "1.5", "1", "a", "MIN", "COUNT", "2"]).unwrap(),
+        ] {
+            let (frames, _) = parse_frames(&command.write_as_protocol()).await;
+            assert_eq!(RedisCommand::parse(&frames[0]), Ok(command));
+        }
+
+        // Replicas pop right away instead of waiting
+        assert_eq!(
+            parse(&["BZMPOP", "0", "1", "a", "MAX"])
+                .unwrap()
+                .for_replication(&RedisType::NullArray),
+            Some(parse(&["ZMPOP", "1", "a", "MAX"]).unwrap())
+        );
     }
 
-    #[test]
-    fn test_parse_replconf_getack() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("REPLCONF"),
-            RedisType::bulk_string("GETACK"),
-            RedisType::bulk_string("*"),
-        ]);
+    #[tokio::test]
+    async fn test_parse_geo_commands() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
 
-        let result = RedisCommand::parse(&data);
         assert_eq!(
-            result,
-            Some(RedisCommand::REPLCONF {
-                arg: ReplConfArgs::GetAck("*".to_string())
+            parse(&["GEOADD", "geo", "13.5", "38", "a", "-1", "2.25", "b"]),
+            Ok(RedisCommand::GEOADD {
+                key: "geo".to_string(),
+                members: vec![
+                    (
+                        Point {
+                            lon: 13.5,
+                            lat: 38.0
+                        },
+                        "a".to_string()
+                    ),
+                    (
+                        Point {
+                            lon: -1.0,
+                            lat: 2.25
+                        },
+                        "b".to_string()
+                    ),
+                ],
+            })
+        );
+        assert_eq!(
+            parse(&["GEOADD", "geo", "13.5", "38"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(
+            parse(&["GEOADD", "geo", "east", "38", "a"]),
+            Err(ParseError::Invalid)
+        );
+
+        assert_eq!(
+            parse(&["GEODIST", "geo", "a", "b", "KM"]),
+            Ok(RedisCommand::GEODIST {
+                key: "geo".to_string(),
+                from: "a".to_string(),
+                to: "b".to_string(),
+                unit: GeoUnit::Kilometers,
+            })
+        );
+        assert_eq!(
+            parse(&["GEODIST", "geo", "a", "b", "yd"]),
+            Err(ParseError::Invalid)
+        );
+
+        assert_eq!(
+            parse(&[
+                "GEOSEARCH",
+                "geo",
+                "byradius",
+                "200",
+                "km",
+                "frommember",
+                "a"
+            ]),
+            Ok(RedisCommand::GEOSEARCH {
+                key: "geo".to_string(),
+                from: GeoSearchFrom::Member("a".to_string()),
+                radius: GeoDistance {
+                    amount: 200.0,
+                    unit: GeoUnit::Kilometers,
+                },
             })
         );
+        assert_eq!(
+            parse(&["GEOSEARCH", "geo", "FROMMEMBER", "a"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(
+            parse(&[
+                "GEOSEARCH",
+                "geo",
+                "FROMMEMBER",
+                "a",
+                "FROMLONLAT",
+                "1",
+                "2",
+                "BYRADIUS",
+                "1",
+                "m"
+            ]),
+            Err(ParseError::Invalid)
+        );
+
+        for command in [
+            parse(&["GEOADD", "geo", "13.361389", "38.115556", "a"]).unwrap(),
+            parse(&["GEOPOS", "geo", "a", "b"]).unwrap(),
+            parse(&["GEODIST", "geo", "a", "b"]).unwrap(),
+            parse(&[
+                "GEOSEARCH",
+                "geo",
+                "FROMLONLAT",
+                "15",
+                "37.5",
+                "BYRADIUS",
+                "0.5",
+                "mi",
+            ])
+            .unwrap(),
+        ] {
+            let (frames, _) = parse_frames(&command.write_as_protocol()).await;
+            assert_eq!(RedisCommand::parse(&frames[0]), Ok(command));
+        }
     }
 
     #[test]
-    fn test_parse_replconf_ack() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("REPLCONF"),
-            RedisType::bulk_string("ACK"),
-            RedisType::bulk_string("*"),
-        ]);
+    fn test_parse_object_encoding() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
 
-        let result = RedisCommand::parse(&data);
         assert_eq!(
-            result,
-            Some(RedisCommand::REPLCONF {
-                arg: ReplConfArgs::GetAck("*".to_string())
+            parse(&["OBJECT", "encoding", "key"]),
+            Ok(RedisCommand::OBJECT {
+                arg: ObjectArgs::Encoding {
+                    key: "key".to_string()
+                }
             })
         );
+        assert_eq!(parse(&["OBJECT", "ENCODING"]), Err(ParseError::Invalid));
+        assert_eq!(parse(&["OBJECT", "FREQ", "key"]), Err(ParseError::Invalid));
     }
 
-    #[test]
-    fn test_parse_invalid_replconf() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("replconf"),
-            RedisType::bulk_string("unknown"),
-        ]);
+    #[tokio::test]
+    async fn test_parse_key_commands() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, None);
+        let command = parse(&["type", "key"]).unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::TYPE {
+                key: "key".to_string()
+            }
+        );
+        assert_eq!(parse(&["TYPE"]), Err(ParseError::Invalid));
+        assert_eq!(parse(&["TYPE", "a", "b"]), Err(ParseError::Invalid));
 
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("replconf"),
-            RedisType::bulk_string("listening-port"),
-            RedisType::bulk_string("invalid-port"),
-        ]);
+        let del = parse(&["DEL", "a", "b"]).unwrap();
+        assert_eq!(
+            del,
+            RedisCommand::DEL {
+                keys: vec!["a".to_string(), "b".to_string()]
+            }
+        );
+        assert_eq!(parse(&["DEL"]), Err(ParseError::Invalid));
+        let (frames, _) = parse_frames(&del.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(del));
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, None);
+        // Repeated keys are kept, each one counts
+        let exists = parse(&["exists", "a", "a"]).unwrap();
+        assert_eq!(
+            exists,
+            RedisCommand::EXISTS {
+                keys: vec!["a".to_string(), "a".to_string()]
+            }
+        );
+        assert_eq!(parse(&["EXISTS"]), Err(ParseError::Invalid));
+        let (frames, _) = parse_frames(&exists.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(exists));
+
+        let (frames, _) = parse_frames(&command.write_as_protocol()).await;
+        assert_eq!(RedisCommand::parse(&frames[0]), Ok(command));
     }
 
     #[test]
-    fn test_parse_psync() {
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("PSYNC"),
-            RedisType::bulk_string("?"),
-            RedisType::bulk_string("-1"),
-        ]);
+    fn test_parse_memory_usage() {
+        let parse = |args: &[&str]| {
+            RedisCommand::parse(&RedisType::list(
+                args.iter().map(|arg| RedisType::bulk_string(arg)).collect(),
+            ))
+        };
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, Some(RedisCommand::psync_from_scrath()));
+        assert_eq!(
+            parse(&["MEMORY", "usage", "key"]),
+            Ok(RedisCommand::MEMORY {
+                arg: MemoryArgs::Usage {
+                    key: "key".to_string(),
+                    samples: None
+                }
+            })
+        );
+        assert_eq!(
+            parse(&["memory", "USAGE", "key", "samples", "0"]),
+            Ok(RedisCommand::MEMORY {
+                arg: MemoryArgs::Usage {
+                    key: "key".to_string(),
+                    samples: Some(0)
+                }
+            })
+        );
 
-        let data = RedisType::list(vec![
-            RedisType::bulk_string("PSYNC"),
-            RedisType::bulk_string("4324fdsgbfdh235"),
-        ]);
+        assert_eq!(parse(&["MEMORY", "USAGE"]), Err(ParseError::Invalid));
+        assert_eq!(
+            parse(&["MEMORY", "USAGE", "key", "SAMPLES"]),
+            Err(ParseError::Invalid)
+        );
+        assert_eq!(
+            parse(&["MEMORY", "USAGE", "key", "SAMPLES", "-1"]),
+            Err(ParseError::Reply(INTEGER_ERROR))
+        );
+        assert_eq!(
+            parse(&["MEMORY", "USAGE", "key", "BOGUS", "5"]),
+            Err(ParseError::Invalid)
+        );
 
-        let result = RedisCommand::parse(&data);
-        assert_eq!(result, None);
+        assert_eq!(
+            parse(&["memory", "stats"]),
+            Ok(RedisCommand::MEMORY {
+                arg: MemoryArgs::Stats
+            })
+        );
+        assert_eq!(
+            parse(&["MEMORY", "DOCTOR"]),
+            Ok(RedisCommand::MEMORY {
+                arg: MemoryArgs::Doctor
+            })
+        );
+        assert_eq!(
+            parse(&["MEMORY", "STATS", "extra"]),
+            Err(ParseError::Invalid)
+        );
     }
 }
@@ -2,64 +2,315 @@ use anyhow::Ok;
 use base64::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
+    future::Future,
     net::{IpAddr, SocketAddr},
-    sync::Arc,
-    time::Instant,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    task::Poll,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
     io::{AsyncWriteExt, WriteHalf},
-    net::TcpStream,
-    sync::Mutex,
+    net::{lookup_host, TcpStream},
+    sync::{Mutex, Notify, RwLockReadGuard, RwLockWriteGuard},
 };
 
 use crate::{
+    command_table,
+    geo::Point,
+    glob_pattern,
+    hyperloglog::HyperLogLog,
+    numeric_arg::{parse_int_arg, INTEGER_ERROR},
     rdb_file,
     redis_client::RedisClient,
-    redis_command::{RedisCommand, ReplConfArgs},
+    redis_command::{
+        CommandArgs, Condition, ConfigArgs, DebugArgs, ExpiryOption, GeoSearchFrom, MemoryArgs,
+        ObjectArgs, RedisCommand, ReplConfArgs, ScoreComparison, XGroupArgs, XInfoArgs,
+        ZAddOptions,
+    },
+    redis_stream::{Stream, StreamFields, StreamId, XReadGroupId, XReadId},
     redis_type::RedisType,
+    replication_backlog::ReplicationBacklog,
     server_config::ServerConfig,
+    server_stats::ServerStats,
+    sorted_set::{PopEnd, Score, SortedSet},
     RedisWritable,
 };
 
-#[derive(Debug)]
+/// What a key holds. Strings keep the protocol type they were written with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StoredValue {
+    String {
+        value: RedisType,
+        // Changed in place, which Redis always leaves raw encoded, even for an integer
+        raw: bool,
+    },
+    Stream(Stream),
+    SortedSet(SortedSet),
+}
+
+impl StoredValue {
+    /// A string value as written by a command, with its encoding following its contents.
+    fn string(value: RedisType) -> Self {
+        StoredValue::String { value, raw: false }
+    }
+
+    /// The name `TYPE` replies with. HyperLogLogs are strings and GEO sets are sorted sets, as in
+    /// Redis.
+    fn type_name(&self) -> &'static str {
+        match self {
+            StoredValue::String { .. } => "string",
+            StoredValue::Stream(_) => "stream",
+            StoredValue::SortedSet(_) => "zset",
+        }
+    }
+
+    /// The bytes of a string value, `None` for other kinds.
+    fn string_bytes(&self) -> Option<&[u8]> {
+        match self {
+            StoredValue::String { value, .. } => value.extract_bytes(),
+            _ => None,
+        }
+    }
+
+    /// The HyperLogLog a string value holds, for the PF commands.
+    fn hyperloglog(&self) -> Result<HyperLogLog, &'static str> {
+        match self.string_bytes() {
+            Some(bytes) => HyperLogLog::from_bytes(bytes),
+            None => Err(WRONGTYPE_ERROR),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 struct ValueWithExpiry {
-    value: RedisType,
+    value: StoredValue,
     expiry: Option<Instant>,
 }
 
+impl ValueWithExpiry {
+    fn is_expired(&self) -> bool {
+        self.expiry.is_some_and(|expiry| Instant::now() >= expiry)
+    }
+
+    fn apply_expiry(&mut self, option: &ExpiryOption) {
+        match option {
+            ExpiryOption::After(ttl) => self.expiry = Some(Instant::now() + *ttl),
+            ExpiryOption::At(time) => {
+                // Instants can't be built from wall clock times, so go through the time left
+                let now = Instant::now();
+                let left = time.duration_since(SystemTime::now()).unwrap_or_default();
+                self.expiry = Some(now + left);
+            }
+            ExpiryOption::Persist => self.expiry = None,
+            ExpiryOption::KeepTtl => {}
+        }
+    }
+
+    /// Approximate bytes used to hold this value under `key`, in the spirit of `MEMORY USAGE`.
+    fn memory_usage(&self, key: &str) -> usize {
+        let value_bytes = match &self.value {
+            StoredValue::String {
+                value: RedisType::BulkString { data } | RedisType::SimpleString { data },
+                ..
+            } => data.capacity(),
+            StoredValue::String {
+                value: RedisType::BulkBytes { data },
+                ..
+            } => data.capacity(),
+            StoredValue::String { value, .. } => value.write_as_protocol().len(),
+            StoredValue::Stream(stream) => stream.memory_usage(),
+            StoredValue::SortedSet(set) => set.memory_usage(),
+        };
+
+        ENTRY_OVERHEAD + key.len() + value_bytes
+    }
+
+    /// The encoding `OBJECT ENCODING` reports. Strings that read as a 64-bit integer, in
+    /// canonical form, are `int`. Short ones are `embstr` and anything longer is `raw`, as is
+    /// any string changed in place.
+    fn encoding(&self) -> &'static str {
+        let bytes = match &self.value {
+            StoredValue::String { raw: true, .. } => return "raw",
+            StoredValue::String { value, .. } => value.extract_bytes().unwrap_or_default(),
+            StoredValue::Stream(_) => return "stream",
+            // Small sets are kept in a single listpack in Redis, larger ones in a skiplist
+            StoredValue::SortedSet(set) => {
+                let small = set.len() <= ZSET_LISTPACK_ENTRIES
+                    && set
+                        .iter()
+                        .all(|(member, _)| member.len() <= ZSET_LISTPACK_VALUE);
+                return if small { "listpack" } else { "skiplist" };
+            }
+        };
+        let is_int =
+            std::str::from_utf8(bytes).is_ok_and(|string| parse_int_arg::<i64>(string).is_some());
+
+        if is_int {
+            "int"
+        } else if bytes.len() <= EMBSTR_SIZE_LIMIT {
+            "embstr"
+        } else {
+            "raw"
+        }
+    }
+}
+
+#[cfg(test)]
+impl RedisRuntime {
+    /// The encoding OBJECT ENCODING reports for `key`, for tests to check without building the
+    /// command and unwrapping its reply.
+    async fn encoding_of(&self, key: &str) -> Option<&'static str> {
+        self.read_values()
+            .await
+            .get(key)
+            .filter(|value| !value.is_expired())
+            .map(ValueWithExpiry::encoding)
+    }
+
+    /// Runs `command` as if the master propagated it.
+    async fn execute_from_master(&self, command: &RedisCommand) -> RedisType {
+        self.execute(command, None, true).await
+    }
+
+    /// The commands propagated to replicas since the last call, as replicas received them.
+    fn take_propagated(&self) -> Vec<RedisCommand> {
+        std::mem::take(&mut *self.propagated.lock().unwrap())
+    }
+}
+
+const WRONGTYPE_ERROR: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// Redis's `OBJ_ENCODING_EMBSTR_SIZE_LIMIT`, the longest string kept in the object's allocation
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+/// Redis's default `zset-max-listpack-entries` and `zset-max-listpack-value`, the largest sorted
+/// sets it keeps in a listpack
+const ZSET_LISTPACK_ENTRIES: usize = 128;
+const ZSET_LISTPACK_VALUE: usize = 64;
+
+// The map entry, the ValueWithExpiry itself and the String headers of the key and value
+const ENTRY_OVERHEAD: usize = 48 + std::mem::size_of::<ValueWithExpiry>();
+
+/// What a bare INFO returns. Commandstats is left out unless asked for, as in Redis
+const DEFAULT_INFO_SECTIONS: &[&str] = &["server", "replication", "stats", "errorstats"];
+const ALL_INFO_SECTIONS: &[&str] = &[
+    "server",
+    "replication",
+    "stats",
+    "commandstats",
+    "errorstats",
+];
+
 #[derive(Debug)]
 pub struct RedisRuntime {
     values: Arc<tokio::sync::RwLock<HashMap<String, ValueWithExpiry>>>,
+    // Keys with a TTL ordered by deadline, so active expiry only looks at keys that are due.
+    // Always locked after `values`
+    expiry_index: std::sync::Mutex<BTreeSet<(Instant, String)>>,
     config: ServerConfig,
-    replication_role: ReplicationRole,
-    replication_id: String,
-    replication_offset: u16,
+    stats: ServerStats,
+    replication: RwLock<ReplicationState>,
+    backlog: Mutex<ReplicationBacklog>,
+    role_changed: Notify,
+    // Wakes blocked XREADs whenever an entry is added to any stream
+    stream_added: Notify,
+    // Wakes blocked BZMPOPs whenever a member is added to any sorted set
+    sorted_set_added: Notify,
+    started_at: Instant,
+    // Set while a replica loads the RDB sent by its master
+    loading: AtomicBool,
+    // Cleared by DEBUG SET-ACTIVE-EXPIRE 0, leaving expired keys to be deleted lazily only
+    active_expire: AtomicBool,
+    // Every command as it was sent to replicas, for tests to check without a replica
+    #[cfg(test)]
+    propagated: std::sync::Mutex<Vec<RedisCommand>>,
 }
 
 impl RedisRuntime {
     pub fn new(server_config: ServerConfig) -> Self {
         Self {
             values: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
-            replication_role: server_config
-                .replica_addr
-                .map(|addr| ReplicationRole::Slave { replicaof: addr })
-                .unwrap_or_else(|| ReplicationRole::Master {
-                    replicas: Arc::new(Mutex::new(Vec::new())),
-                }),
-            replication_id: generate_alphanumeric_string(40),
-            replication_offset: 0,
+            expiry_index: std::sync::Mutex::new(BTreeSet::new()),
+            replication: RwLock::new(ReplicationState::new(server_config.replica_addr)),
+            backlog: Mutex::new(ReplicationBacklog::new(server_config.repl_backlog_size)),
+            role_changed: Notify::new(),
+            stream_added: Notify::new(),
+            sorted_set_added: Notify::new(),
+            started_at: Instant::now(),
+            loading: AtomicBool::new(false),
+            active_expire: AtomicBool::new(true),
+            #[cfg(test)]
+            propagated: std::sync::Mutex::new(Vec::new()),
+            stats: ServerStats::default(),
             config: server_config,
         }
     }
     pub async fn execute_no_conn(&self, command: &RedisCommand) -> RedisType {
-        self.execute(command, None).await
+        self.execute(command, None, false).await
     }
 
+    /// Runs `command`, recording it in the command stats. `from_master` is set for commands the
+    /// master propagated, which still see the keys it didn't delete yet even if they expired here.
     pub async fn execute(
         &self,
         command: &RedisCommand,
         connection: Option<(IpAddr, Arc<Mutex<WriteHalf<TcpStream>>>)>,
+        from_master: bool,
+    ) -> RedisType {
+        let started = Instant::now();
+        let result = self.execute_command(command, connection, from_master).await;
+
+        let error = match &result {
+            RedisType::SimpleError { message } => Some(message.as_str()),
+            _ => None,
+        };
+        self.stats
+            .record_call(command.name(), started.elapsed(), error);
+
+        result
+    }
+
+    /// Runs `command` like `execute`, so a panic while handling it becomes an error reply instead
+    /// of ending the connection. The keyspace locks are tokio's, which a panic releases without
+    /// poisoning, so other connections carry on as well.
+    pub async fn execute_isolated(
+        &self,
+        command: &RedisCommand,
+        connection: Option<(IpAddr, Arc<Mutex<WriteHalf<TcpStream>>>)>,
+        from_master: bool,
+    ) -> RedisType {
+        let started = Instant::now();
+
+        match isolate(self.execute(command, connection, from_master)).await {
+            Some(result) => result,
+            None => {
+                let message = format!("ERR Internal error while running '{}'", command.name());
+                self.stats
+                    .record_call(command.name(), started.elapsed(), Some(&message));
+
+                RedisType::simple_error(&message)
+            }
+        }
+    }
+
+    pub fn stats(&self) -> &ServerStats {
+        &self.stats
+    }
+
+    pub fn max_line_len(&self) -> usize {
+        self.config.proto_inline_max_size
+    }
+
+    async fn execute_command(
+        &self,
+        command: &RedisCommand,
+        connection: Option<(IpAddr, Arc<Mutex<WriteHalf<TcpStream>>>)>,
+        from_master: bool,
     ) -> RedisType {
         match command {
             RedisCommand::PING => RedisType::SimpleString {
@@ -68,54 +319,199 @@ impl RedisRuntime {
             RedisCommand::ECHO(payload) => RedisType::BulkString {
                 data: payload.clone(),
             },
-            RedisCommand::SET { key, val, ttl } => {
-                self.values.write().await.insert(
-                    key.clone(),
-                    ValueWithExpiry {
-                        value: val.clone(),
-                        expiry: ttl.map(|ttl| Instant::now() + ttl),
-                    },
+            RedisCommand::SET {
+                key,
+                val,
+                expiry,
+                condition,
+            } => {
+                let mut write_guard = self.write_values().await;
+                if !from_master
+                    && write_guard
+                        .get(key)
+                        .is_some_and(|existing| existing.is_expired())
+                {
+                    let removed = write_guard.remove(key).unwrap();
+                    self.track_expiry(key, removed.expiry, None);
+                    self.propagate_expired(key).await;
+                }
+                let existing = write_guard.get(key);
+
+                // The master's writes still see the key, as it exists there until its DEL arrives
+                let exists =
+                    existing.is_some_and(|existing| !existing.is_expired() || from_master);
+                match condition {
+                    Some(Condition::Nx) if exists => return RedisType::NullBulkString,
+                    Some(Condition::Xx) if !exists => return RedisType::NullBulkString,
+                    _ => {}
+                }
+                let mut entry = ValueWithExpiry {
+                    value: StoredValue::string(val.clone()),
+                    expiry: existing
+                        .filter(|existing| !existing.is_expired())
+                        .and_then(|existing| existing.expiry),
+                };
+                // Overwriting a key discards its TTL unless asked otherwise
+                entry.apply_expiry(expiry.as_ref().unwrap_or(&ExpiryOption::Persist));
+                self.track_expiry(
+                    key,
+                    existing.and_then(|existing| existing.expiry),
+                    entry.expiry,
                 );
+                write_guard.insert(key.clone(), entry);
 
                 RedisType::SimpleString {
                     data: "OK".to_string(),
                 }
             }
             RedisCommand::GET { key } => {
-                let read_guard = self.values.read().await;
+                let read_guard = self.read_values().await;
 
-                if let Some(val_with_expiry) = read_guard.get(key) {
-                    if let Some(expiry) = val_with_expiry.expiry {
-                        if Instant::now() > expiry {
+                let existing = self.get_live(&read_guard, key);
+                self.stats.record_keyspace_lookup(existing.is_some());
+
+                match existing.map(|existing| &existing.value) {
+                    Some(StoredValue::String { value, .. }) => value.clone(),
+                    Some(_) => RedisType::simple_error(WRONGTYPE_ERROR),
+                    None => {
+                        // Replicas wait for the master to propagate the deletion
+                        if read_guard.contains_key(key) && self.is_master() {
                             drop(read_guard);
-                            self.values.write().await.remove(key);
+                            self.remove_expired(key).await;
+                        }
 
-                            return RedisType::NullBulkString;
+                        RedisType::NullBulkString
+                    }
+                }
+            }
+            RedisCommand::APPEND { key, value } => {
+                self.with_value_mut(key, from_master, |existing| match existing {
+                    // Appending in place lets the String grow its capacity geometrically,
+                    // so many small APPENDs to one key stay linear instead of quadratic. An
+                    // integer is already held as its decimal digits, so they're what's extended
+                    Some(StoredValue::String { value: stored, raw })
+                        if stored.extract_bytes().is_some() =>
+                    {
+                        match stored {
+                            RedisType::BulkString { data } | RedisType::SimpleString { data } => {
+                                match std::str::from_utf8(value) {
+                                    std::result::Result::Ok(text) => data.push_str(text),
+                                    // Bytes that aren't UTF-8 make the whole value bytes
+                                    Err(_) => {
+                                        let mut bytes = std::mem::take(data).into_bytes();
+                                        bytes.extend_from_slice(value);
+                                        *stored = RedisType::BulkBytes { data: bytes };
+                                    }
+                                }
+                            }
+                            RedisType::BulkBytes { data } => data.extend_from_slice(value),
+                            _ => unreachable!("Only strings have bytes"),
                         }
+                        *raw = true;
+                        RedisType::integer(stored.extract_bytes().map_or(0, <[u8]>::len) as i64)
+                    }
+                    Some(_) => RedisType::simple_error(WRONGTYPE_ERROR),
+                    None => {
+                        *existing = Some(StoredValue::string(RedisType::bulk_bytes(value)));
+                        RedisType::integer(value.len() as i64)
                     }
-                    return val_with_expiry.value.clone();
+                })
+                .await
+            }
+            RedisCommand::INCR { key } => self.incr_by(key, 1, from_master).await,
+            RedisCommand::DECR { key } => self.incr_by(key, -1, from_master).await,
+            RedisCommand::INCRBY { key, delta } => self.incr_by(key, *delta, from_master).await,
+            // Negating the smallest i64 overflows before the value is even looked at
+            RedisCommand::DECRBY { key, delta } => match delta.checked_neg() {
+                Some(delta) => self.incr_by(key, delta, from_master).await,
+                None => RedisType::simple_error("ERR decrement would overflow"),
+            },
+            RedisCommand::GETRANGE { key, start, end } => {
+                let read_guard = self.read_values().await;
+
+                let existing = self.get_live(&read_guard, key);
+                self.stats.record_keyspace_lookup(existing.is_some());
+
+                match existing {
+                    Some(existing) => match existing.value.string_bytes() {
+                        // Ranges are over bytes, so they can split a multi-byte character
+                        Some(bytes) => {
+                            let range = normalize_range(*start, *end, bytes.len())
+                                .map(|(start, end)| &bytes[start..=end])
+                                .unwrap_or_default();
+
+                            RedisType::bulk_bytes(range)
+                        }
+                        None => RedisType::simple_error(WRONGTYPE_ERROR),
+                    },
+                    None => RedisType::bulk_string(""),
                 }
+            }
+            RedisCommand::XADD { key, id, fields } => {
+                self.with_value_mut(key, from_master, |existing| {
+                    let created = existing.is_none();
+                    let stream = match existing
+                        .get_or_insert_with(|| StoredValue::Stream(Stream::default()))
+                    {
+                        StoredValue::Stream(stream) => stream,
+                        _ => return RedisType::simple_error(WRONGTYPE_ERROR),
+                    };
 
-                RedisType::NullBulkString
+                    match stream.add(*id, fields.clone()) {
+                        std::result::Result::Ok(id) => {
+                            self.stream_added.notify_waiters();
+                            RedisType::bulk_string(&id.to_string())
+                        }
+                        Err(error) => {
+                            // A rejected entry doesn't leave an empty stream behind
+                            if created {
+                                *existing = None;
+                            }
+                            RedisType::simple_error(error)
+                        }
+                    }
+                })
+                .await
             }
-            RedisCommand::INFO { arg } => match arg.to_lowercase().as_str() {
-                "replication" => RedisType::BulkString {
-                    data: format!(
-                        "role:{}
-master_replid:{}
-master_repl_offset:{}",
-                        self.replication_role.type_str(),
-                        self.replication_id,
-                        self.replication_offset
+            RedisCommand::XLEN { key } => {
+                let read_guard = self.read_values().await;
+
+                let existing = self.get_live(&read_guard, key);
+                self.stats.record_keyspace_lookup(existing.is_some());
+
+                match existing.map(|existing| &existing.value) {
+                    Some(StoredValue::Stream(stream)) => RedisType::integer(stream.len() as i64),
+                    Some(_) => RedisType::simple_error(WRONGTYPE_ERROR),
+                    None => RedisType::integer(0),
+                }
+            }
+            RedisCommand::XRANGE {
+                key,
+                start,
+                end,
+                count,
+            } => {
+                let read_guard = self.read_values().await;
+
+                let existing = self.get_live(&read_guard, key);
+                self.stats.record_keyspace_lookup(existing.is_some());
+
+                match existing.map(|existing| &existing.value) {
+                    Some(StoredValue::Stream(stream)) => RedisType::list(
+                        stream
+                            .range(*start, *end, *count)
+                            .into_iter()
+                            .map(|(id, fields)| stream_entry(id, fields))
+                            .collect(),
                     ),
-                },
-                unknown => RedisType::SimpleError {
-                    message: format!("Unknown arg for INFO: {}", unknown),
-                },
-            },
+                    Some(_) => RedisType::simple_error(WRONGTYPE_ERROR),
+                    None => RedisType::list(vec![]),
+                }
+            }
+            RedisCommand::INFO { sections } => RedisType::bulk_string(&self.info(sections).await),
             RedisCommand::REPLCONF { arg } => match &arg {
-                ReplConfArgs::Port(port) => match &self.replication_role {
-                    ReplicationRole::Master { replicas } => {
+                ReplConfArgs::Port(port) => match self.replicas() {
+                    Some(replicas) => {
                         if let Some((peer_ip, connection)) = connection {
                             println!("Adding new replica at {}:{}", peer_ip, port);
 
@@ -123,13 +519,12 @@ master_repl_offset:{}",
                                 .lock()
                                 .await
                                 .push(Replica::new(connection, SocketAddr::new(peer_ip, *port)));
+                            self.backlog.lock().await.activate();
                         }
 
                         RedisType::simple_string("OK")
                     }
-                    ReplicationRole::Slave { .. } => {
-                        RedisType::simple_error("You can't sync with a replica")
-                    }
+                    None => RedisType::simple_error("You can't sync with a replica"),
                 },
                 ReplConfArgs::Capabilities(_) => RedisType::simple_string("OK"),
                 ReplConfArgs::GetAck(_) => {
@@ -140,14 +535,17 @@ master_repl_offset:{}",
                     }
                 }
                 ReplConfArgs::Ack(_) => RedisType::simple_string("OK"),
+                ReplConfArgs::Other(_) => RedisType::simple_string("OK"),
             },
             RedisCommand::PSYNC {
                 master_id,
                 master_offset,
             } => {
                 if master_id == "?" && *master_offset == -1 {
+                    let replication_id = self.replication.read().unwrap().id.clone();
+
                     RedisType::multiple(vec![
-                        RedisType::simple_string(&format!("FULLRESYNC {} 0", self.replication_id)),
+                        RedisType::simple_string(&format!("FULLRESYNC {} 0", replication_id)),
                         RedisType::RDBFile {
                             file: rdb_file::get_empty_rdb_decoded(),
                         },
@@ -156,331 +554,5049 @@ master_repl_offset:{}",
                     RedisType::simple_error("Not capable of syncing with those options")
                 }
             }
-        }
-    }
+            RedisCommand::REPLICAOF { master } => {
+                let replicaof = match master {
+                    Some((host, port)) => match lookup_host((host.as_str(), *port))
+                        .await
+                        .ok()
+                        .and_then(|mut addrs| addrs.next())
+                    {
+                        Some(addr) => Some(addr),
+                        None => {
+                            return RedisType::simple_error(&format!(
+                                "ERR Invalid master address {}:{}",
+                                host, port
+                            ))
+                        }
+                    },
+                    None => None,
+                };
 
-    pub async fn perform_handshake(&self) -> Result<Option<TcpStream>, anyhow::Error> {
-        match self.replication_role {
-            ReplicationRole::Master { .. } => Ok(None), // Do nothing
-            ReplicationRole::Slave { replicaof } => {
-                println!("Starting handshake with {}", replicaof);
-                let mut client = RedisClient::new(replicaof).await?;
+                if replicaof.is_some()
+                    && self.replication.read().unwrap().role.replicaof() == replicaof
+                {
+                    return RedisType::simple_string("OK Already connected to specified master");
+                }
 
-                println!("Sending PING");
-                let response = client.send_command(&RedisCommand::PING).await?;
-                response.expect_string("pong", "Unexpected return from ping")?;
+                if replicaof.is_some() || !self.is_master() {
+                    self.set_replication_role(replicaof).await;
+                }
 
-                println!("Sending REPLCONF port {}", self.config.port);
-                let response = client
-                    .send_command(&RedisCommand::REPLCONF {
-                        arg: ReplConfArgs::Port(self.config.port),
+                RedisType::simple_string("OK")
+            }
+            RedisCommand::COMMAND { arg } => match arg {
+                CommandArgs::GetKeysAndFlags(args) => Self::get_keys_and_flags(args),
+                CommandArgs::Docs(names) => Self::command_docs(names),
+            },
+            RedisCommand::FAILOVER {
+                target,
+                force,
+                abort,
+                timeout,
+            } => self.failover(target, *force, *abort, *timeout).await,
+            RedisCommand::MEMORY { arg } => match arg {
+                // Strings are measured whole, so there are no elements to sample
+                MemoryArgs::Usage { key, .. } => {
+                    let read_guard = self.read_values().await;
+
+                    match self.get_live(&read_guard, key) {
+                        Some(value) => RedisType::integer(value.memory_usage(key) as i64),
+                        None => RedisType::NullBulkString,
+                    }
+                }
+                MemoryArgs::Stats => {
+                    let stats = self.memory_stats().await;
+                    RedisType::list(
+                        stats
+                            .into_iter()
+                            .flat_map(|(name, value)| {
+                                [
+                                    RedisType::bulk_string(name),
+                                    RedisType::integer(value as i64),
+                                ]
+                            })
+                            .collect(),
+                    )
+                }
+                MemoryArgs::Doctor => RedisType::bulk_string(self.memory_doctor().await),
+            },
+            RedisCommand::XREAD {
+                count,
+                block,
+                streams,
+            } => self.xread(streams, *count, *block).await,
+            RedisCommand::XGROUP { arg } => match arg {
+                XGroupArgs::Create {
+                    key,
+                    group,
+                    id,
+                    mkstream,
+                } => {
+                    self.with_value_mut(key, from_master, |existing| {
+                        if existing.is_none() && *mkstream {
+                            *existing = Some(StoredValue::Stream(Stream::default()));
+                        }
+
+                        match existing {
+                            Some(StoredValue::Stream(stream)) => {
+                                let last_delivered = match id {
+                                    XReadId::Last => stream.last_id(),
+                                    XReadId::After(id) => *id,
+                                };
+
+                                match stream.create_group(group, last_delivered) {
+                                    std::result::Result::Ok(()) => RedisType::simple_string("OK"),
+                                    Err(error) => RedisType::simple_error(error),
+                                }
+                            }
+                            Some(_) => RedisType::simple_error(WRONGTYPE_ERROR),
+                            None => RedisType::simple_error(
+                                "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+                            ),
+                        }
                     })
-                    .await?;
-                response.expect_string("ok", "Unexpected return from REPLCONF port")?;
+                    .await
+                }
+            },
+            RedisCommand::XREADGROUP {
+                group,
+                consumer,
+                count,
+                streams,
+            } => {
+                self.xreadgroup(group, consumer, *count, streams, from_master)
+                    .await
+            }
+            RedisCommand::XACK { key, group, ids } => {
+                self.with_value_mut(key, from_master, |existing| match existing {
+                    Some(StoredValue::Stream(stream)) => {
+                        RedisType::integer(stream.ack(group, ids) as i64)
+                    }
+                    Some(_) => RedisType::simple_error(WRONGTYPE_ERROR),
+                    None => RedisType::integer(0),
+                })
+                .await
+            }
+            RedisCommand::XINFO { arg } => {
+                let key = match arg {
+                    XInfoArgs::Stream { key } | XInfoArgs::Groups { key } => key,
+                };
+                let read_guard = self.read_values().await;
 
-                println!("Sending REPLCONF capabilities");
-                let response = client
-                    .send_command(&RedisCommand::default_capabilities())
-                    .await?;
-                response.expect_string("ok", "Unexpected return from REPLCONF capabilities")?;
+                let existing = self.get_live(&read_guard, key);
+                self.stats.record_keyspace_lookup(existing.is_some());
 
-                println!("Sending PSYNC");
-                let response = client
-                    .send_command(&RedisCommand::psync_from_scrath())
-                    .await?;
-                self.handle_psync(&response, &mut client).await?;
+                match (arg, existing.map(|existing| &existing.value)) {
+                    (XInfoArgs::Stream { .. }, Some(StoredValue::Stream(stream))) => {
+                        stream_info(stream)
+                    }
+                    (XInfoArgs::Groups { .. }, Some(StoredValue::Stream(stream))) => {
+                        stream_groups_info(stream)
+                    }
+                    (_, Some(_)) => RedisType::simple_error(WRONGTYPE_ERROR),
+                    (_, None) => RedisType::simple_error("ERR no such key"),
+                }
+            }
+            RedisCommand::PFADD { key, elements } => {
+                self.with_value_mut(key, from_master, |existing| {
+                    let (mut hll, mut changed) = match existing {
+                        Some(value) => match value.hyperloglog() {
+                            std::result::Result::Ok(hll) => (hll, false),
+                            Err(error) => return RedisType::simple_error(error),
+                        },
+                        None => (HyperLogLog::default(), true),
+                    };
 
-                println!("Handshake successful. Ready to receive commands");
-                Ok(Some(client.buffer.into_inner()))
+                    for element in elements {
+                        changed |= hll.add(element.as_bytes());
+                    }
+                    if changed {
+                        *existing = Some(StoredValue::string(RedisType::bulk_bytes_owned(
+                            hll.into_bytes(),
+                        )));
+                    }
+
+                    RedisType::integer(changed as i64)
+                })
+                .await
             }
-        }
-    }
+            RedisCommand::PFCOUNT { keys } => {
+                let read_guard = self.read_values().await;
 
-    pub async fn replicate_command(&self, command: &RedisCommand) -> anyhow::Result<()> {
-        if !command.is_write_command() {
-            return Ok(());
-        }
+                let mut union = HyperLogLog::default();
+                for key in keys {
+                    let existing = self.get_live(&read_guard, key);
+                    self.stats.record_keyspace_lookup(existing.is_some());
 
-        if let ReplicationRole::Master { replicas } = &self.replication_role {
-            for replica in replicas.lock().await.iter() {
-                let mut writer = replica.connection.lock().await;
-                println!("Replicating command {:?} to {}", command, replica.addr);
+                    if let Some(existing) = existing {
+                        match existing.value.hyperloglog() {
+                            std::result::Result::Ok(hll) => union.merge(&hll),
+                            Err(error) => return RedisType::simple_error(error),
+                        }
+                    }
+                }
 
-                if let Err(e) = writer.write_all(&command.write_as_protocol()).await {
-                    println!(
-                        "Error replicating command {:?} to {}. {}",
-                        command, replica.addr, e
-                    );
+                RedisType::integer(union.count() as i64)
+            }
+            RedisCommand::PFMERGE { dest, sources } => {
+                let mut union = HyperLogLog::default();
+                {
+                    let read_guard = self.read_values().await;
+                    for source in sources {
+                        // Like any write from the master, keys it didn't delete yet are still seen
+                        if let Some(existing) = read_guard
+                            .get(source)
+                            .filter(|existing| !existing.is_expired() || from_master)
+                        {
+                            match existing.value.hyperloglog() {
+                                std::result::Result::Ok(hll) => union.merge(&hll),
+                                Err(error) => return RedisType::simple_error(error),
+                            }
+                        }
+                    }
                 }
+
+                // Merging is idempotent, so reading the destination again under the write lock
+                // can't lose elements added to it since the sources were read
+                self.with_value_mut(dest, from_master, |existing| {
+                    if let Some(value) = existing {
+                        match value.hyperloglog() {
+                            std::result::Result::Ok(hll) => union.merge(&hll),
+                            Err(error) => return RedisType::simple_error(error),
+                        }
+                    }
+
+                    *existing = Some(StoredValue::string(RedisType::bulk_bytes_owned(
+                        union.into_bytes(),
+                    )));
+                    RedisType::simple_string("OK")
+                })
+                .await
             }
-        }
+            RedisCommand::GEOADD { key, members } => {
+                // Nothing is added unless every pair is valid
+                if let Some(error) = members.iter().find_map(|(point, _)| point.validate().err()) {
+                    return RedisType::simple_error(&error);
+                }
 
-        Ok(())
-    }
+                self.with_value_mut(key, from_master, |existing| {
+                    let set = match existing
+                        .get_or_insert_with(|| StoredValue::SortedSet(SortedSet::default()))
+                    {
+                        StoredValue::SortedSet(set) => set,
+                        _ => return RedisType::simple_error(WRONGTYPE_ERROR),
+                    };
 
-    pub fn is_master(&self) -> bool {
-        matches!(self.replication_role, ReplicationRole::Master { .. })
-    }
+                    let added = members
+                        .iter()
+                        .filter(|(point, member)| set.insert(member, point.geohash() as f64))
+                        .count();
+                    if added > 0 {
+                        self.sorted_set_added.notify_waiters();
+                    }
+                    RedisType::integer(added as i64)
+                })
+                .await
+            }
+            RedisCommand::GEOPOS { key, members } => {
+                let read_guard = self.read_values().await;
 
-    async fn handle_psync(
-        &self,
-        response: &RedisType,
-        client: &mut RedisClient<TcpStream>,
-    ) -> Result<(), anyhow::Error> {
-        let repl_id = match response {
-            RedisType::SimpleString { data } => self.parse_fullresync(data),
-            other => {
-                return Err(anyhow::anyhow!(
-                    "Unexpected return type from PSYNC. Expected a simple string, received: {:?}",
-                    other
-                ))
+                let set = match self.get_sorted_set(&read_guard, key) {
+                    std::result::Result::Ok(set) => set,
+                    Err(error) => return error,
+                };
+                RedisType::list(
+                    members
+                        .iter()
+                        .map(|member| match set.and_then(|set| member_position(set, member)) {
+                            Some(point) => RedisType::list(vec![
+                                RedisType::bulk_string(&format_coordinate(point.lon)),
+                                RedisType::bulk_string(&format_coordinate(point.lat)),
+                            ]),
+                            None => RedisType::NullArray,
+                        })
+                        .collect(),
+                )
             }
-        }?;
+            RedisCommand::GEODIST {
+                key,
+                from,
+                to,
+                unit,
+            } => {
+                let read_guard = self.read_values().await;
 
-        println!("Captured REPL_ID: {}", repl_id);
+                let set = match self.get_sorted_set(&read_guard, key) {
+                    std::result::Result::Ok(set) => set,
+                    Err(error) => return error,
+                };
+                let from = set.and_then(|set| member_position(set, from));
+                let to = set.and_then(|set| member_position(set, to));
 
-        let file = client.accept_rdb_file().await?;
-        self.handle_rdb_file(&file)?;
+                match from.zip(to) {
+                    Some((from, to)) => RedisType::bulk_string(&format!(
+                        "{:.4}",
+                        from.distance(&to) / unit.meters()
+                    )),
+                    None => RedisType::NullBulkString,
+                }
+            }
+            RedisCommand::GEOSEARCH { key, from, radius } => {
+                let read_guard = self.read_values().await;
 
-        Ok(())
-    }
+                let set = match self.get_sorted_set(&read_guard, key) {
+                    std::result::Result::Ok(Some(set)) => set,
+                    std::result::Result::Ok(None) => return RedisType::list(vec![]),
+                    Err(error) => return error,
+                };
+                let center = match from {
+                    GeoSearchFrom::Member(member) => match member_position(set, member) {
+                        Some(point) => point,
+                        None => {
+                            return RedisType::simple_error(
+                                "ERR could not decode requested zset member",
+                            )
+                        }
+                    },
+                    GeoSearchFrom::Point(point) => *point,
+                };
 
-    fn parse_fullresync(&self, data: &str) -> Result<String, anyhow::Error> {
-        let parts: Vec<&str> = data.split_whitespace().collect();
-        if parts.len() == 3 && parts[0] == "FULLRESYNC" && parts[2] == "0" {
-            let repl_id = parts[1].to_string();
-            Ok(repl_id)
-        } else {
-            Err(anyhow::anyhow!(
-                "Unexpected format from PSYNC. Expected 'FULLRESYNC <REPL_ID> 0', received: {}",
-                data
-            ))
-        }
-    }
+                // Every member is checked, fine for the small sets this is used with
+                let radius = radius.meters();
+                RedisType::list(
+                    set.iter()
+                        .filter(|(_, score)| {
+                            Point::from_geohash(*score as u64).distance(&center) <= radius
+                        })
+                        .map(|(member, _)| RedisType::bulk_string(member))
+                        .collect(),
+                )
+            }
+            RedisCommand::ZADD {
+                key,
+                options,
+                members,
+            } => self.zadd(key, options, members, from_master).await,
+            RedisCommand::ZMPOP { keys, end, count } => self
+                .zmpop(keys, *end, *count, from_master)
+                .await
+                .unwrap_or(RedisType::NullArray),
+            RedisCommand::BZMPOP {
+                timeout,
+                keys,
+                end,
+                count,
+            } => {
+                self.bzmpop(*timeout, keys, *end, *count, from_master)
+                    .await
+            }
+            RedisCommand::OBJECT { arg } => match arg {
+                ObjectArgs::Encoding { key } => {
+                    let read_guard = self.read_values().await;
 
-    fn handle_rdb_file(&self, response: &RedisType) -> Result<(), anyhow::Error> {
-        if let RedisType::RDBFile { file } = response {
-            let file_text = BASE64_STANDARD.encode(file);
-            println!("Received file: {}", file_text);
+                    match self.get_live(&read_guard, key) {
+                        Some(value) => RedisType::bulk_string(value.encoding()),
+                        None => RedisType::NullBulkString,
+                    }
+                }
+            },
+            RedisCommand::DEL { keys } => {
+                let mut values = self.write_values().await;
 
-            if String::from_utf8_lossy(&file[..5]) == "REDIS" {
-                Ok(())
-            } else {
-                Err(anyhow::anyhow!("File is not an RDB file!"))
+                let mut deleted = 0;
+                for key in keys {
+                    if let Some(removed) = values.remove(key) {
+                        self.track_expiry(key, removed.expiry, None);
+                        // An expired key is already gone, except for the master deleting it
+                        if !removed.is_expired() || from_master {
+                            deleted += 1;
+                        }
+                    }
+                }
+
+                RedisType::integer(deleted)
             }
-        } else {
-            Err(anyhow::anyhow!(
-                "Unexpected type for RDB file. Expected a RDB file, received: {:?}",
-                response
-            ))
-        }
-    }
-}
+            RedisCommand::EXISTS { keys } => {
+                let read_guard = self.read_values().await;
 
-impl Default for RedisRuntime {
-    fn default() -> Self {
-        Self::new(Default::default())
-    }
-}
+                let mut found = 0;
+                let mut expired = Vec::new();
+                for key in keys {
+                    let hit = self.get_live(&read_guard, key).is_some();
+                    if hit {
+                        found += 1;
+                    } else if read_guard.contains_key(key) {
+                        expired.push(key);
+                    }
+                    self.stats.record_keyspace_lookup(hit);
+                }
+                drop(read_guard);
 
-#[derive(Debug)]
-struct Replica {
-    connection: Arc<Mutex<WriteHalf<TcpStream>>>,
-    addr: SocketAddr,
-}
+                // Like GET, expired keys are removed on the way, unless the master will delete them
+                if self.is_master() {
+                    for key in expired {
+                        self.remove_expired(key).await;
+                    }
+                }
 
-impl Replica {
-    fn new(client: Arc<Mutex<WriteHalf<TcpStream>>>, addr: SocketAddr) -> Self {
-        Self {
-            connection: client,
+                RedisType::integer(found)
+            }
+            RedisCommand::TYPE { key } => {
+                let read_guard = self.read_values().await;
+
+                let existing = self.get_live(&read_guard, key);
+                self.stats.record_keyspace_lookup(existing.is_some());
+
+                RedisType::simple_string(
+                    existing.map_or("none", |existing| existing.value.type_name()),
+                )
+            }
+            RedisCommand::TIME => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+
+                RedisType::list(vec![
+                    RedisType::bulk_string(&now.as_secs().to_string()),
+                    RedisType::bulk_string(&now.subsec_micros().to_string()),
+                ])
+            }
+            RedisCommand::CONFIG { arg } => match arg {
+                ConfigArgs::ResetStat => {
+                    self.stats.reset();
+                    RedisType::simple_string("OK")
+                }
+            },
+            // Like Redis, this counts keys that expired but weren't deleted yet
+            RedisCommand::DBSIZE => RedisType::integer(self.read_values().await.len() as i64),
+            RedisCommand::DEBUG { arg } => match arg {
+                DebugArgs::StringMatchLen { pattern, string } => RedisType::integer(
+                    glob_pattern::string_match(pattern.as_bytes(), string.as_bytes(), false) as i64,
+                ),
+                DebugArgs::SetActiveExpire(enabled) => {
+                    self.active_expire.store(*enabled, Ordering::Release);
+                    RedisType::simple_string("OK")
+                }
+            },
+        }
+    }
+
+    /// Validates a FAILOVER the same way Redis does. Handing over needs to know when the target
+    /// replica caught up with our offset, but replica ACKs aren't tracked, so a valid request is
+    /// refused rather than risking lost writes. No failover is ever in progress as a result.
+    async fn failover(
+        &self,
+        target: &Option<(String, u16)>,
+        force: bool,
+        abort: bool,
+        timeout: Option<u64>,
+    ) -> RedisType {
+        if abort {
+            return if target.is_some() || force || timeout.is_some() {
+                RedisType::simple_error("ERR FAILOVER ABORT can't be combined with other options")
+            } else {
+                RedisType::simple_error("ERR No failover in progress.")
+            };
+        }
+        if force && (timeout.is_none() || target.is_none()) {
+            return RedisType::simple_error(
+                "ERR FAILOVER with force option requires both a timeout and target HOST and IP.",
+            );
+        }
+
+        let Some(replicas) = self.replicas() else {
+            return RedisType::simple_error("ERR FAILOVER is not valid when server is a replica.");
+        };
+        let replicas: Vec<_> = replicas
+            .lock()
+            .await
+            .iter()
+            .map(|replica| replica.addr)
+            .collect();
+        if replicas.is_empty() {
+            return RedisType::simple_error("ERR FAILOVER requires connected replicas.");
+        }
+
+        if let Some((host, port)) = target {
+            let addrs: Vec<_> = lookup_host((host.as_str(), *port))
+                .await
+                .map(|addrs| addrs.collect())
+                .unwrap_or_default();
+
+            if !addrs.iter().any(|addr| replicas.contains(addr)) {
+                return RedisType::simple_error(
+                    "ERR FAILOVER target HOST and PORT is not a replica.",
+                );
+            }
+        }
+
+        RedisType::simple_error(
+            "ERR FAILOVER is not supported, as replica offsets aren't tracked to wait for the \
+             target to catch up.",
+        )
+    }
+
+    /// Deletes the keys whose TTL has passed, returning how many were removed. Only masters expire
+    /// keys actively, replicas keep them until the master propagates a DEL for each. Nothing is
+    /// deleted while DEBUG SET-ACTIVE-EXPIRE turned it off.
+    pub async fn active_expire_cycle(&self) -> usize {
+        if !self.is_master() || !self.active_expire.load(Ordering::Acquire) {
+            return 0;
+        }
+
+        let mut values = self.write_values().await;
+        let mut expired = Vec::new();
+        {
+            let mut index = self.expiry_index.lock().unwrap();
+            let now = Instant::now();
+
+            while index.first().is_some_and(|(deadline, _)| *deadline <= now) {
+                let (deadline, key) = index.pop_first().unwrap();
+                // The key may have been overwritten since, with another TTL or none at all
+                if values
+                    .get(&key)
+                    .is_some_and(|value| value.expiry == Some(deadline))
+                {
+                    values.remove(&key);
+                    expired.push(key);
+                }
+            }
+        }
+
+        for key in &expired {
+            self.propagate_expired(key).await;
+        }
+
+        expired.len()
+    }
+
+    /// Takes the keyspace read lock. An uncontended lock, the common case, is taken right away
+    /// with `try_read`, skipping the queueing and wakeup machinery of the async acquire.
+    async fn read_values(&self) -> RwLockReadGuard<'_, HashMap<String, ValueWithExpiry>> {
+        match self.values.try_read() {
+            std::result::Result::Ok(guard) => guard,
+            Err(_) => self.values.read().await,
+        }
+    }
+
+    /// Like `read_values`, for the write lock.
+    async fn write_values(&self) -> RwLockWriteGuard<'_, HashMap<String, ValueWithExpiry>> {
+        match self.values.try_write() {
+            std::result::Result::Ok(guard) => guard,
+            Err(_) => self.values.write().await,
+        }
+    }
+
+    /// Reads and rewrites `key` under a single write lock, so read-modify-write commands can't
+    /// lose updates to each other. `f` gets the live value, `None` if the key is missing or
+    /// expired, and can change it in place, replace it, or set it to `None` to delete the key.
+    /// The TTL survives changes to a live value, while a newly created key has none.
+    async fn with_value_mut<R>(
+        &self,
+        key: &str,
+        from_master: bool,
+        f: impl FnOnce(&mut Option<StoredValue>) -> R,
+    ) -> R {
+        let mut write_guard = self.write_values().await;
+        let previous = write_guard.remove(key);
+        let old_expiry = previous.as_ref().and_then(|previous| previous.expiry);
+
+        // Writes from the master still see the key, as it exists there until its DEL arrives. Any
+        // other write finds it expired, and a master sends replicas the DEL ahead of the write
+        let live = match previous {
+            Some(previous) if previous.is_expired() && !from_master => {
+                self.propagate_expired(key).await;
+                None
+            }
+            previous => previous,
+        };
+        let mut write_back = WriteBack {
+            runtime: self,
+            values: write_guard,
+            key,
+            old_expiry,
+            live_expiry: live.as_ref().and_then(|live| live.expiry),
+            value: live.map(|live| live.value),
+        };
+
+        f(&mut write_back.value)
+    }
+
+    /// Lazily deletes `key` if it expired, as found by a read that only held the read lock.
+    async fn remove_expired(&self, key: &str) {
+        let mut values = self.write_values().await;
+        // Someone may have set the key again since it was read
+        if values
+            .get(key)
+            .is_some_and(|existing| existing.is_expired())
+        {
+            let removed = values.remove(key).unwrap();
+            self.track_expiry(key, removed.expiry, None);
+            self.propagate_expired(key).await;
+        }
+    }
+
+    /// Sends replicas a DEL for `key`, which the master just deleted as expired. Replicas keep
+    /// expired keys until then, so a later write to the key finds it missing there too. Callers
+    /// still hold the `values` write lock, keeping the DEL ahead of anything that writes the key
+    /// again.
+    async fn propagate_expired(&self, key: &str) {
+        let del = RedisCommand::DEL {
+            keys: vec![key.to_string()],
+        };
+        if let Err(e) = self.replicate_command(&del, &RedisType::integer(1)).await {
+            println!("Error propagating the expiry of {}. {}", key, e);
+        }
+    }
+
+    /// The value at `key`, `None` once it expired. Every read goes through here, so expired
+    /// values never leak into a reply, even on replicas keeping them until the master deletes them.
+    fn get_live<'a>(
+        &self,
+        values: &'a HashMap<String, ValueWithExpiry>,
+        key: &str,
+    ) -> Option<&'a ValueWithExpiry> {
+        values.get(key).filter(|existing| !existing.is_expired())
+    }
+
+    /// The sorted set at `key`, `None` if there's no live key, or the WRONGTYPE reply for another
+    /// kind of value.
+    fn get_sorted_set<'a>(
+        &self,
+        values: &'a HashMap<String, ValueWithExpiry>,
+        key: &str,
+    ) -> Result<Option<&'a SortedSet>, RedisType> {
+        let existing = self.get_live(values, key);
+        self.stats.record_keyspace_lookup(existing.is_some());
+
+        match existing.map(|existing| &existing.value) {
+            Some(StoredValue::SortedSet(set)) => std::result::Result::Ok(Some(set)),
+            Some(_) => Err(RedisType::simple_error(WRONGTYPE_ERROR)),
+            None => std::result::Result::Ok(None),
+        }
+    }
+
+    /// Replies to XREAD. With `block`, waits for an XADD when no stream has new entries, and
+    /// replies nil if the time runs out first. A zero `block` waits for as long as it takes.
+    async fn xread(
+        &self,
+        streams: &[(String, XReadId)],
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> RedisType {
+        let mut after = Vec::with_capacity(streams.len());
+        {
+            let values = self.read_values().await;
+            for (key, id) in streams {
+                let stream = match self.get_live(&values, key).map(|existing| &existing.value) {
+                    Some(StoredValue::Stream(stream)) => Some(stream),
+                    Some(_) => return RedisType::simple_error(WRONGTYPE_ERROR),
+                    None => None,
+                };
+
+                after.push(match id {
+                    XReadId::After(id) => *id,
+                    // Only entries added after the call, so `$` is pinned before any waiting
+                    XReadId::Last => stream.map_or(StreamId::MIN, Stream::last_id),
+                });
+            }
+        }
+
+        let deadline = block.and_then(block_deadline);
+
+        loop {
+            // Registered before looking, so an XADD in between can't be missed
+            let added = self.stream_added.notified();
+            tokio::pin!(added);
+            added.as_mut().enable();
+
+            let mut reply = Vec::new();
+            {
+                let values = self.read_values().await;
+                for ((key, _), after) in streams.iter().zip(&after) {
+                    if let Some(StoredValue::Stream(stream)) =
+                        self.get_live(&values, key).map(|existing| &existing.value)
+                    {
+                        let entries = stream.after(*after, count);
+                        if !entries.is_empty() {
+                            reply.push(RedisType::list(vec![
+                                RedisType::bulk_string(key),
+                                RedisType::list(
+                                    entries
+                                        .into_iter()
+                                        .map(|(id, fields)| stream_entry(id, fields))
+                                        .collect(),
+                                ),
+                            ]));
+                        }
+                    }
+                }
+            }
+
+            if !reply.is_empty() {
+                return RedisType::list(reply);
+            }
+
+            match (block, deadline) {
+                (None, _) => return RedisType::NullArray,
+                (Some(_), None) => added.await,
+                (Some(_), Some(deadline)) => {
+                    if tokio::time::timeout_at(deadline, added).await.is_err() {
+                        return RedisType::NullArray;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replies to ZADD. With INCR the reply is the new score, or nil when the options kept the
+    /// member from being updated.
+    async fn zadd(
+        &self,
+        key: &str,
+        options: &ZAddOptions,
+        members: &[(Score, String)],
+        from_master: bool,
+    ) -> RedisType {
+        self.with_value_mut(key, from_master, |existing| {
+            let set = match existing
+                .get_or_insert_with(|| StoredValue::SortedSet(SortedSet::default()))
+            {
+                StoredValue::SortedSet(set) => set,
+                _ => return RedisType::simple_error(WRONGTYPE_ERROR),
+            };
+
+            let (mut added, mut updated) = (0, 0);
+            let mut incr_reply = RedisType::NullBulkString;
+            for (Score(score), member) in members {
+                let current = set.score(member);
+                let skip = match options.condition {
+                    Some(Condition::Nx) => current.is_some(),
+                    Some(Condition::Xx) => current.is_none(),
+                    None => false,
+                };
+                if skip {
+                    continue;
+                }
+
+                let score = match current {
+                    Some(current) if options.incr => current + score,
+                    _ => *score,
+                };
+                if score.is_nan() {
+                    return RedisType::simple_error("ERR resulting score is not a number (NaN)");
+                }
+                // GT and LT only hold back updates, new members are always added
+                let allowed = match (current, options.comparison) {
+                    (Some(current), Some(ScoreComparison::Gt)) => score > current,
+                    (Some(current), Some(ScoreComparison::Lt)) => score < current,
+                    _ => true,
+                };
+                if !allowed {
+                    continue;
+                }
+
+                match current {
+                    None => added += 1,
+                    Some(current) if current != score => updated += 1,
+                    Some(_) => {}
+                }
+                set.insert(member, score);
+                incr_reply = RedisType::bulk_string(&format_double(score));
+            }
+
+            // Nothing was added to a new key, like with XX on a missing one
+            if set.is_empty() {
+                *existing = None;
+            }
+            if added > 0 {
+                self.sorted_set_added.notify_waiters();
+            }
+
+            if options.incr {
+                incr_reply
+            } else if options.changed {
+                RedisType::integer(added + updated)
+            } else {
+                RedisType::integer(added)
+            }
+        })
+        .await
+    }
+
+    /// Adds `delta` to the integer in the string at `key`, a missing key counting as 0.
+    async fn incr_by(&self, key: &str, delta: i64, from_master: bool) -> RedisType {
+        self.with_value_mut(key, from_master, |existing| {
+            let current = match existing {
+                Some(existing @ StoredValue::String { .. }) => {
+                    match existing
+                        .string_bytes()
+                        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+                        .and_then(parse_int_arg::<i64>)
+                    {
+                        Some(current) => current,
+                        None => return RedisType::simple_error(INTEGER_ERROR),
+                    }
+                }
+                Some(_) => return RedisType::simple_error(WRONGTYPE_ERROR),
+                None => 0,
+            };
+
+            match current.checked_add(delta) {
+                Some(result) => {
+                    *existing = Some(StoredValue::string(RedisType::bulk_string(
+                        &result.to_string(),
+                    )));
+                    RedisType::integer(result)
+                }
+                None => RedisType::simple_error("ERR increment or decrement would overflow"),
+            }
+        })
+        .await
+    }
+
+    /// Pops from the first of `keys` that holds a sorted set, `None` when none of them exists.
+    async fn zmpop(
+        &self,
+        keys: &[String],
+        end: PopEnd,
+        count: usize,
+        from_master: bool,
+    ) -> Option<RedisType> {
+        for key in keys {
+            let reply = self
+                .with_value_mut(key, from_master, |existing| {
+                    let set = match existing {
+                        Some(StoredValue::SortedSet(set)) => set,
+                        Some(_) => return Some(RedisType::simple_error(WRONGTYPE_ERROR)),
+                        None => return None,
+                    };
+
+                    let popped = std::iter::from_fn(|| set.pop(end))
+                        .take(count)
+                        .map(|(member, score)| {
+                            RedisType::list(vec![
+                                RedisType::bulk_string(&member),
+                                RedisType::bulk_string(&format_double(score)),
+                            ])
+                        })
+                        .collect();
+                    // Popping the last member deletes the key
+                    if set.is_empty() {
+                        *existing = None;
+                    }
+
+                    Some(RedisType::list(vec![
+                        RedisType::bulk_string(key),
+                        RedisType::list(popped),
+                    ]))
+                })
+                .await;
+
+            if reply.is_some() {
+                return reply;
+            }
+        }
+
+        None
+    }
+
+    /// Like ZMPOP, but waits up to `timeout` for a member to be added when there's nothing to
+    /// pop. A zero timeout waits for good.
+    async fn bzmpop(
+        &self,
+        timeout: Duration,
+        keys: &[String],
+        end: PopEnd,
+        count: usize,
+        from_master: bool,
+    ) -> RedisType {
+        let deadline = block_deadline(timeout);
+
+        loop {
+            // Registered before looking, so a member added in between can't be missed
+            let added = self.sorted_set_added.notified();
+            tokio::pin!(added);
+            added.as_mut().enable();
+
+            if let Some(reply) = self.zmpop(keys, end, count, from_master).await {
+                return reply;
+            }
+
+            match deadline {
+                None => added.await,
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, added).await.is_err() {
+                        return RedisType::NullArray;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replies to XREADGROUP. Every stream must have the group before anything is delivered, so a
+    /// failed read doesn't leave entries pending on some of them.
+    async fn xreadgroup(
+        &self,
+        group: &str,
+        consumer: &str,
+        count: Option<usize>,
+        streams: &[(String, XReadGroupId)],
+        from_master: bool,
+    ) -> RedisType {
+        let mut values = self.write_values().await;
+
+        for (key, _) in streams {
+            match values
+                .get(key)
+                .filter(|existing| !existing.is_expired() || from_master)
+                .map(|existing| &existing.value)
+            {
+                Some(StoredValue::Stream(stream)) if stream.has_group(group) => {}
+                Some(StoredValue::Stream(_)) | None => {
+                    return RedisType::simple_error(&format!(
+                        "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                        key, group
+                    ));
+                }
+                Some(_) => return RedisType::simple_error(WRONGTYPE_ERROR),
+            }
+        }
+
+        let mut reply = Vec::new();
+        for (key, id) in streams {
+            if let Some(StoredValue::Stream(stream)) =
+                values.get_mut(key).map(|existing| &mut existing.value)
+            {
+                let entries = stream
+                    .read_group(group, consumer, *id, count)
+                    .unwrap_or_default();
+
+                // A consumer's history is listed even when empty, unlike new entries
+                if !entries.is_empty() || matches!(id, XReadGroupId::Pending(_)) {
+                    reply.push(RedisType::list(vec![
+                        RedisType::bulk_string(key),
+                        RedisType::list(
+                            entries
+                                .into_iter()
+                                .map(|(id, fields)| stream_entry(id, fields))
+                                .collect(),
+                        ),
+                    ]));
+                }
+            }
+        }
+
+        if reply.is_empty() {
+            RedisType::NullArray
+        } else {
+            RedisType::list(reply)
+        }
+    }
+
+    /// Builds the INFO reply for the requested `sections`, in order and separated by a blank
+    /// line. No sections means the default set. Unknown ones are skipped, as Redis does.
+    async fn info(&self, sections: &[String]) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        for section in sections.iter().map(|section| section.to_lowercase()) {
+            let expanded: &[&str] = match section.as_str() {
+                "default" => DEFAULT_INFO_SECTIONS,
+                "all" | "everything" => ALL_INFO_SECTIONS,
+                _ => match ALL_INFO_SECTIONS.iter().find(|name| **name == section) {
+                    Some(name) => std::slice::from_ref(name),
+                    None => &[],
+                },
+            };
+            for name in expanded {
+                if !names.contains(name) {
+                    names.push(name);
+                }
+            }
+        }
+        if sections.is_empty() {
+            names.extend(DEFAULT_INFO_SECTIONS);
+        }
+
+        let mut info = Vec::with_capacity(names.len());
+        for name in names {
+            info.push(match name {
+                "server" => format!(
+                    "# Server\nredis_version:{}\nredis_mode:standalone\nprocess_id:{}\ntcp_port:{}\nuptime_in_seconds:{}",
+                    env!("CARGO_PKG_VERSION"),
+                    std::process::id(),
+                    self.config.port,
+                    self.started_at.elapsed().as_secs(),
+                ),
+                "replication" => self.info_replication().await,
+                "stats" => self.stats.info_stats(),
+                "commandstats" => self.stats.info_commandstats(),
+                _ => self.stats.info_errorstats(),
+            });
+        }
+
+        info.join("\n\n")
+    }
+
+    async fn info_replication(&self) -> String {
+        let backlog = self.backlog.lock().await;
+        let replication = self.replication.read().unwrap();
+
+        format!(
+            "# Replication
+role:{}
+master_replid:{}
+master_repl_offset:{}
+repl_backlog_active:{}
+repl_backlog_size:{}
+repl_backlog_first_byte_offset:{}
+repl_backlog_histlen:{}",
+            replication.role.type_str(),
+            replication.id,
+            backlog.offset(),
+            backlog.is_active() as u8,
+            backlog.size(),
+            backlog.first_byte_offset(),
+            backlog.histlen(),
+        )
+    }
+
+    /// Keeps the expiry index in sync when the TTL of `key` goes from `old` to `new`. Callers
+    /// must hold the `values` write lock.
+    fn track_expiry(&self, key: &str, old: Option<Instant>, new: Option<Instant>) {
+        if old == new {
+            return;
+        }
+
+        let mut index = self.expiry_index.lock().unwrap();
+        if let Some(old) = old {
+            index.remove(&(old, key.to_string()));
+        }
+        if let Some(new) = new {
+            index.insert((new, key.to_string()));
+        }
+    }
+
+    /// The metrics reported by `MEMORY STATS`, computed by walking the keyspace since memory isn't
+    /// accounted for as values change. Overhead covers bookkeeping and the replication backlog,
+    /// while the dataset is the key and value bytes themselves.
+    async fn memory_stats(&self) -> Vec<(&'static str, usize)> {
+        let values = self.read_values().await;
+        let keys = values.len();
+        let entries: usize = values
+            .iter()
+            .map(|(key, value)| value.memory_usage(key))
+            .sum();
+
+        let backlog = self.backlog.lock().await;
+        let backlog_bytes = if backlog.is_active() {
+            backlog.size()
+        } else {
+            0
+        };
+
+        let overhead = keys * ENTRY_OVERHEAD + backlog_bytes;
+        let dataset = entries - keys * ENTRY_OVERHEAD;
+        let total = overhead + dataset;
+
+        vec![
+            ("total.allocated", total),
+            ("replication.backlog", backlog_bytes),
+            ("overhead.total", overhead),
+            ("keys.count", keys),
+            ("keys.bytes-per-key", total.checked_div(keys).unwrap_or(0)),
+            ("dataset.bytes", dataset),
+        ]
+    }
+
+    async fn memory_doctor(&self) -> &'static str {
+        let stats = self.memory_stats().await;
+        let stat = |name| {
+            stats
+                .iter()
+                .find(|(stat, _)| *stat == name)
+                .map_or(0, |(_, value)| *value)
+        };
+
+        // Same threshold as Redis, below which there is too little data to judge
+        if stat("total.allocated") < 5 * 1024 * 1024 {
+            "Hi Sam, this instance is empty or is using very little memory, my issues detector \
+             can't be used in these conditions. Please, leave for your mission on Earth and fill \
+             it with some data. The new Sam and I will be back to our programming as soon as I \
+             finished rebooting."
+        } else if stat("replication.backlog") > stat("dataset.bytes") {
+            "Sam, I detected a few issues in this Redis instance memory implants:\n\n \
+             * Big replication backlog: the replication backlog is larger than the dataset \
+             itself. Consider lowering repl-backlog-size.\n\n\
+             I'm here to keep you safe, Sam. I want to help you."
+        } else {
+            "Hi Sam, I can't find any memory issue in your instance. I can only account for what \
+             occurs on this base."
+        }
+    }
+
+    /// A map, flattened for RESP2, from each known command in `names` to its documentation.
+    /// Unknown names are left out, so asking only for those gives an empty map.
+    fn command_docs(names: &[String]) -> RedisType {
+        let specs: Vec<_> = if names.is_empty() {
+            command_table::COMMAND_TABLE.iter().collect()
+        } else {
+            names
+                .iter()
+                .filter_map(|name| command_table::lookup(name))
+                .collect()
+        };
+
+        let mut docs = Vec::new();
+        for spec in specs {
+            let arguments = spec
+                .arguments
+                .iter()
+                .map(|arg| {
+                    let mut fields = vec![
+                        RedisType::bulk_string("name"),
+                        RedisType::bulk_string(arg.name),
+                        RedisType::bulk_string("type"),
+                        RedisType::bulk_string(arg.arg_type),
+                    ];
+                    if arg.optional {
+                        fields.push(RedisType::bulk_string("flags"));
+                        fields.push(RedisType::list(vec![RedisType::simple_string("optional")]));
+                    }
+
+                    RedisType::list(fields)
+                })
+                .collect();
+
+            docs.push(RedisType::bulk_string(spec.name));
+            docs.push(RedisType::list(vec![
+                RedisType::bulk_string("summary"),
+                RedisType::bulk_string(spec.summary),
+                RedisType::bulk_string("since"),
+                RedisType::bulk_string(spec.since),
+                RedisType::bulk_string("group"),
+                RedisType::bulk_string(spec.group),
+                RedisType::bulk_string("arguments"),
+                RedisType::list(arguments),
+            ]));
+        }
+
+        RedisType::list(docs)
+    }
+
+    fn get_keys_and_flags(args: &[String]) -> RedisType {
+        let Some(spec) = command_table::lookup(&args[0]) else {
+            return RedisType::simple_error("ERR Invalid command specified");
+        };
+        let Some(key_spec) = &spec.keys else {
+            return RedisType::simple_error("ERR The command has no key arguments");
+        };
+        let Some(keys) = key_spec.extract_keys(args) else {
+            return RedisType::simple_error(
+                "ERR Invalid number of arguments specified for command",
+            );
+        };
+
+        let flags: Vec<_> = key_spec
+            .flags
+            .iter()
+            .map(|flag| RedisType::simple_string(flag))
+            .collect();
+
+        RedisType::list(
+            keys.into_iter()
+                .map(|key| {
+                    RedisType::list(vec![
+                        RedisType::bulk_string(key),
+                        RedisType::list(flags.clone()),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    pub async fn perform_handshake(&self) -> Result<Option<TcpStream>, anyhow::Error> {
+        let replicaof = self.replication.read().unwrap().role.replicaof();
+
+        match replicaof {
+            None => Ok(None), // Do nothing, since the instance is a master
+            Some(replicaof) => {
+                println!("Starting handshake with {}", replicaof);
+                let mut client = self
+                    .handshake_step("connect", RedisClient::new(replicaof))
+                    .await?;
+
+                println!("Sending PING");
+                let response = self
+                    .handshake_step("PING", client.send_command(&RedisCommand::PING))
+                    .await?;
+                response.expect_string("pong", "Unexpected return from ping")?;
+
+                println!("Sending REPLCONF port {}", self.config.port);
+                let port = RedisCommand::REPLCONF {
+                    arg: ReplConfArgs::Port(self.config.port),
+                };
+                let response = self
+                    .handshake_step("REPLCONF port", client.send_command(&port))
+                    .await?;
+                response.expect_string("ok", "Unexpected return from REPLCONF port")?;
+
+                println!("Sending REPLCONF capabilities");
+                let capabilities = RedisCommand::default_capabilities();
+                let response = self
+                    .handshake_step("REPLCONF capa", client.send_command(&capabilities))
+                    .await?;
+                response.expect_string("ok", "Unexpected return from REPLCONF capabilities")?;
+
+                println!("Sending PSYNC");
+                let psync = RedisCommand::psync_from_scrath();
+                let response = self
+                    .handshake_step("PSYNC", client.send_command(&psync))
+                    .await?;
+                self.handle_psync(&response, &mut client).await?;
+
+                println!("Handshake successful. Ready to receive commands");
+                Ok(Some(client.buffer.into_inner()))
+            }
+        }
+    }
+
+    pub async fn replicate_command(
+        &self,
+        command: &RedisCommand,
+        result: &RedisType,
+    ) -> anyhow::Result<()> {
+        if !command.is_write_command() {
+            return Ok(());
+        }
+
+        let Some(command) = command.for_replication(result) else {
+            return Ok(());
+        };
+        if let Some(replicas) = self.replicas() {
+            #[cfg(test)]
+            self.propagated.lock().unwrap().push(command.clone());
+
+            let bytes = command.write_as_protocol();
+            self.backlog.lock().await.feed(&bytes);
+
+            for replica in replicas.lock().await.iter() {
+                let mut writer = replica.connection.lock().await;
+                println!("Replicating command {:?} to {}", command, replica.addr);
+
+                if let Err(e) = writer.write_all(&bytes).await {
+                    println!(
+                        "Error replicating command {:?} to {}. {}",
+                        command, replica.addr, e
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replicas only accept writes coming from their master, unless `replica-read-only` is off.
+    /// Returns the error to reply with when `command` has to be rejected.
+    pub fn read_only_error(&self, command: &RedisCommand, from_master: bool) -> Option<RedisType> {
+        if self.config.replica_read_only
+            && !self.is_master()
+            && command.is_write_command()
+            && !from_master
+        {
+            Some(RedisType::simple_error(
+                "READONLY You can't write against a read only replica.",
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Clients get a LOADING error while the dataset is being replaced by the master's RDB,
+    /// except for the few commands that don't touch the keyspace. Returns the error to reply with
+    /// when `command` has to be rejected.
+    pub fn loading_error(&self, command: &RedisCommand, from_master: bool) -> Option<RedisType> {
+        let allowed = matches!(
+            command,
+            RedisCommand::INFO { .. }
+                | RedisCommand::CONFIG { .. }
+                | RedisCommand::COMMAND { .. }
+                | RedisCommand::TIME
+                | RedisCommand::REPLICAOF { .. }
+        );
+
+        if self.loading.load(Ordering::Acquire) && !allowed && !from_master {
+            Some(RedisType::simple_error(
+                "LOADING Redis is loading the dataset in memory",
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.replication.read().unwrap().role.replicaof().is_none()
+    }
+
+    /// Switches the instance to replicate `replicaof`, or to be a master when `None`. The
+    /// replication id, offset and backlog all start over, as the old history no longer applies.
+    pub async fn set_replication_role(&self, replicaof: Option<SocketAddr>) {
+        let mut backlog = self.backlog.lock().await;
+        *backlog = ReplicationBacklog::new(self.config.repl_backlog_size);
+        *self.replication.write().unwrap() = ReplicationState::new(replicaof);
+        self.role_changed.notify_one();
+    }
+
+    /// Resolves once the replication role changes, so the replica loop can (re)connect.
+    pub async fn replication_role_changed(&self) {
+        self.role_changed.notified().await
+    }
+
+    fn replicas(&self) -> Option<Arc<Mutex<Vec<Replica>>>> {
+        match &self.replication.read().unwrap().role {
+            ReplicationRole::Master { replicas } => Some(Arc::clone(replicas)),
+            ReplicationRole::Slave { .. } => None,
+        }
+    }
+
+    async fn handle_psync(
+        &self,
+        response: &RedisType,
+        client: &mut RedisClient<TcpStream>,
+    ) -> Result<(), anyhow::Error> {
+        let repl_id = match response {
+            RedisType::SimpleString { data } => self.parse_fullresync(data),
+            other => {
+                return Err(anyhow::anyhow!(
+                    "Unexpected return type from PSYNC. Expected a simple string, received: {:?}",
+                    other
+                ))
+            }
+        }?;
+
+        println!("Captured REPL_ID: {}", repl_id);
+
+        // Commands from the master are only read once the handshake returns, so nothing gets
+        // applied before the RDB is loaded. Clients are held off with LOADING meanwhile
+        self.loading.store(true, Ordering::Release);
+        let loaded = async {
+            let file = self
+                .handshake_step("RDB transfer", client.accept_rdb_file())
+                .await?;
+            self.handle_rdb_file(&file)
+        }
+        .await;
+        self.loading.store(false, Ordering::Release);
+
+        loaded
+    }
+
+    /// Runs one step of the handshake, failing it if the master takes longer than `repl-timeout`.
+    /// A master that stalls or half-closes then ends in an error the replica loop retries on.
+    async fn handshake_step<T, E: std::fmt::Display>(
+        &self,
+        step: &str,
+        future: impl Future<Output = Result<T, E>>,
+    ) -> anyhow::Result<T> {
+        match tokio::time::timeout(self.config.repl_timeout, future).await {
+            std::result::Result::Ok(result) => {
+                result.map_err(|e| anyhow::anyhow!("Handshake step {} failed: {}", step, e))
+            }
+            Err(_) => Err(anyhow::anyhow!(
+                "Timeout waiting for the master during handshake step {}",
+                step
+            )),
+        }
+    }
+
+    fn parse_fullresync(&self, data: &str) -> Result<String, anyhow::Error> {
+        let parts: Vec<&str> = data.split_whitespace().collect();
+        if parts.len() == 3 && parts[0] == "FULLRESYNC" && parts[2] == "0" {
+            let repl_id = parts[1].to_string();
+            Ok(repl_id)
+        } else {
+            Err(anyhow::anyhow!(
+                "Unexpected format from PSYNC. Expected 'FULLRESYNC <REPL_ID> 0', received: {}",
+                data
+            ))
+        }
+    }
+
+    fn handle_rdb_file(&self, response: &RedisType) -> Result<(), anyhow::Error> {
+        if let RedisType::RDBFile { file } = response {
+            let file_text = BASE64_STANDARD.encode(file);
+            println!("Received file: {}", file_text);
+
+            rdb_file::validate(file, self.config.rdbchecksum)?;
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "Unexpected type for RDB file. Expected a RDB file, received: {:?}",
+                response
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+impl RedisRuntime {
+    /// Compares the logical content of two keyspaces: same live keys, same values, and expiries
+    /// that differ by no more than `tolerance`. Meant for persistence round-trip tests, where
+    /// comparing serialized bytes would be too brittle.
+    pub async fn same_keyspace_as(
+        &self,
+        other: &RedisRuntime,
+        tolerance: std::time::Duration,
+    ) -> bool {
+        let now = Instant::now();
+        let live = |values: &HashMap<String, ValueWithExpiry>| {
+            values
+                .iter()
+                .filter(|(_, val)| val.expiry.is_none_or(|expiry| expiry > now))
+                .map(|(key, val)| (key.clone(), val.clone()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let ours = live(&*self.read_values().await);
+        let theirs = live(&*other.values.read().await);
+
+        ours.len() == theirs.len()
+            && ours.iter().all(|(key, val)| match theirs.get(key) {
+                Some(other_val) => {
+                    val.value == other_val.value
+                        && match (val.expiry, other_val.expiry) {
+                            (None, None) => true,
+                            (Some(a), Some(b)) => a.max(b).duration_since(a.min(b)) <= tolerance,
+                            _ => false,
+                        }
+                }
+                None => false,
+            })
+    }
+}
+
+/// The value `with_value_mut` took out of the keyspace, stored back when dropped. That happens
+/// even if the closure changing it panics, so the key isn't lost along with the command.
+struct WriteBack<'a> {
+    runtime: &'a RedisRuntime,
+    values: RwLockWriteGuard<'a, HashMap<String, ValueWithExpiry>>,
+    key: &'a str,
+    old_expiry: Option<Instant>,
+    live_expiry: Option<Instant>,
+    value: Option<StoredValue>,
+}
+
+impl Drop for WriteBack<'_> {
+    fn drop(&mut self) {
+        let new_expiry = self.value.as_ref().and(self.live_expiry);
+        if let Some(value) = self.value.take() {
+            self.values.insert(
+                self.key.to_string(),
+                ValueWithExpiry {
+                    value,
+                    expiry: new_expiry,
+                },
+            );
+        }
+        self.runtime
+            .track_expiry(self.key, self.old_expiry, new_expiry);
+    }
+}
+
+impl Default for RedisRuntime {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+#[derive(Debug)]
+struct Replica {
+    connection: Arc<Mutex<WriteHalf<TcpStream>>>,
+    addr: SocketAddr,
+}
+
+impl Replica {
+    fn new(client: Arc<Mutex<WriteHalf<TcpStream>>>, addr: SocketAddr) -> Self {
+        Self {
+            connection: client,
             addr,
         }
     }
-}
+}
+
+#[derive(Debug)]
+enum ReplicationRole {
+    Master { replicas: Arc<Mutex<Vec<Replica>>> },
+    Slave { replicaof: SocketAddr },
+}
+
+impl ReplicationRole {
+    fn new(replicaof: Option<SocketAddr>) -> Self {
+        replicaof
+            .map(|addr| ReplicationRole::Slave { replicaof: addr })
+            .unwrap_or_else(|| ReplicationRole::Master {
+                replicas: Arc::new(Mutex::new(Vec::new())),
+            })
+    }
+
+    fn replicaof(&self) -> Option<SocketAddr> {
+        match self {
+            ReplicationRole::Master { .. } => None,
+            ReplicationRole::Slave { replicaof } => Some(*replicaof),
+        }
+    }
+
+    fn type_str(&self) -> &str {
+        match self {
+            ReplicationRole::Master { .. } => "master",
+            ReplicationRole::Slave { .. } => "slave",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ReplicationState {
+    role: ReplicationRole,
+    id: String,
+}
+
+impl ReplicationState {
+    fn new(replicaof: Option<SocketAddr>) -> Self {
+        Self {
+            role: ReplicationRole::new(replicaof),
+            id: generate_alphanumeric_string(40),
+        }
+    }
+}
+
+/// A stream entry as XRANGE and XREAD reply with it: the id, then the fields and values.
+fn stream_entry(id: &StreamId, fields: &StreamFields) -> RedisType {
+    RedisType::list(vec![
+        RedisType::bulk_string(&id.to_string()),
+        RedisType::list(
+            fields
+                .iter()
+                .flat_map(|(field, value)| {
+                    [RedisType::bulk_string(field), RedisType::bulk_string(value)]
+                })
+                .collect(),
+        ),
+    ])
+}
+
+/// The XINFO STREAM reply, a flat list of field names and values. Entries are never deleted, so
+/// every entry added is still there.
+fn stream_info(stream: &Stream) -> RedisType {
+    let entry = |entry: Option<(&StreamId, &StreamFields)>| match entry {
+        Some((id, fields)) => stream_entry(id, fields),
+        None => RedisType::NullBulkString,
+    };
+    let first_id = stream
+        .first_entry()
+        .map_or(StreamId::MIN, |(id, _)| *id)
+        .to_string();
+
+    RedisType::list(vec![
+        RedisType::bulk_string("length"),
+        RedisType::integer(stream.len() as i64),
+        RedisType::bulk_string("last-generated-id"),
+        RedisType::bulk_string(&stream.last_id().to_string()),
+        RedisType::bulk_string("max-deleted-entry-id"),
+        RedisType::bulk_string(&StreamId::MIN.to_string()),
+        RedisType::bulk_string("entries-added"),
+        RedisType::integer(stream.len() as i64),
+        RedisType::bulk_string("recorded-first-entry-id"),
+        RedisType::bulk_string(&first_id),
+        RedisType::bulk_string("groups"),
+        RedisType::integer(stream.groups().count() as i64),
+        RedisType::bulk_string("first-entry"),
+        entry(stream.first_entry()),
+        RedisType::bulk_string("last-entry"),
+        entry(stream.last_entry()),
+    ])
+}
+
+/// The XINFO GROUPS reply, a flat list of field names and values for each group.
+fn stream_groups_info(stream: &Stream) -> RedisType {
+    RedisType::list(
+        stream
+            .groups()
+            .map(|(name, group)| {
+                let entries_read = stream.entries_read(group);
+
+                RedisType::list(vec![
+                    RedisType::bulk_string("name"),
+                    RedisType::bulk_string(name),
+                    RedisType::bulk_string("consumers"),
+                    RedisType::integer(group.consumers() as i64),
+                    RedisType::bulk_string("pending"),
+                    RedisType::integer(group.pending() as i64),
+                    RedisType::bulk_string("last-delivered-id"),
+                    RedisType::bulk_string(&group.last_delivered().to_string()),
+                    RedisType::bulk_string("entries-read"),
+                    RedisType::integer(entries_read as i64),
+                    RedisType::bulk_string("lag"),
+                    RedisType::integer((stream.len() - entries_read) as i64),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// When a command blocking for `timeout` gives up, `None` to wait for good. That's for a zero
+/// timeout, and for one so long the timer can't hold it, which comes down to the same.
+fn block_deadline(timeout: Duration) -> Option<tokio::time::Instant> {
+    const FOREVER: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+    if timeout.is_zero() || timeout > FOREVER {
+        return None;
+    }
+    tokio::time::Instant::now().checked_add(timeout)
+}
+
+/// Awaits `future`, `None` if it panicked. Each poll runs under `catch_unwind`, so this costs
+/// no task spawn and no `'static` copy of what the future borrows, unlike isolating it in a task.
+/// A future that panicked is dropped without being polled again.
+async fn isolate<T>(future: impl Future<Output = T>) -> Option<T> {
+    let mut future = std::pin::pin!(future);
+
+    std::future::poll_fn(|cx| {
+        match std::panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            std::result::Result::Ok(Poll::Ready(output)) => Poll::Ready(Some(output)),
+            std::result::Result::Ok(Poll::Pending) => Poll::Pending,
+            Err(_) => {
+                println!("Command handler panicked");
+                Poll::Ready(None)
+            }
+        }
+    })
+    .await
+}
+
+/// Where a GEO member is, from the geohash in its score.
+fn member_position(set: &SortedSet, member: &str) -> Option<Point> {
+    set.score(member)
+        .map(|score| Point::from_geohash(score as u64))
+}
+
+/// Formats a coordinate like Redis does for GEOPOS, with 17 decimals and no trailing zeros.
+fn format_coordinate(value: f64) -> String {
+    let formatted = format!("{:.17}", value);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Formats a score like Redis does: the shortest digits that read back as the same double, in
+/// scientific notation where `%.17g` would use it, and infinities spelled out.
+fn format_double(value: f64) -> String {
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if (-4..17).contains(&exponent) {
+        value.to_string()
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+    }
+}
+
+/// Resolves an inclusive `start..=end` range over `len` bytes the way GETRANGE does, where negative
+/// indexes count from the end. `None` when the range selects nothing.
+fn normalize_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    if start < 0 && end < 0 && start > end {
+        return None;
+    }
+
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+
+    if len == 0 || start > end {
+        None
+    } else {
+        Some((start as usize, end as usize))
+    }
+}
+
+fn generate_alphanumeric_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        geo::{GeoDistance, GeoUnit},
+        redis_stream::XAddId,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ping_command() {
+        let runtime = RedisRuntime::default();
+        let result = runtime.execute_no_conn(&RedisCommand::PING).await;
+        assert_eq!(result, RedisType::simple_string("PONG"));
+    }
+
+    #[tokio::test]
+    async fn test_echo_command() {
+        let runtime = RedisRuntime::default();
+        let result = runtime
+            .execute_no_conn(&RedisCommand::ECHO("Hello, Redis!".to_string()))
+            .await;
+        assert_eq!(result, RedisType::bulk_string("Hello, Redis!"));
+    }
+
+    #[tokio::test]
+    async fn test_set_command() {
+        let runtime = RedisRuntime::default();
+        let result = runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "key1".to_string(),
+                val: RedisType::bulk_string("value1"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(result, RedisType::simple_string("OK"));
+
+        // Ensure the value is actually set
+        let guard = runtime.values.read().await;
+        let value = &guard.get("key1").unwrap().value;
+        assert_eq!(
+            value,
+            &StoredValue::string(RedisType::bulk_string("value1"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_command_with_ttl() {
+        let runtime = RedisRuntime::default();
+
+        let key = "key_with_ttl";
+        let result = runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: key.to_string(),
+                val: RedisType::bulk_string("temporary"),
+                expiry: Some(ExpiryOption::After(Duration::from_millis(100))),
+                condition: None,
+            })
+            .await;
+        assert_eq!(result, RedisType::simple_string("OK"));
+
+        // Ensure the value is actually set
+        let value = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: key.to_string(),
+            })
+            .await;
+        assert_eq!(value, RedisType::bulk_string("temporary"));
+
+        tokio::time::sleep(Duration::from_millis(101)).await;
+
+        // Ensure the value has expired
+        let value = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: key.to_string(),
+            })
+            .await;
+        assert_eq!(value, RedisType::NullBulkString);
+    }
+
+    #[tokio::test]
+    async fn test_get_command_existing_key() {
+        let runtime = RedisRuntime::default();
+        runtime.values.write().await.insert(
+            "key1".to_string(),
+            ValueWithExpiry {
+                value: StoredValue::string(RedisType::bulk_string("value1")),
+
+                expiry: None,
+            },
+        );
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "key1".to_string(),
+            })
+            .await;
+        assert_eq!(result, RedisType::bulk_string("value1"));
+    }
+
+    #[tokio::test]
+    async fn test_replication_info() {
+        let runtime = RedisRuntime::default();
+        assert!(matches!(
+            runtime.replication.read().unwrap().role,
+            ReplicationRole::Master { .. },
+        ));
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::INFO {
+                sections: vec!["replication".to_string()],
+            })
+            .await;
+
+        match result {
+            RedisType::BulkString { data } => {
+                assert!(data.contains("role:master"));
+                assert!(data.contains("master_replid:"));
+                assert!(data.contains("master_repl_offset:0"));
+            }
+            _ => panic!("Result was not a bulk string"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replication_info_backlog() {
+        let runtime = RedisRuntime::new(ServerConfig {
+            repl_backlog_size: 16,
+            ..Default::default()
+        });
+        runtime.backlog.lock().await.activate();
+
+        let command = RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry: None,
+            condition: None,
+        };
+        runtime
+            .replicate_command(&command, &RedisType::simple_string("OK"))
+            .await
+            .unwrap();
+
+        let written = command.write_as_protocol().len();
+        let result = runtime
+            .execute_no_conn(&RedisCommand::INFO {
+                sections: vec!["replication".to_string()],
+            })
+            .await;
+
+        match result {
+            RedisType::BulkString { data } => {
+                assert!(data.contains(&format!("master_repl_offset:{}", written)));
+                assert!(data.contains("repl_backlog_active:1"));
+                assert!(data.contains("repl_backlog_size:16"));
+                assert!(data.contains("repl_backlog_histlen:16"));
+                assert!(data.contains(&format!(
+                    "repl_backlog_first_byte_offset:{}",
+                    written - 16 + 1
+                )));
+            }
+            _ => panic!("Result was not a bulk string"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_info() {
+        let runtime = RedisRuntime::default();
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::INFO {
+                sections: vec!["anything".to_string()],
+            })
+            .await;
+        assert_eq!(result, RedisType::bulk_string(""));
+    }
+
+    #[tokio::test]
+    async fn test_info_several_sections() {
+        let runtime = RedisRuntime::default();
+        let info = |sections: &[&str]| RedisCommand::INFO {
+            sections: sections.iter().map(|section| section.to_string()).collect(),
+        };
+        let bulk_data = |result: RedisType| match result {
+            RedisType::BulkString { data } => data,
+            other => panic!("Result was not a bulk string: {:?}", other),
+        };
+
+        let data = bulk_data(
+            runtime
+                .execute_no_conn(&info(&["replication", "anything", "SERVER"]))
+                .await,
+        );
+        assert!(data.starts_with("# Replication\nrole:master\n"));
+        assert!(data.contains("\n\n# Server\nredis_version:"));
+        assert!(data.contains("tcp_port:6379"));
+        assert!(!data.contains("# Stats"));
+
+        let data = bulk_data(runtime.execute_no_conn(&info(&[])).await);
+        assert!(data.starts_with("# Server\n"));
+        assert!(data.contains("# Replication"));
+        assert!(data.contains("# Stats"));
+        assert!(!data.contains("# Commandstats"));
+
+        let data = bulk_data(runtime.execute_no_conn(&info(&["all", "server"])).await);
+        assert_eq!(data.matches("# Server").count(), 1);
+        assert!(data.contains("# Commandstats"));
+    }
+
+    #[tokio::test]
+    async fn test_get_command_non_existing_key() {
+        let runtime = RedisRuntime::default();
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "key1".to_string(),
+            })
+            .await;
+        assert_eq!(result, RedisType::NullBulkString);
+    }
+
+    #[tokio::test]
+    async fn test_replconf() {
+        let runtime = RedisRuntime::default();
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::REPLCONF {
+                arg: ReplConfArgs::Port(1234),
+            })
+            .await;
+        assert_eq!(result, RedisType::simple_string("OK"));
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::REPLCONF {
+                arg: ReplConfArgs::Capabilities(vec!["psync2".to_string()]),
+            })
+            .await;
+        assert_eq!(result, RedisType::simple_string("OK"));
+    }
+
+    #[tokio::test]
+    async fn test_replconf_unknown_option() {
+        let runtime = RedisRuntime::default();
+        let command = RedisCommand::parse(&RedisType::list(vec![
+            RedisType::bulk_string("REPLCONF"),
+            RedisType::bulk_string("rdb-only"),
+            RedisType::bulk_string("0"),
+        ]))
+        .unwrap();
+
+        let result = runtime.execute_no_conn(&command).await;
+        assert_eq!(result, RedisType::simple_string("OK"));
+    }
+
+    #[tokio::test]
+    async fn test_same_keyspace_as() {
+        let runtime = RedisRuntime::default();
+        let ttl = Some(ExpiryOption::After(Duration::from_secs(10)));
+        for (key, expiry) in [("a", None), ("b", ttl)] {
+            runtime
+                .execute_no_conn(&RedisCommand::SET {
+                    key: key.to_string(),
+                    val: RedisType::bulk_string(key),
+                    expiry,
+                    condition: None,
+                })
+                .await;
+        }
+
+        let copy = RedisRuntime::default();
+        *copy.values.write().await = runtime.values.read().await.clone();
+        assert!(runtime.same_keyspace_as(&copy, Duration::ZERO).await);
+
+        // Re-setting the key moves its expiry slightly, which is fine within a tolerance
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        copy.execute_no_conn(&RedisCommand::SET {
+            key: "b".to_string(),
+            val: RedisType::bulk_string("b"),
+            expiry: Some(ExpiryOption::After(Duration::from_secs(10))),
+            condition: None,
+        })
+        .await;
+        assert!(!runtime.same_keyspace_as(&copy, Duration::ZERO).await);
+        assert!(
+            runtime
+                .same_keyspace_as(&copy, Duration::from_secs(1))
+                .await
+        );
+
+        copy.execute_no_conn(&RedisCommand::SET {
+            key: "a".to_string(),
+            val: RedisType::bulk_string("changed"),
+            expiry: None,
+            condition: None,
+        })
+        .await;
+        assert!(
+            !runtime
+                .same_keyspace_as(&copy, Duration::from_secs(1))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_append_command() {
+        let runtime = RedisRuntime::default();
+        let append = |value: &str| RedisCommand::APPEND {
+            key: "key".to_string(),
+            value: value.as_bytes().to_vec(),
+        };
+
+        let result = runtime.execute_no_conn(&append("Hello")).await;
+        assert_eq!(result, RedisType::integer(5));
+
+        let result = runtime.execute_no_conn(&append(" World")).await;
+        assert_eq!(result, RedisType::integer(11));
+
+        let value = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "key".to_string(),
+            })
+            .await;
+        assert_eq!(value, RedisType::bulk_string("Hello World"));
+    }
+
+    #[tokio::test]
+    async fn test_append_binary_value() {
+        let runtime = RedisRuntime::default();
+        let append = |value: &[u8]| RedisCommand::APPEND {
+            key: "key".to_string(),
+            value: value.to_vec(),
+        };
+        let get = RedisCommand::GET {
+            key: "key".to_string(),
+        };
+
+        // Text stays text until bytes that aren't UTF-8 are appended to it
+        assert_eq!(
+            runtime.execute_no_conn(&append(b"ab")).await,
+            RedisType::integer(2)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&append(&[0xff, 0x00])).await,
+            RedisType::integer(4)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&append(b"c")).await,
+            RedisType::integer(5)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&get).await,
+            RedisType::bulk_bytes(b"ab\xff\x00c")
+        );
+
+        runtime
+            .execute_no_conn(&RedisCommand::DEL {
+                keys: vec!["key".to_string()],
+            })
+            .await;
+        assert_eq!(
+            runtime.execute_no_conn(&append(&[0xfe])).await,
+            RedisType::integer(1)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&get).await,
+            RedisType::bulk_bytes(&[0xfe])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_append_many_times() {
+        let runtime = RedisRuntime::default();
+        let count = 100_000;
+
+        for _ in 0..count {
+            runtime
+                .execute_no_conn(&RedisCommand::APPEND {
+                    key: "key".to_string(),
+                    value: b"ab".to_vec(),
+                })
+                .await;
+        }
+
+        let guard = runtime.values.read().await;
+        let value = &guard.get("key").unwrap().value;
+        assert_eq!(value.string_bytes(), Some("ab".repeat(count).as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn test_append_to_expired_key() {
+        let runtime = RedisRuntime::default();
+        runtime.values.write().await.insert(
+            "key".to_string(),
+            ValueWithExpiry {
+                value: StoredValue::string(RedisType::bulk_string("old")),
+                expiry: Some(Instant::now() - Duration::from_millis(1)),
+            },
+        );
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::APPEND {
+                key: "key".to_string(),
+                value: b"new".to_vec(),
+            })
+            .await;
+        assert_eq!(result, RedisType::integer(3));
+        assert!(runtime
+            .values
+            .read()
+            .await
+            .get("key")
+            .unwrap()
+            .expiry
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_append_keeps_ttl() {
+        let runtime = RedisRuntime::default();
+        let expiry = Instant::now() + Duration::from_secs(60);
+        runtime.values.write().await.insert(
+            "key".to_string(),
+            ValueWithExpiry {
+                value: StoredValue::string(RedisType::bulk_string("old")),
+                expiry: Some(expiry),
+            },
+        );
+
+        runtime
+            .execute_no_conn(&RedisCommand::APPEND {
+                key: "key".to_string(),
+                value: b"new".to_vec(),
+            })
+            .await;
+        let guard = runtime.values.read().await;
+        let value = guard.get("key").unwrap();
+        assert_eq!(
+            value.value,
+            StoredValue::String {
+                value: RedisType::bulk_string("oldnew"),
+                raw: true
+            }
+        );
+        assert_eq!(value.expiry, Some(expiry));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_get_and_set() {
+        let runtime = Arc::new(RedisRuntime::default());
+        let set = |key: &str, value: &str| RedisCommand::SET {
+            key: key.to_string(),
+            val: RedisType::bulk_string(value),
+            expiry: None,
+            condition: None,
+        };
+        let get = |key: &str| RedisCommand::GET {
+            key: key.to_string(),
+        };
+        runtime.execute_no_conn(&set("shared", "0")).await;
+
+        let tasks: Vec<_> = (0..8)
+            .map(|task| {
+                let runtime = Arc::clone(&runtime);
+                tokio::spawn(async move {
+                    for i in 0..500 {
+                        let key = format!("key:{}:{}", task, i);
+                        runtime.execute_no_conn(&set(&key, &i.to_string())).await;
+                        runtime
+                            .execute_no_conn(&set("shared", &task.to_string()))
+                            .await;
+
+                        assert_eq!(
+                            runtime.execute_no_conn(&get(&key)).await,
+                            RedisType::bulk_string(&i.to_string())
+                        );
+                        // Some task's write, never a torn or missing value
+                        let shared = runtime.execute_no_conn(&get("shared")).await;
+                        let shared: usize = shared.extract_string().unwrap().parse().unwrap();
+                        assert!(shared < 8);
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(
+            runtime.execute_no_conn(&RedisCommand::DBSIZE).await,
+            RedisType::integer(8 * 500 + 1)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_with_value_mut_concurrent_updates() {
+        let runtime = Arc::new(RedisRuntime::default());
+        let increment = |value: &mut Option<StoredValue>| {
+            let current: i64 = value
+                .as_ref()
+                .and_then(|value| {
+                    std::str::from_utf8(value.string_bytes()?)
+                        .ok()?
+                        .parse()
+                        .ok()
+                })
+                .unwrap_or(0);
+            *value = Some(StoredValue::string(RedisType::bulk_string(
+                &(current + 1).to_string(),
+            )));
+        };
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let runtime = Arc::clone(&runtime);
+                tokio::spawn(async move {
+                    for _ in 0..250 {
+                        runtime.with_value_mut("counter", false, increment).await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let value = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "counter".to_string(),
+            })
+            .await;
+        assert_eq!(value, RedisType::bulk_string("2000"));
+    }
+
+    #[tokio::test]
+    async fn test_debug_stringmatch_len() {
+        let runtime = RedisRuntime::default();
+        let stringmatch = |pattern: &str, string: &str| RedisCommand::DEBUG {
+            arg: DebugArgs::StringMatchLen {
+                pattern: pattern.to_string(),
+                string: string.to_string(),
+            },
+        };
+
+        let result = runtime
+            .execute_no_conn(&stringmatch("user:[0-9]*", "user:42"))
+            .await;
+        assert_eq!(result, RedisType::integer(1));
+
+        let result = runtime
+            .execute_no_conn(&stringmatch("user:\\*", "user:42"))
+            .await;
+        assert_eq!(result, RedisType::integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_replica_does_not_delete_expired_keys() {
+        let runtime = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6379".parse().unwrap()),
+            ..Default::default()
+        });
+        runtime.values.write().await.insert(
+            "key".to_string(),
+            ValueWithExpiry {
+                value: StoredValue::string(RedisType::bulk_string("value")),
+                expiry: Some(Instant::now() - Duration::from_millis(1)),
+            },
+        );
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "key".to_string(),
+            })
+            .await;
+        assert_eq!(result, RedisType::NullBulkString);
+        assert!(runtime.values.read().await.contains_key("key"));
+        assert!(runtime.take_propagated().is_empty());
+
+        // Other reads don't see it either
+        let result = runtime
+            .execute_no_conn(&RedisCommand::TYPE {
+                key: "key".to_string(),
+            })
+            .await;
+        assert_eq!(result, RedisType::simple_string("none"));
+        let result = runtime
+            .execute_no_conn(&RedisCommand::GETRANGE {
+                key: "key".to_string(),
+                start: 0,
+                end: -1,
+            })
+            .await;
+        assert_eq!(result, RedisType::bulk_string(""));
+
+        // A write propagated by the master still sees the key
+        let result = runtime
+            .execute_from_master(&RedisCommand::APPEND {
+                key: "key".to_string(),
+                value: b"!".to_vec(),
+            })
+            .await;
+        assert_eq!(result, RedisType::integer(6));
+    }
+
+    #[tokio::test]
+    async fn test_writable_replica_clients_see_expired_keys_as_missing() {
+        let runtime = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6379".parse().unwrap()),
+            replica_read_only: false,
+            ..Default::default()
+        });
+        for key in ["counter", "nx", "append"] {
+            runtime.values.write().await.insert(
+                key.to_string(),
+                ValueWithExpiry {
+                    value: StoredValue::string(RedisType::bulk_string("5")),
+                    expiry: Some(Instant::now() - Duration::from_millis(1)),
+                },
+            );
+        }
+
+        // Only the master's writes see the keys still, a client's start from scratch
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::INCR {
+                    key: "counter".to_string(),
+                })
+                .await,
+            RedisType::integer(1)
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::SET {
+                    key: "nx".to_string(),
+                    val: RedisType::bulk_string("new"),
+                    expiry: None,
+                    condition: Some(Condition::Nx),
+                })
+                .await,
+            RedisType::simple_string("OK")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::APPEND {
+                    key: "append".to_string(),
+                    value: b"!".to_vec(),
+                })
+                .await,
+            RedisType::integer(1)
+        );
+
+        for (key, value) in [("counter", "1"), ("nx", "new"), ("append", "!")] {
+            assert_eq!(
+                runtime
+                    .execute_no_conn(&RedisCommand::GET {
+                        key: key.to_string(),
+                    })
+                    .await,
+                RedisType::bulk_string(value)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_master_deletes_expired_keys_on_read() {
+        let runtime = RedisRuntime::default();
+        runtime.values.write().await.insert(
+            "key".to_string(),
+            ValueWithExpiry {
+                value: StoredValue::string(RedisType::bulk_string("value")),
+                expiry: Some(Instant::now() - Duration::from_millis(1)),
+            },
+        );
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "key".to_string(),
+            })
+            .await;
+        assert_eq!(result, RedisType::NullBulkString);
+        assert!(!runtime.values.read().await.contains_key("key"));
+
+        // Replicas are told, as they keep expired keys until the master deletes them
+        let del = |key: &str| RedisCommand::DEL {
+            keys: vec![key.to_string()],
+        };
+        assert_eq!(runtime.take_propagated(), vec![del("key")]);
+
+        runtime.values.write().await.insert(
+            "other".to_string(),
+            ValueWithExpiry {
+                value: StoredValue::string(RedisType::bulk_string("value")),
+                expiry: Some(Instant::now() - Duration::from_millis(1)),
+            },
+        );
+        let result = runtime
+            .execute_no_conn(&RedisCommand::EXISTS {
+                keys: vec!["other".to_string(), "missing".to_string()],
+            })
+            .await;
+        assert_eq!(result, RedisType::integer(0));
+        assert_eq!(runtime.take_propagated(), vec![del("other")]);
+    }
+
+    #[tokio::test]
+    async fn test_reads_treat_expired_keys_as_missing() {
+        let runtime = RedisRuntime::default();
+        let mut set = SortedSet::default();
+        set.insert("member", 1.0);
+        for (key, value) in [
+            (
+                "string",
+                StoredValue::string(RedisType::bulk_string("value")),
+            ),
+            ("zset", StoredValue::SortedSet(set)),
+        ] {
+            runtime.values.write().await.insert(
+                key.to_string(),
+                ValueWithExpiry {
+                    value,
+                    expiry: Some(Instant::now() - Duration::from_millis(1)),
+                },
+            );
+        }
+
+        let reads = [
+            (
+                RedisCommand::GETRANGE {
+                    key: "string".to_string(),
+                    start: 0,
+                    end: -1,
+                },
+                RedisType::bulk_string(""),
+            ),
+            (
+                RedisCommand::TYPE {
+                    key: "string".to_string(),
+                },
+                RedisType::simple_string("none"),
+            ),
+            (
+                RedisCommand::OBJECT {
+                    arg: ObjectArgs::Encoding {
+                        key: "string".to_string(),
+                    },
+                },
+                RedisType::NullBulkString,
+            ),
+            (
+                RedisCommand::MEMORY {
+                    arg: MemoryArgs::Usage {
+                        key: "string".to_string(),
+                        samples: None,
+                    },
+                },
+                RedisType::NullBulkString,
+            ),
+            (
+                RedisCommand::GEOPOS {
+                    key: "zset".to_string(),
+                    members: vec!["member".to_string()],
+                },
+                RedisType::list(vec![RedisType::NullArray]),
+            ),
+        ];
+        for (command, missing) in reads {
+            assert_eq!(
+                runtime.execute_no_conn(&command).await,
+                missing,
+                "{:?}",
+                command
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_replication_role() {
+        let runtime = RedisRuntime::default();
+        runtime.backlog.lock().await.activate();
+        runtime
+            .replicate_command(
+                &RedisCommand::SET {
+                    key: "key".to_string(),
+                    val: RedisType::bulk_string("value"),
+                    expiry: None,
+                    condition: None,
+                },
+                &RedisType::simple_string("OK"),
+            )
+            .await
+            .unwrap();
+        let old_id = runtime.replication.read().unwrap().id.clone();
+
+        runtime
+            .set_replication_role(Some("127.0.0.1:6380".parse().unwrap()))
+            .await;
+        assert!(!runtime.is_master());
+
+        let info = runtime
+            .execute_no_conn(&RedisCommand::INFO {
+                sections: vec!["replication".to_string()],
+            })
+            .await;
+        match info {
+            RedisType::BulkString { data } => {
+                assert!(data.contains("role:slave"));
+                assert!(data.contains("master_repl_offset:0"));
+                assert!(data.contains("repl_backlog_active:0"));
+                assert!(!data.contains(&old_id));
+            }
+            _ => panic!("Result was not a bulk string"),
+        }
+
+        runtime.set_replication_role(None).await;
+        assert!(runtime.is_master());
+    }
+
+    #[tokio::test]
+    async fn test_object_encoding() {
+        let runtime = RedisRuntime::default();
+        let encoding = |key: &str| RedisCommand::OBJECT {
+            arg: ObjectArgs::Encoding {
+                key: key.to_string(),
+            },
+        };
+        let values = [
+            ("int", "12345".to_string()),
+            ("negative", "-9223372036854775808".to_string()),
+            ("padded", "007".to_string()),
+            ("embstr", "a".repeat(44)),
+            ("raw", "a".repeat(45)),
+        ];
+
+        for (key, value) in &values {
+            runtime
+                .execute_no_conn(&RedisCommand::SET {
+                    key: key.to_string(),
+                    val: RedisType::bulk_string(value),
+                    expiry: None,
+                    condition: None,
+                })
+                .await;
+        }
+
+        let expected = [
+            ("int", "int"),
+            ("negative", "int"),
+            ("padded", "embstr"),
+            ("embstr", "embstr"),
+            ("raw", "raw"),
+        ];
+        for (key, expected) in expected {
+            assert_eq!(
+                runtime.execute_no_conn(&encoding(key)).await,
+                RedisType::bulk_string(expected),
+                "Wrong encoding for {}",
+                key
+            );
+        }
+        assert_eq!(
+            runtime.execute_no_conn(&encoding("missing")).await,
+            RedisType::NullBulkString
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_nx_and_xx() {
+        let runtime = RedisRuntime::default();
+        let set = |value: &str, condition: Condition| RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string(value),
+            expiry: None,
+            condition: Some(condition),
+        };
+        let get = RedisCommand::GET {
+            key: "key".to_string(),
+        };
+
+        assert_eq!(
+            runtime.execute_no_conn(&set("first", Condition::Xx)).await,
+            RedisType::NullBulkString
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&get).await,
+            RedisType::NullBulkString
+        );
+
+        assert_eq!(
+            runtime.execute_no_conn(&set("first", Condition::Nx)).await,
+            RedisType::simple_string("OK")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&set("second", Condition::Nx)).await,
+            RedisType::NullBulkString
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&set("third", Condition::Xx)).await,
+            RedisType::simple_string("OK")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&get).await,
+            RedisType::bulk_string("third")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_writes_to_expired_keys_keep_replicas_in_sync() {
+        let master = RedisRuntime::default();
+        let replica = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6379".parse().unwrap()),
+            ..Default::default()
+        });
+        for runtime in [&master, &replica] {
+            runtime.values.write().await.insert(
+                "counter".to_string(),
+                ValueWithExpiry {
+                    value: StoredValue::string(RedisType::bulk_string("10")),
+                    expiry: Some(Instant::now() - Duration::from_millis(1)),
+                },
+            );
+        }
+        let incr = RedisCommand::INCR {
+            key: "counter".to_string(),
+        };
+
+        let result = master.execute_no_conn(&incr).await;
+        assert_eq!(result, RedisType::integer(1));
+        master.replicate_command(&incr, &result).await.unwrap();
+
+        // The replica still has the expired key, so it has to delete it before the INCR
+        let propagated = master.take_propagated();
+        assert_eq!(
+            propagated,
+            vec![
+                RedisCommand::DEL {
+                    keys: vec!["counter".to_string()]
+                },
+                incr
+            ]
+        );
+        for command in &propagated {
+            replica.execute_from_master(command).await;
+        }
+
+        let get = RedisCommand::GET {
+            key: "counter".to_string(),
+        };
+        assert_eq!(
+            replica.execute_no_conn(&get).await,
+            master.execute_no_conn(&get).await
+        );
+        assert_eq!(
+            replica.execute_no_conn(&get).await,
+            RedisType::bulk_string("1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_with_ttl_propagates_absolute_expiry() {
+        let runtime = RedisRuntime::default();
+        let set = RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry: Some(ExpiryOption::After(Duration::from_millis(1500))),
+            condition: None,
+        };
+        let get = RedisCommand::GET {
+            key: "key".to_string(),
+        };
+
+        let before = SystemTime::now();
+        for command in [&set, &get] {
+            let result = runtime.execute_no_conn(command).await;
+            runtime.replicate_command(command, &result).await.unwrap();
+        }
+        let after = SystemTime::now();
+
+        let propagated = runtime.take_propagated();
+        assert_eq!(propagated.len(), 1, "Only writes are propagated");
+        match &propagated[0] {
+            RedisCommand::SET {
+                key,
+                val,
+                expiry: Some(ExpiryOption::At(at)),
+                condition: None,
+            } => {
+                assert_eq!(
+                    (key.as_str(), val),
+                    ("key", &RedisType::bulk_string("value"))
+                );
+                assert!(*at >= before + Duration::from_millis(1500));
+                assert!(*at <= after + Duration::from_millis(1500));
+            }
+            other => panic!("Expected SET with PXAT, got {:?}", other),
+        }
+        assert!(propagated[0]
+            .write_as_protocol()
+            .windows(4)
+            .any(|window| window == b"PXAT"));
+
+        // Replicas pass nothing on
+        runtime
+            .set_replication_role(Some("127.0.0.1:6379".parse().unwrap()))
+            .await;
+        runtime
+            .replicate_command(&set, &RedisType::simple_string("OK"))
+            .await
+            .unwrap();
+        assert!(runtime.take_propagated().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_conditional_set_propagates_its_outcome() {
+        let runtime = RedisRuntime::default();
+        let set = |value: &str, condition| RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string(value),
+            expiry: None,
+            condition: Some(condition),
+        };
+
+        for command in [
+            set("first", Condition::Nx),
+            set("second", Condition::Nx),
+            set("third", Condition::Xx),
+        ] {
+            let result = runtime.execute_no_conn(&command).await;
+            runtime.replicate_command(&command, &result).await.unwrap();
+        }
+
+        // The SET held back by NX isn't sent, and replicas run the others without a condition
+        let unconditional = |value: &str| RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string(value),
+            expiry: None,
+            condition: None,
+        };
+        assert_eq!(
+            runtime.take_propagated(),
+            vec![unconditional("first"), unconditional("third")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xadd_propagates_generated_id() {
+        let runtime = RedisRuntime::default();
+        let xadd = |id: &str| RedisCommand::XADD {
+            key: "stream".to_string(),
+            id: XAddId::parse(id).unwrap(),
+            fields: vec![("field".to_string(), "value".to_string())],
+        };
+
+        for command in [xadd("5-*"), xadd("5-*"), xadd("*"), xadd("0-1")] {
+            let result = runtime.execute_no_conn(&command).await;
+            runtime.replicate_command(&command, &result).await.unwrap();
+        }
+
+        let propagated = runtime.take_propagated();
+        let generated = match &propagated[2] {
+            RedisCommand::XADD {
+                id: XAddId::Explicit(id),
+                ..
+            } => *id,
+            other => panic!("Expected XADD with an explicit id, got {:?}", other),
+        };
+        assert!(generated.ms > 0);
+        assert_eq!(
+            propagated,
+            vec![xadd("5-0"), xadd("5-1"), xadd(&generated.to_string()),]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_keyspace_is_not_propagated() {
+        let runtime = RedisRuntime::default();
+        let pfadd = |element: &str| RedisCommand::PFADD {
+            key: "hll".to_string(),
+            elements: vec![element.to_string()],
+        };
+        let incr = RedisCommand::INCR {
+            key: "hll".to_string(),
+        };
+
+        // Adding the same element again changes nothing, and INCR on a HyperLogLog fails
+        for command in [pfadd("a"), pfadd("a"), incr.clone(), pfadd("b")] {
+            let result = runtime.execute_no_conn(&command).await;
+            runtime.replicate_command(&command, &result).await.unwrap();
+        }
+
+        assert_eq!(runtime.take_propagated(), vec![pfadd("a"), pfadd("b")]);
+    }
+
+    #[tokio::test]
+    async fn test_panic_is_isolated() {
+        let runtime = Arc::new(RedisRuntime::default());
+
+        // Panicking with the keyspace write lock held
+        let panicking = Arc::clone(&runtime);
+        let result = isolate(async move {
+            let _guard = panicking.write_values().await;
+            panic!("Handler bug");
+        })
+        .await;
+        assert_eq!(result, None::<()>);
+
+        // The lock was released, so commands still run
+        let set = RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry: None,
+            condition: None,
+        };
+        let get = RedisCommand::GET {
+            key: "key".to_string(),
+        };
+        assert_eq!(
+            runtime.execute_isolated(&set, None, false).await,
+            RedisType::simple_string("OK")
+        );
+        assert_eq!(
+            runtime.execute_isolated(&get, None, false).await,
+            RedisType::bulk_string("value")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_panic_while_changing_a_value_keeps_the_key() {
+        let runtime = RedisRuntime::default();
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "key".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: Some(ExpiryOption::After(Duration::from_secs(60))),
+                condition: None,
+            })
+            .await;
+
+        let result = isolate(runtime.with_value_mut("key", false, |_| panic!("Handler bug"))).await;
+        assert_eq!(result, None::<()>);
+
+        // Still there with its TTL, which the index still knows about
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::GET {
+                    key: "key".to_string(),
+                })
+                .await,
+            RedisType::bulk_string("value")
+        );
+        let expiry = runtime.values.read().await["key"].expiry.unwrap();
+        assert!(runtime
+            .expiry_index
+            .lock()
+            .unwrap()
+            .contains(&(expiry, "key".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_string_encodings() {
+        let runtime = RedisRuntime::default();
+        let set = |value: String| RedisCommand::SET {
+            key: "string".to_string(),
+            val: RedisType::bulk_string(&value),
+            expiry: None,
+            condition: None,
+        };
+
+        assert_eq!(runtime.encoding_of("string").await, None);
+        runtime.execute_no_conn(&set("42".to_string())).await;
+        assert_eq!(runtime.encoding_of("string").await, Some("int"));
+        runtime.execute_no_conn(&set("a".repeat(44))).await;
+        assert_eq!(runtime.encoding_of("string").await, Some("embstr"));
+
+        // Growing past the embstr limit makes it raw
+        runtime
+            .execute_no_conn(&RedisCommand::APPEND {
+                key: "string".to_string(),
+                value: b"a".to_vec(),
+            })
+            .await;
+        assert_eq!(runtime.encoding_of("string").await, Some("raw"));
+    }
+
+    #[tokio::test]
+    async fn test_append_to_integer() {
+        let runtime = RedisRuntime::default();
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "number".to_string(),
+                val: RedisType::bulk_string("123"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(runtime.encoding_of("number").await, Some("int"));
+
+        // Still digits, but an appended string stays raw like in Redis
+        let result = runtime
+            .execute_no_conn(&RedisCommand::APPEND {
+                key: "number".to_string(),
+                value: b"4".to_vec(),
+            })
+            .await;
+        assert_eq!(result, RedisType::integer(4));
+        assert_eq!(runtime.encoding_of("number").await, Some("raw"));
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::GET {
+                    key: "number".to_string(),
+                })
+                .await,
+            RedisType::bulk_string("1234")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::GETRANGE {
+                    key: "number".to_string(),
+                    start: 1,
+                    end: -1,
+                })
+                .await,
+            RedisType::bulk_string("234")
+        );
+
+        // A value created by APPEND is encoded from its contents, and SET starts over
+        runtime
+            .execute_no_conn(&RedisCommand::APPEND {
+                key: "created".to_string(),
+                value: b"99".to_vec(),
+            })
+            .await;
+        assert_eq!(runtime.encoding_of("created").await, Some("int"));
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "number".to_string(),
+                val: RedisType::bulk_string("5"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(runtime.encoding_of("number").await, Some("int"));
+    }
+
+    #[test]
+    fn test_format_double() {
+        assert_eq!(format_double(3.0), "3");
+        assert_eq!(format_double(2.75), "2.75");
+        assert_eq!(format_double(-0.1 - 0.2), "-0.30000000000000004");
+        assert_eq!(format_double(f64::INFINITY), "inf");
+        assert_eq!(format_double(f64::NEG_INFINITY), "-inf");
+        assert_eq!(format_double(1e16), "10000000000000000");
+        assert_eq!(format_double(1e17), "1e+17");
+        assert_eq!(format_double(0.0001), "0.0001");
+        assert_eq!(format_double(1.5e-5), "1.5e-05");
+        assert_eq!(format_double(-2.5e300), "-2.5e+300");
+    }
+
+    #[tokio::test]
+    async fn test_incr_and_decr() {
+        let runtime = RedisRuntime::default();
+        let incr = RedisCommand::INCR {
+            key: "counter".to_string(),
+        };
+        let decr = RedisCommand::DECR {
+            key: "counter".to_string(),
+        };
+        let set = |value: &str| RedisCommand::SET {
+            key: "counter".to_string(),
+            val: RedisType::bulk_string(value),
+            expiry: Some(ExpiryOption::After(Duration::from_secs(100))),
+            condition: None,
+        };
+
+        // A missing key starts at 0
+        assert_eq!(runtime.execute_no_conn(&incr).await, RedisType::integer(1));
+        assert_eq!(runtime.execute_no_conn(&decr).await, RedisType::integer(0));
+        assert_eq!(runtime.execute_no_conn(&decr).await, RedisType::integer(-1));
+
+        runtime.execute_no_conn(&set("41")).await;
+        assert_eq!(runtime.execute_no_conn(&incr).await, RedisType::integer(42));
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::GET {
+                    key: "counter".to_string()
+                })
+                .await,
+            RedisType::bulk_string("42")
+        );
+        // The TTL is kept, and the result is stored as an integer
+        assert_eq!(runtime.expiry_index.lock().unwrap().len(), 1);
+        assert_eq!(runtime.encoding_of("counter").await, Some("int"));
+
+        for value in ["abc", "1.5", " 1", "01"] {
+            runtime.execute_no_conn(&set(value)).await;
+            assert_eq!(
+                runtime.execute_no_conn(&incr).await,
+                RedisType::simple_error("ERR value is not an integer or out of range")
+            );
+        }
+
+        runtime.execute_no_conn(&set(&i64::MAX.to_string())).await;
+        assert_eq!(
+            runtime.execute_no_conn(&incr).await,
+            RedisType::simple_error("ERR increment or decrement would overflow")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&decr).await,
+            RedisType::integer(i64::MAX - 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incrby_and_decrby() {
+        let runtime = RedisRuntime::default();
+        let incrby = |delta: i64| RedisCommand::INCRBY {
+            key: "counter".to_string(),
+            delta,
+        };
+        let decrby = |delta: i64| RedisCommand::DECRBY {
+            key: "counter".to_string(),
+            delta,
+        };
+
+        assert_eq!(
+            runtime.execute_no_conn(&incrby(5)).await,
+            RedisType::integer(5)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&decrby(15)).await,
+            RedisType::integer(-10)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&incrby(i64::MAX)).await,
+            RedisType::integer(i64::MAX - 10)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&incrby(11)).await,
+            RedisType::simple_error("ERR increment or decrement would overflow")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&decrby(i64::MIN)).await,
+            RedisType::simple_error("ERR decrement would overflow")
+        );
+        // Failed increments leave the value alone
+        assert_eq!(
+            runtime.execute_no_conn(&incrby(0)).await,
+            RedisType::integer(i64::MAX - 10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_del() {
+        let runtime = RedisRuntime::default();
+        let set = |key: &str, expiry: Option<ExpiryOption>| RedisCommand::SET {
+            key: key.to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry,
+            condition: None,
+        };
+
+        runtime.execute_no_conn(&set("a", None)).await;
+        runtime
+            .execute_no_conn(&set(
+                "b",
+                Some(ExpiryOption::After(Duration::from_secs(100))),
+            ))
+            .await;
+        let del = RedisCommand::DEL {
+            keys: vec!["a".to_string(), "b".to_string(), "missing".to_string()],
+        };
+        assert_eq!(runtime.execute_no_conn(&del).await, RedisType::integer(2));
+        assert_eq!(
+            runtime.execute_no_conn(&RedisCommand::DBSIZE).await,
+            RedisType::integer(0)
+        );
+        // The TTL went with the key
+        assert!(runtime.expiry_index.lock().unwrap().is_empty());
+        assert_eq!(runtime.execute_no_conn(&del).await, RedisType::integer(0));
+
+        // Expired keys don't count as deleted
+        runtime
+            .execute_no_conn(&set(
+                "a",
+                Some(ExpiryOption::After(Duration::from_millis(1))),
+            ))
+            .await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(runtime.execute_no_conn(&del).await, RedisType::integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_exists() {
+        let runtime = RedisRuntime::default();
+        let exists = |keys: &[&str]| RedisCommand::EXISTS {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        };
+
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "foo".to_string(),
+                val: RedisType::bulk_string("bar"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(
+            runtime.execute_no_conn(&exists(&["foo", "foo"])).await,
+            RedisType::integer(2)
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&exists(&["foo", "missing", "foo"]))
+                .await,
+            RedisType::integer(2)
+        );
+
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "temporary".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: Some(ExpiryOption::After(Duration::from_millis(1))),
+                condition: None,
+            })
+            .await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(
+            runtime
+                .execute_no_conn(&exists(&["temporary", "foo", "temporary"]))
+                .await,
+            RedisType::integer(1)
+        );
+        // It was deleted on the way, so only foo is left
+        assert_eq!(
+            runtime.execute_no_conn(&RedisCommand::DBSIZE).await,
+            RedisType::integer(1)
+        );
+        assert!(runtime.expiry_index.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_type_of_every_kind() {
+        let runtime = RedisRuntime::default();
+        let type_of = |key: &str| RedisCommand::TYPE {
+            key: key.to_string(),
+        };
+
+        runtime
+            .execute_no_conn(&RedisCommand::XADD {
+                key: "stream".to_string(),
+                id: XAddId::Auto,
+                fields: vec![("field".to_string(), "value".to_string())],
+            })
+            .await;
+        runtime
+            .execute_no_conn(&RedisCommand::PFADD {
+                key: "hll".to_string(),
+                elements: vec!["a".to_string()],
+            })
+            .await;
+        runtime
+            .execute_no_conn(&RedisCommand::GEOADD {
+                key: "geo".to_string(),
+                members: vec![(Point { lon: 1.0, lat: 2.0 }, "member".to_string())],
+            })
+            .await;
+        for (key, expiry) in [
+            ("string", None),
+            (
+                "expired",
+                Some(ExpiryOption::After(Duration::from_millis(1))),
+            ),
+        ] {
+            runtime
+                .execute_no_conn(&RedisCommand::SET {
+                    key: key.to_string(),
+                    val: RedisType::bulk_string("value"),
+                    expiry,
+                    condition: None,
+                })
+                .await;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        for (key, expected) in [
+            ("string", "string"),
+            ("stream", "stream"),
+            ("hll", "string"),
+            ("geo", "zset"),
+            ("expired", "none"),
+            ("missing", "none"),
+        ] {
+            assert_eq!(
+                runtime.execute_no_conn(&type_of(key)).await,
+                RedisType::simple_string(expected),
+                "Wrong type for {}",
+                key
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_encoding() {
+        let runtime = RedisRuntime::default();
+        for _ in 0..200 {
+            runtime
+                .execute_no_conn(&RedisCommand::XADD {
+                    key: "stream".to_string(),
+                    id: XAddId::Auto,
+                    fields: vec![("field".to_string(), "value".to_string())],
+                })
+                .await;
+        }
+
+        // Streams have a single encoding whatever their size
+        assert_eq!(runtime.encoding_of("stream").await, Some("stream"));
+    }
+
+    #[tokio::test]
+    async fn test_sorted_set_encodings() {
+        let runtime = RedisRuntime::default();
+        let geoadd = |key: &str, members: Vec<String>| RedisCommand::GEOADD {
+            key: key.to_string(),
+            members: members
+                .into_iter()
+                .map(|member| (Point { lon: 1.0, lat: 2.0 }, member))
+                .collect(),
+        };
+
+        runtime
+            .execute_no_conn(&geoadd("small", (0..128).map(|i| i.to_string()).collect()))
+            .await;
+        assert_eq!(runtime.encoding_of("small").await, Some("listpack"));
+        runtime
+            .execute_no_conn(&geoadd("small", vec!["one more".to_string()]))
+            .await;
+        assert_eq!(runtime.encoding_of("small").await, Some("skiplist"));
+
+        runtime
+            .execute_no_conn(&geoadd("long", vec!["a".repeat(64)]))
+            .await;
+        assert_eq!(runtime.encoding_of("long").await, Some("listpack"));
+        runtime
+            .execute_no_conn(&geoadd("long", vec!["a".repeat(65)]))
+            .await;
+        assert_eq!(runtime.encoding_of("long").await, Some("skiplist"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_commands() {
+        let runtime = RedisRuntime::default();
+        let xadd = |id: &str, value: &str| RedisCommand::XADD {
+            key: "stream".to_string(),
+            id: XAddId::parse(id).unwrap(),
+            fields: vec![("n".to_string(), value.to_string())],
+        };
+        let xlen = RedisCommand::XLEN {
+            key: "stream".to_string(),
+        };
+
+        assert_eq!(runtime.execute_no_conn(&xlen).await, RedisType::integer(0));
+        // A rejected first entry doesn't create the key
+        assert_eq!(
+            runtime.execute_no_conn(&xadd("0-0", "x")).await,
+            RedisType::simple_error("ERR The ID specified in XADD must be greater than 0-0")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&RedisCommand::DBSIZE).await,
+            RedisType::integer(0)
+        );
+
+        for (id, expected) in [("1-1", "1-1"), ("1-*", "1-2"), ("2", "2-0"), ("3-*", "3-0")] {
+            assert_eq!(
+                runtime.execute_no_conn(&xadd(id, expected)).await,
+                RedisType::bulk_string(expected)
+            );
+        }
+        assert_eq!(
+            runtime.execute_no_conn(&xadd("2-5", "x")).await,
+            RedisType::simple_error(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+            )
+        );
+
+        let mut previous = StreamId::new(3, 0);
+        for _ in 0..10 {
+            let id = match runtime.execute_no_conn(&xadd("*", "auto")).await {
+                RedisType::BulkString { data } => StreamId::parse(&data, 0).unwrap(),
+                other => panic!("XADD did not reply with an id: {:?}", other),
+            };
+            assert!(id > previous);
+            previous = id;
+        }
+        assert_eq!(runtime.execute_no_conn(&xlen).await, RedisType::integer(14));
+
+        let xrange = |start: &str, end: &str, count: Option<usize>| RedisCommand::XRANGE {
+            key: "stream".to_string(),
+            start: StreamId::parse_range_bound(start, true).unwrap(),
+            end: StreamId::parse_range_bound(end, false).unwrap(),
+            count,
+        };
+        let entry = |id: &str| {
+            RedisType::list(vec![
+                RedisType::bulk_string(id),
+                RedisType::list(vec![
+                    RedisType::bulk_string("n"),
+                    RedisType::bulk_string(id),
+                ]),
+            ])
+        };
+        assert_eq!(
+            runtime.execute_no_conn(&xrange("1-2", "2", None)).await,
+            RedisType::list(vec![entry("1-2"), entry("2-0")])
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&xrange("(1-1", "+", Some(2))).await,
+            RedisType::list(vec![entry("1-2"), entry("2-0")])
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::XRANGE {
+                    key: "missing".to_string(),
+                    start: StreamId::MIN,
+                    end: StreamId::MAX,
+                    count: None,
+                })
+                .await,
+            RedisType::list(vec![])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_xread() {
+        let runtime = Arc::new(RedisRuntime::default());
+        let xadd = |key: &str, id: &str| RedisCommand::XADD {
+            key: key.to_string(),
+            id: XAddId::parse(id).unwrap(),
+            fields: vec![("id".to_string(), id.to_string())],
+        };
+        let xread = |block: Option<u64>, streams: &[(&str, &str)]| RedisCommand::XREAD {
+            count: Some(10),
+            block: block.map(Duration::from_millis),
+            streams: streams
+                .iter()
+                .map(|(key, id)| (key.to_string(), XReadId::parse(id).unwrap()))
+                .collect(),
+        };
+        let reply = |streams: &[(&str, &[&str])]| {
+            RedisType::list(
+                streams
+                    .iter()
+                    .map(|(key, ids)| {
+                        RedisType::list(vec![
+                            RedisType::bulk_string(key),
+                            RedisType::list(
+                                ids.iter()
+                                    .map(|id| {
+                                        stream_entry(
+                                            &StreamId::parse(id, 0).unwrap(),
+                                            &vec![("id".to_string(), id.to_string())],
+                                        )
+                                    })
+                                    .collect(),
+                            ),
+                        ])
+                    })
+                    .collect(),
+            )
+        };
+
+        for (key, id) in [("a", "1-1"), ("a", "1-2"), ("b", "5-0")] {
+            runtime.execute_no_conn(&xadd(key, id)).await;
+        }
+        assert_eq!(
+            runtime
+                .execute_no_conn(&xread(None, &[("a", "1-1"), ("b", "0"), ("c", "0")]))
+                .await,
+            reply(&[("a", &["1-2"]), ("b", &["5-0"])])
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&xread(None, &[("a", "$")])).await,
+            RedisType::NullArray
+        );
+
+        // Times out to nil when nothing is added
+        let result = runtime
+            .execute_no_conn(&xread(Some(50), &[("a", "$")]))
+            .await;
+        assert_eq!(result, RedisType::NullArray);
+
+        // Woken up by an XADD from another client
+        let blocked = tokio::spawn({
+            let runtime = Arc::clone(&runtime);
+            async move {
+                runtime
+                    .execute_no_conn(&xread(Some(0), &[("b", "$"), ("a", "$")]))
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!blocked.is_finished());
+        runtime.execute_no_conn(&xadd("a", "2-0")).await;
+
+        let result = tokio::time::timeout(Duration::from_secs(1), blocked)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result, reply(&[("a", &["2-0"])]));
+    }
+
+    #[tokio::test]
+    async fn test_consumer_groups() {
+        let runtime = RedisRuntime::default();
+        let create = |key: &str, id: &str, mkstream: bool| RedisCommand::XGROUP {
+            arg: XGroupArgs::Create {
+                key: key.to_string(),
+                group: "group".to_string(),
+                id: XReadId::parse(id).unwrap(),
+                mkstream,
+            },
+        };
+        let read = |consumer: &str, id: &str| RedisCommand::XREADGROUP {
+            group: "group".to_string(),
+            consumer: consumer.to_string(),
+            count: None,
+            streams: vec![("stream".to_string(), XReadGroupId::parse(id).unwrap())],
+        };
+        let ack = |ids: &[&str]| RedisCommand::XACK {
+            key: "stream".to_string(),
+            group: "group".to_string(),
+            ids: ids
+                .iter()
+                .map(|id| StreamId::parse(id, 0).unwrap())
+                .collect(),
+        };
+        let entries = |ids: &[&str]| {
+            RedisType::list(vec![RedisType::list(vec![
+                RedisType::bulk_string("stream"),
+                RedisType::list(
+                    ids.iter()
+                        .map(|id| {
+                            stream_entry(
+                                &StreamId::parse(id, 0).unwrap(),
+                                &vec![("id".to_string(), id.to_string())],
+                            )
+                        })
+                        .collect(),
+                ),
+            ])])
+        };
+        let xadd = |id: &str| RedisCommand::XADD {
+            key: "stream".to_string(),
+            id: XAddId::parse(id).unwrap(),
+            fields: vec![("id".to_string(), id.to_string())],
+        };
+
+        assert_eq!(
+            runtime.execute_no_conn(&create("stream", "$", false)).await,
+            RedisType::simple_error("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&read("alice", ">")).await,
+            RedisType::simple_error(
+                "NOGROUP No such key 'stream' or consumer group 'group' in XREADGROUP with GROUP option"
+            )
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&create("stream", "$", true)).await,
+            RedisType::simple_string("OK")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&create("stream", "0", false)).await,
+            RedisType::simple_error("BUSYGROUP Consumer Group name already exists")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&read("alice", ">")).await,
+            RedisType::NullArray
+        );
+
+        for id in ["1-1", "1-2", "1-3"] {
+            runtime.execute_no_conn(&xadd(id)).await;
+        }
+        assert_eq!(
+            runtime.execute_no_conn(&read("alice", ">")).await,
+            entries(&["1-1", "1-2", "1-3"])
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&read("bob", ">")).await,
+            RedisType::NullArray
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&read("bob", "0")).await,
+            entries(&[])
+        );
+
+        // Acknowledged entries leave the consumer's pending entries
+        assert_eq!(
+            runtime.execute_no_conn(&ack(&["1-1", "1-3", "9-9"])).await,
+            RedisType::integer(2)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&ack(&["1-1"])).await,
+            RedisType::integer(0)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&read("alice", "0")).await,
+            entries(&["1-2"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xinfo() {
+        let runtime = RedisRuntime::default();
+        let info = |arg: XInfoArgs| RedisCommand::XINFO { arg };
+        let stream = || XInfoArgs::Stream {
+            key: "stream".to_string(),
+        };
+        let groups = || XInfoArgs::Groups {
+            key: "stream".to_string(),
+        };
+        let entry = |id: &str| {
+            stream_entry(
+                &StreamId::parse(id, 0).unwrap(),
+                &vec![("id".to_string(), id.to_string())],
+            )
+        };
+
+        assert_eq!(
+            runtime.execute_no_conn(&info(stream())).await,
+            RedisType::simple_error("ERR no such key")
+        );
+
+        for id in ["1-1", "1-2", "2-0"] {
+            runtime
+                .execute_no_conn(&RedisCommand::XADD {
+                    key: "stream".to_string(),
+                    id: XAddId::parse(id).unwrap(),
+                    fields: vec![("id".to_string(), id.to_string())],
+                })
+                .await;
+        }
+        assert_eq!(
+            runtime.execute_no_conn(&info(stream())).await,
+            RedisType::list(vec![
+                RedisType::bulk_string("length"),
+                RedisType::integer(3),
+                RedisType::bulk_string("last-generated-id"),
+                RedisType::bulk_string("2-0"),
+                RedisType::bulk_string("max-deleted-entry-id"),
+                RedisType::bulk_string("0-0"),
+                RedisType::bulk_string("entries-added"),
+                RedisType::integer(3),
+                RedisType::bulk_string("recorded-first-entry-id"),
+                RedisType::bulk_string("1-1"),
+                RedisType::bulk_string("groups"),
+                RedisType::integer(0),
+                RedisType::bulk_string("first-entry"),
+                entry("1-1"),
+                RedisType::bulk_string("last-entry"),
+                entry("2-0"),
+            ])
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&info(groups())).await,
+            RedisType::list(vec![])
+        );
+
+        runtime
+            .execute_no_conn(&RedisCommand::XGROUP {
+                arg: XGroupArgs::Create {
+                    key: "stream".to_string(),
+                    group: "group".to_string(),
+                    id: XReadId::After(StreamId::MIN),
+                    mkstream: false,
+                },
+            })
+            .await;
+        runtime
+            .execute_no_conn(&RedisCommand::XREADGROUP {
+                group: "group".to_string(),
+                consumer: "alice".to_string(),
+                count: Some(2),
+                streams: vec![("stream".to_string(), XReadGroupId::New)],
+            })
+            .await;
+        assert_eq!(
+            runtime.execute_no_conn(&info(groups())).await,
+            RedisType::list(vec![RedisType::list(vec![
+                RedisType::bulk_string("name"),
+                RedisType::bulk_string("group"),
+                RedisType::bulk_string("consumers"),
+                RedisType::integer(1),
+                RedisType::bulk_string("pending"),
+                RedisType::integer(2),
+                RedisType::bulk_string("last-delivered-id"),
+                RedisType::bulk_string("1-2"),
+                RedisType::bulk_string("entries-read"),
+                RedisType::integer(2),
+                RedisType::bulk_string("lag"),
+                RedisType::integer(1),
+            ])])
+        );
+
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "string".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(
+            runtime
+                .execute_no_conn(&info(XInfoArgs::Groups {
+                    key: "string".to_string(),
+                }))
+                .await,
+            RedisType::simple_error(WRONGTYPE_ERROR)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hyperloglog() {
+        let runtime = RedisRuntime::default();
+        let pfadd = |key: &str, elements: Vec<String>| RedisCommand::PFADD {
+            key: key.to_string(),
+            elements,
+        };
+        let pfcount = |keys: &[&str]| RedisCommand::PFCOUNT {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        };
+        let elements = |range: std::ops::Range<usize>| -> Vec<String> {
+            range.map(|i| format!("element:{}", i)).collect()
+        };
+
+        assert_eq!(
+            runtime
+                .execute_no_conn(&pfadd("a", elements(0..1000)))
+                .await,
+            RedisType::integer(1)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&pfadd("a", elements(0..10))).await,
+            RedisType::integer(0)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&pfadd("empty", vec![])).await,
+            RedisType::integer(1)
+        );
+        runtime
+            .execute_no_conn(&pfadd("b", elements(500..1500)))
+            .await;
+
+        let count = |reply: RedisType| match reply {
+            RedisType::Integer { data } => data as f64,
+            other => panic!("Expected an integer, got {:?}", other),
+        };
+        let a = count(runtime.execute_no_conn(&pfcount(&["a"])).await);
+        assert!((a - 1000.0).abs() / 1000.0 < 0.03, "Estimated {}", a);
+        let union = count(
+            runtime
+                .execute_no_conn(&pfcount(&["a", "b", "missing"]))
+                .await,
+        );
+        assert!(
+            (union - 1500.0).abs() / 1500.0 < 0.03,
+            "Estimated {}",
+            union
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&pfcount(&["empty", "missing"]))
+                .await,
+            RedisType::integer(0)
+        );
+
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::PFMERGE {
+                    dest: "a".to_string(),
+                    sources: vec!["b".to_string(), "missing".to_string()],
+                })
+                .await,
+            RedisType::simple_string("OK")
+        );
+        assert_eq!(
+            count(runtime.execute_no_conn(&pfcount(&["a"])).await),
+            union
+        );
+
+        // The value is a plain string, so it can be copied with GET and SET
+        let copy = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "a".to_string(),
+            })
+            .await;
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "copy".to_string(),
+                val: copy,
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(
+            count(runtime.execute_no_conn(&pfcount(&["copy"])).await),
+            union
+        );
+
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "string".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(
+            runtime
+                .execute_no_conn(&pfadd("string", elements(0..1)))
+                .await,
+            RedisType::simple_error("WRONGTYPE Key is not a valid HyperLogLog string value.")
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&pfcount(&["a", "string"])).await,
+            RedisType::simple_error("WRONGTYPE Key is not a valid HyperLogLog string value.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zadd() {
+        let runtime = RedisRuntime::default();
+        let zadd = |options: ZAddOptions, members: &[(f64, &str)]| RedisCommand::ZADD {
+            key: "zset".to_string(),
+            options,
+            members: members
+                .iter()
+                .map(|(score, member)| (Score(*score), member.to_string()))
+                .collect(),
+        };
+        let incr = |condition: Option<Condition>| ZAddOptions {
+            condition,
+            incr: true,
+            ..Default::default()
+        };
+        let pop_all = RedisCommand::ZMPOP {
+            keys: vec!["zset".to_string()],
+            end: PopEnd::Min,
+            count: 10,
+        };
+
+        // XX on a missing key adds nothing, and doesn't create it
+        let xx = ZAddOptions {
+            condition: Some(Condition::Xx),
+            ..Default::default()
+        };
+        assert_eq!(
+            runtime.execute_no_conn(&zadd(xx, &[(1.0, "a")])).await,
+            RedisType::integer(0)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&RedisCommand::DBSIZE).await,
+            RedisType::integer(0)
+        );
+
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(
+                    ZAddOptions::default(),
+                    &[(1.0, "a"), (f64::INFINITY, "b")]
+                ))
+                .await,
+            RedisType::integer(2)
+        );
+
+        // NX blocks the increment of an existing member, XX of a missing one
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(incr(Some(Condition::Nx)), &[(5.0, "a")]))
+                .await,
+            RedisType::NullBulkString
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(incr(Some(Condition::Nx)), &[(5.0, "c")]))
+                .await,
+            RedisType::bulk_string("5")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(incr(Some(Condition::Xx)), &[(1.0, "d")]))
+                .await,
+            RedisType::NullBulkString
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(incr(None), &[(0.5, "a")]))
+                .await,
+            RedisType::bulk_string("1.5")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(incr(None), &[(1e20, "f")]))
+                .await,
+            RedisType::bulk_string("1e+20")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(incr(None), &[(f64::NEG_INFINITY, "b")]))
+                .await,
+            RedisType::simple_error("ERR resulting score is not a number (NaN)")
+        );
+
+        // GT only raises scores, and CH counts the updates
+        let gt_ch = ZAddOptions {
+            comparison: Some(ScoreComparison::Gt),
+            changed: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zadd(gt_ch, &[(0.0, "a"), (9.0, "c"), (2.0, "e")]))
+                .await,
+            RedisType::integer(2)
+        );
+
+        let member = |member: &str, score: &str| {
+            RedisType::list(vec![
+                RedisType::bulk_string(member),
+                RedisType::bulk_string(score),
+            ])
+        };
+        assert_eq!(
+            runtime.execute_no_conn(&pop_all).await,
+            RedisType::list(vec![
+                RedisType::bulk_string("zset"),
+                RedisType::list(vec![
+                    member("a", "1.5"),
+                    member("e", "2"),
+                    member("c", "9"),
+                    member("f", "1e+20"),
+                    member("b", "inf"),
+                ]),
+            ])
+        );
+
+        // As sent by a client: infinite scores are fine, NaN is refused with its own error
+        let parse_zadd = |score: &str| {
+            RedisCommand::parse(&RedisType::list(vec![
+                RedisType::bulk_string("ZADD"),
+                RedisType::bulk_string("zset"),
+                RedisType::bulk_string(score),
+                RedisType::bulk_string("a"),
+            ]))
+        };
+        let command = parse_zadd("+inf").unwrap();
+        assert_eq!(
+            runtime.execute_no_conn(&command).await,
+            RedisType::integer(1)
+        );
+        assert_eq!(
+            parse_zadd("nan").unwrap_err().to_string(),
+            "ERR value is not a valid float"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zmpop() {
+        let runtime = RedisRuntime::default();
+        let zmpop = |keys: &[&str], end: PopEnd, count: usize| RedisCommand::ZMPOP {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            end,
+            count,
+        };
+        let popped = |key: &str, members: &[(&str, u64)]| {
+            RedisType::list(vec![
+                RedisType::bulk_string(key),
+                RedisType::list(
+                    members
+                        .iter()
+                        .map(|(member, score)| {
+                            RedisType::list(vec![
+                                RedisType::bulk_string(member),
+                                RedisType::bulk_string(&score.to_string()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ])
+        };
+
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zmpop(&["a", "b"], PopEnd::Min, 1))
+                .await,
+            RedisType::NullArray
+        );
+
+        // Scores are the geohashes
+        let origin = Point::parse("0", "0").unwrap();
+        let north = Point::parse("0", "10").unwrap();
+        let east = Point::parse("10", "0").unwrap();
+        runtime
+            .execute_no_conn(&RedisCommand::GEOADD {
+                key: "b".to_string(),
+                members: vec![
+                    (origin, "origin".to_string()),
+                    (north, "north".to_string()),
+                    (east, "east".to_string()),
+                ],
+            })
+            .await;
+        let mut by_score = [
+            ("origin", origin.geohash()),
+            ("north", north.geohash()),
+            ("east", east.geohash()),
+        ];
+        by_score.sort_by_key(|(_, score)| *score);
+
+        // The first key holding a set is popped from
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zmpop(&["a", "b"], PopEnd::Max, 1))
+                .await,
+            popped("b", &[by_score[2]])
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zmpop(&["a", "b"], PopEnd::Min, 5))
+                .await,
+            popped("b", &[by_score[0], by_score[1]])
+        );
+        // Popping the last member deleted the key
+        assert_eq!(
+            runtime.execute_no_conn(&RedisCommand::DBSIZE).await,
+            RedisType::integer(0)
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zmpop(&["a", "b"], PopEnd::Min, 1))
+                .await,
+            RedisType::NullArray
+        );
+
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "a".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        assert_eq!(
+            runtime
+                .execute_no_conn(&zmpop(&["a", "b"], PopEnd::Min, 1))
+                .await,
+            RedisType::simple_error(WRONGTYPE_ERROR)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bzmpop() {
+        let runtime = Arc::new(RedisRuntime::default());
+        let bzmpop = |timeout: u64| RedisCommand::BZMPOP {
+            timeout: Duration::from_millis(timeout),
+            keys: vec!["a".to_string(), "b".to_string()],
+            end: PopEnd::Min,
+            count: 1,
+        };
+
+        // Times out to nil when nothing is added
+        assert_eq!(
+            runtime.execute_no_conn(&bzmpop(50)).await,
+            RedisType::NullArray
+        );
+
+        // Woken up by a member added to the second key
+        let blocked = tokio::spawn({
+            let runtime = Arc::clone(&runtime);
+            async move { runtime.execute_no_conn(&bzmpop(5000)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!blocked.is_finished());
+        let point = Point::parse("0", "0").unwrap();
+        runtime
+            .execute_no_conn(&RedisCommand::GEOADD {
+                key: "b".to_string(),
+                members: vec![(point, "member".to_string())],
+            })
+            .await;
+
+        let result = tokio::time::timeout(Duration::from_secs(1), blocked)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            result,
+            RedisType::list(vec![
+                RedisType::bulk_string("b"),
+                RedisType::list(vec![RedisType::list(vec![
+                    RedisType::bulk_string("member"),
+                    RedisType::bulk_string(&point.geohash().to_string()),
+                ])]),
+            ])
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&RedisCommand::DBSIZE).await,
+            RedisType::integer(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_deadline() {
+        assert_eq!(block_deadline(Duration::ZERO), None);
+        assert!(block_deadline(Duration::from_secs(1)).is_some());
+
+        // Too far out for the timer, or even for the addition, so these wait for good
+        for timeout in [
+            Duration::from_secs(1_000_000_000_000_000_000),
+            Duration::from_secs(10_000_000_000_000_000_000),
+            Duration::MAX,
+        ] {
+            assert_eq!(block_deadline(timeout), None, "{:?}", timeout);
+        }
+
+        // Which BZMPOP and XREAD get to use without panicking
+        let runtime = RedisRuntime::default();
+        let point = Point::parse("0", "0").unwrap();
+        runtime
+            .execute_no_conn(&RedisCommand::GEOADD {
+                key: "a".to_string(),
+                members: vec![(point, "member".to_string())],
+            })
+            .await;
+        let result = runtime
+            .execute_isolated(
+                &RedisCommand::BZMPOP {
+                    timeout: Duration::from_secs(1_000_000_000_000_000_000),
+                    keys: vec!["a".to_string()],
+                    end: PopEnd::Min,
+                    count: 1,
+                },
+                None,
+                false,
+            )
+            .await;
+        assert!(matches!(result, RedisType::List { .. }), "{:?}", result);
+
+        runtime
+            .execute_no_conn(&RedisCommand::XADD {
+                key: "stream".to_string(),
+                id: XAddId::parse("1-1").unwrap(),
+                fields: vec![("field".to_string(), "value".to_string())],
+            })
+            .await;
+        let result = runtime
+            .execute_isolated(
+                &RedisCommand::XREAD {
+                    count: None,
+                    block: Some(Duration::from_millis(u64::MAX)),
+                    streams: vec![("stream".to_string(), XReadId::After(StreamId::MIN))],
+                },
+                None,
+                false,
+            )
+            .await;
+        assert!(matches!(result, RedisType::List { .. }), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_geo() {
+        let runtime = RedisRuntime::default();
+        let geoadd = RedisCommand::GEOADD {
+            key: "Sicily".to_string(),
+            members: vec![
+                (
+                    Point::parse("13.361389", "38.115556").unwrap(),
+                    "Palermo".to_string(),
+                ),
+                (
+                    Point::parse("15.087269", "37.502669").unwrap(),
+                    "Catania".to_string(),
+                ),
+            ],
+        };
+        let geodist = |to: &str, unit: GeoUnit| RedisCommand::GEODIST {
+            key: "Sicily".to_string(),
+            from: "Palermo".to_string(),
+            to: to.to_string(),
+            unit,
+        };
+        let geosearch = |from: GeoSearchFrom, km: f64| RedisCommand::GEOSEARCH {
+            key: "Sicily".to_string(),
+            from,
+            radius: GeoDistance {
+                amount: km,
+                unit: GeoUnit::Kilometers,
+            },
+        };
+        let members = |members: &[&str]| {
+            RedisType::list(
+                members
+                    .iter()
+                    .map(|member| RedisType::bulk_string(member))
+                    .collect(),
+            )
+        };
+
+        assert_eq!(
+            runtime.execute_no_conn(&geoadd).await,
+            RedisType::integer(2)
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&geoadd).await,
+            RedisType::integer(0)
+        );
+
+        // The same replies Redis gives for the cities in its documentation
+        assert_eq!(
+            runtime
+                .execute_no_conn(&geodist("Catania", GeoUnit::Meters))
+                .await,
+            RedisType::bulk_string("166274.1516")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&geodist("Catania", GeoUnit::Kilometers))
+                .await,
+            RedisType::bulk_string("166.2742")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&geodist("Agrigento", GeoUnit::Meters))
+                .await,
+            RedisType::NullBulkString
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::GEOPOS {
+                    key: "Sicily".to_string(),
+                    members: vec!["Palermo".to_string(), "Agrigento".to_string()],
+                })
+                .await,
+            RedisType::list(vec![
+                RedisType::list(vec![
+                    RedisType::bulk_string("13.36138933897018433"),
+                    RedisType::bulk_string("38.11555639549629859"),
+                ]),
+                RedisType::NullArray,
+            ])
+        );
+
+        let palermo = GeoSearchFrom::Member("Palermo".to_string());
+        assert_eq!(
+            runtime
+                .execute_no_conn(&geosearch(palermo.clone(), 100.0))
+                .await,
+            members(&["Palermo"])
+        );
+        assert_eq!(
+            runtime.execute_no_conn(&geosearch(palermo, 200.0)).await,
+            members(&["Palermo", "Catania"])
+        );
+        let point = GeoSearchFrom::Point(Point::parse("15", "37").unwrap());
+        assert_eq!(
+            runtime.execute_no_conn(&geosearch(point, 100.0)).await,
+            members(&["Catania"])
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&geosearch(GeoSearchFrom::Member("Rome".to_string()), 1.0))
+                .await,
+            RedisType::simple_error("ERR could not decode requested zset member")
+        );
+
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::GEOADD {
+                    key: "Sicily".to_string(),
+                    members: vec![(Point::parse("181", "10").unwrap(), "Nowhere".to_string())],
+                })
+                .await,
+            RedisType::simple_error("ERR invalid longitude,latitude pair 181.000000,10.000000")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::OBJECT {
+                    arg: ObjectArgs::Encoding {
+                        key: "Sicily".to_string()
+                    }
+                })
+                .await,
+            RedisType::bulk_string("listpack")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_wrong_type() {
+        let runtime = RedisRuntime::default();
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "string".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: None,
+                condition: None,
+            })
+            .await;
+        runtime
+            .execute_no_conn(&RedisCommand::XADD {
+                key: "stream".to_string(),
+                id: XAddId::Auto,
+                fields: vec![("a".to_string(), "1".to_string())],
+            })
+            .await;
+        let wrong_type = RedisType::simple_error(WRONGTYPE_ERROR);
+
+        let commands = [
+            RedisCommand::XADD {
+                key: "string".to_string(),
+                id: XAddId::Auto,
+                fields: vec![("a".to_string(), "1".to_string())],
+            },
+            RedisCommand::XLEN {
+                key: "string".to_string(),
+            },
+            RedisCommand::GEOPOS {
+                key: "stream".to_string(),
+                members: vec!["member".to_string()],
+            },
+            RedisCommand::XACK {
+                key: "string".to_string(),
+                group: "group".to_string(),
+                ids: vec![StreamId::new(1, 0)],
+            },
+            RedisCommand::GET {
+                key: "stream".to_string(),
+            },
+            RedisCommand::APPEND {
+                key: "stream".to_string(),
+                value: b"x".to_vec(),
+            },
+        ];
+        for command in &commands {
+            assert_eq!(runtime.execute_no_conn(command).await, wrong_type);
+        }
+        assert_eq!(
+            runtime
+                .execute_no_conn(&RedisCommand::OBJECT {
+                    arg: ObjectArgs::Encoding {
+                        key: "stream".to_string()
+                    }
+                })
+                .await,
+            RedisType::bulk_string("stream")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replicaof_no_one() {
+        let runtime = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6380".parse().unwrap()),
+            ..Default::default()
+        });
+        assert!(!runtime.is_master());
+
+        let result = runtime
+            .execute_no_conn(&RedisCommand::REPLICAOF { master: None })
+            .await;
+        assert_eq!(result, RedisType::simple_string("OK"));
+        assert!(runtime.is_master());
+
+        tokio::time::timeout(Duration::from_secs(1), runtime.replication_role_changed())
+            .await
+            .expect("Role change was not notified");
+    }
+
+    #[tokio::test]
+    async fn test_replicaof_master() {
+        let runtime = RedisRuntime::default();
+
+        let replicaof = RedisCommand::REPLICAOF {
+            master: Some(("127.0.0.1".to_string(), 6380)),
+        };
+        let result = runtime.execute_no_conn(&replicaof).await;
+        assert_eq!(result, RedisType::simple_string("OK"));
+        assert!(!runtime.is_master());
+        assert_eq!(
+            runtime.replication.read().unwrap().role.replicaof(),
+            Some("127.0.0.1:6380".parse().unwrap())
+        );
+
+        let result = runtime.execute_no_conn(&replicaof).await;
+        assert_eq!(
+            result,
+            RedisType::simple_string("OK Already connected to specified master")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_expiry_options() {
+        let runtime = RedisRuntime::default();
+        let set = |expiry: Option<ExpiryOption>| RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry,
+            condition: None,
+        };
+        let expiry = || {
+            runtime
+                .values
+                .try_read()
+                .unwrap()
+                .get("key")
+                .unwrap()
+                .expiry
+        };
+
+        let in_a_minute = SystemTime::now() + Duration::from_secs(60);
+        runtime
+            .execute_no_conn(&set(Some(ExpiryOption::At(in_a_minute))))
+            .await;
+        let deadline = expiry().unwrap();
+        assert!(deadline > Instant::now() + Duration::from_secs(59));
+
+        runtime
+            .execute_no_conn(&set(Some(ExpiryOption::KeepTtl)))
+            .await;
+        assert_eq!(expiry(), Some(deadline));
+
+        runtime.execute_no_conn(&set(None)).await;
+        assert_eq!(expiry(), None);
+
+        let a_minute_ago = SystemTime::now() - Duration::from_secs(60);
+        runtime
+            .execute_no_conn(&set(Some(ExpiryOption::At(a_minute_ago))))
+            .await;
+        let value = runtime
+            .execute_no_conn(&RedisCommand::GET {
+                key: "key".to_string(),
+            })
+            .await;
+        assert_eq!(value, RedisType::NullBulkString);
+    }
+
+    #[tokio::test]
+    async fn test_replica_rejects_client_writes() {
+        let runtime = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6380".parse().unwrap()),
+            ..Default::default()
+        });
+        let set = RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry: None,
+            condition: None,
+        };
+
+        let error = runtime.read_only_error(&set, false).unwrap();
+        assert!(error.write_as_protocol().starts_with(b"-READONLY "));
+
+        assert_eq!(runtime.read_only_error(&set, true), None);
+        let get = RedisCommand::GET {
+            key: "key".to_string(),
+        };
+        assert_eq!(runtime.read_only_error(&get, false), None);
+        assert_eq!(RedisRuntime::default().read_only_error(&set, false), None);
+    }
 
-#[derive(Debug)]
-enum ReplicationRole {
-    Master { replicas: Arc<Mutex<Vec<Replica>>> },
-    Slave { replicaof: SocketAddr },
-}
+    #[tokio::test]
+    async fn test_writable_replica() {
+        let runtime = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6380".parse().unwrap()),
+            replica_read_only: false,
+            ..Default::default()
+        });
+        let set = RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry: None,
+            condition: None,
+        };
 
-impl ReplicationRole {
-    fn type_str(&self) -> &str {
-        match self {
-            ReplicationRole::Master { .. } => "master",
-            ReplicationRole::Slave { .. } => "slave",
-        }
+        assert_eq!(runtime.read_only_error(&set, false), None);
+        assert_eq!(
+            runtime.execute_no_conn(&set).await,
+            RedisType::simple_string("OK")
+        );
+
+        // Local writes aren't replicated, so the replication offset doesn't move
+        runtime
+            .replicate_command(&set, &RedisType::simple_string("OK"))
+            .await
+            .unwrap();
+        assert_eq!(runtime.backlog.lock().await.offset(), 0);
     }
-}
 
-fn generate_alphanumeric_string(length: usize) -> String {
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(length)
-        .map(char::from)
-        .collect()
-}
+    #[tokio::test]
+    async fn test_command_getkeysandflags() {
+        let runtime = RedisRuntime::default();
+        let getkeysandflags = |args: &[&str]| RedisCommand::COMMAND {
+            arg: CommandArgs::GetKeysAndFlags(args.iter().map(|arg| arg.to_string()).collect()),
+        };
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+        let result = runtime
+            .execute_no_conn(&getkeysandflags(&["set", "key", "value"]))
+            .await;
+        assert_eq!(
+            result,
+            RedisType::list(vec![RedisType::list(vec![
+                RedisType::bulk_string("key"),
+                RedisType::list(vec![
+                    RedisType::simple_string("OW"),
+                    RedisType::simple_string("update"),
+                ]),
+            ])])
+        );
 
-    use super::*;
+        let result = runtime
+            .execute_no_conn(&getkeysandflags(&["GET", "key"]))
+            .await;
+        assert_eq!(
+            result,
+            RedisType::list(vec![RedisType::list(vec![
+                RedisType::bulk_string("key"),
+                RedisType::list(vec![
+                    RedisType::simple_string("RO"),
+                    RedisType::simple_string("access"),
+                ]),
+            ])])
+        );
+
+        let result = runtime.execute_no_conn(&getkeysandflags(&["ping"])).await;
+        assert_eq!(
+            result,
+            RedisType::simple_error("ERR The command has no key arguments")
+        );
+
+        let result = runtime.execute_no_conn(&getkeysandflags(&["get"])).await;
+        assert_eq!(
+            result,
+            RedisType::simple_error("ERR Invalid number of arguments specified for command")
+        );
+
+        let result = runtime.execute_no_conn(&getkeysandflags(&["nope"])).await;
+        assert_eq!(
+            result,
+            RedisType::simple_error("ERR Invalid command specified")
+        );
+    }
 
     #[tokio::test]
-    async fn test_ping_command() {
+    async fn test_memory_usage() {
         let runtime = RedisRuntime::default();
-        let result = runtime.execute_no_conn(&RedisCommand::PING).await;
-        assert_eq!(result, RedisType::simple_string("PONG"));
+        let usage = |key: &str| RedisCommand::MEMORY {
+            arg: MemoryArgs::Usage {
+                key: key.to_string(),
+                samples: None,
+            },
+        };
+        let set = |key: &str, value: &str| RedisCommand::SET {
+            key: key.to_string(),
+            val: RedisType::bulk_string(value),
+            expiry: None,
+            condition: None,
+        };
+
+        runtime.execute_no_conn(&set("short", "a")).await;
+        runtime
+            .execute_no_conn(&set("other", &"a".repeat(1000)))
+            .await;
+
+        let usage_of = |key: &'static str| async {
+            match runtime.execute_no_conn(&usage(key)).await {
+                RedisType::Integer { data } => data,
+                other => panic!("MEMORY USAGE did not reply with an integer: {:?}", other),
+            }
+        };
+        let short = usage_of("short").await;
+        let long = usage_of("other").await;
+        assert!(short > 1);
+        assert!(long >= short + 999);
+
+        assert_eq!(
+            runtime.execute_no_conn(&usage("missing")).await,
+            RedisType::NullBulkString
+        );
     }
 
     #[tokio::test]
-    async fn test_echo_command() {
+    async fn test_memory_stats_and_doctor() {
         let runtime = RedisRuntime::default();
-        let result = runtime
-            .execute_no_conn(&RedisCommand::ECHO("Hello, Redis!".to_string()))
+        for key in ["a", "b", "c"] {
+            runtime
+                .execute_no_conn(&RedisCommand::SET {
+                    key: key.to_string(),
+                    val: RedisType::bulk_string("value"),
+                    expiry: None,
+                    condition: None,
+                })
+                .await;
+        }
+
+        let stats = match runtime
+            .execute_no_conn(&RedisCommand::MEMORY {
+                arg: MemoryArgs::Stats,
+            })
+            .await
+        {
+            RedisType::List { data } => data,
+            other => panic!("MEMORY STATS did not reply with a list: {:?}", other),
+        };
+        let keys_count = stats
+            .chunks(2)
+            .find(|pair| pair[0].extract_string() == Some("keys.count"))
+            .map(|pair| pair[1].as_ref().clone());
+
+        let dbsize = runtime.execute_no_conn(&RedisCommand::DBSIZE).await;
+        assert_eq!(dbsize, RedisType::integer(3));
+        assert_eq!(keys_count, Some(dbsize));
+
+        let doctor = runtime
+            .execute_no_conn(&RedisCommand::MEMORY {
+                arg: MemoryArgs::Doctor,
+            })
             .await;
-        assert_eq!(result, RedisType::bulk_string("Hello, Redis!"));
+        assert!(
+            matches!(doctor, RedisType::BulkString { data } if data.contains("very little memory"))
+        );
     }
 
     #[tokio::test]
-    async fn test_set_command() {
+    async fn test_active_expire_cycle() {
         let runtime = RedisRuntime::default();
-        let result = runtime
+        for i in 0..100 {
+            runtime
+                .execute_no_conn(&RedisCommand::SET {
+                    key: format!("persistent{}", i),
+                    val: RedisType::bulk_string("value"),
+                    expiry: None,
+                    condition: None,
+                })
+                .await;
+        }
+        runtime
             .execute_no_conn(&RedisCommand::SET {
-                key: "key1".to_string(),
-                val: RedisType::bulk_string("value1"),
-                ttl: None,
+                key: "volatile".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: Some(ExpiryOption::After(Duration::from_millis(20))),
+                condition: None,
             })
             .await;
-        assert_eq!(result, RedisType::simple_string("OK"));
 
-        // Ensure the value is actually set
-        let guard = runtime.values.read().await;
-        let value = &guard.get("key1").unwrap().value;
-        assert_eq!(value, &RedisType::bulk_string("value1"));
+        // Only keys with a TTL are ever looked at by the cycle
+        assert_eq!(runtime.expiry_index.lock().unwrap().len(), 1);
+        assert_eq!(runtime.active_expire_cycle().await, 0);
+
+        tokio::time::sleep(Duration::from_millis(21)).await;
+        assert_eq!(runtime.active_expire_cycle().await, 1);
+        assert!(!runtime.values.read().await.contains_key("volatile"));
+        assert_eq!(runtime.values.read().await.len(), 100);
+        assert!(runtime.expiry_index.lock().unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn test_set_command_with_ttl() {
+    async fn test_active_expire_cycle_propagates_deletions() {
         let runtime = RedisRuntime::default();
+        for key in ["first", "second"] {
+            runtime
+                .execute_no_conn(&RedisCommand::SET {
+                    key: key.to_string(),
+                    val: RedisType::bulk_string("value"),
+                    expiry: Some(ExpiryOption::After(Duration::from_millis(5))),
+                    condition: None,
+                })
+                .await;
+        }
 
-        let key = "key_with_ttl";
-        let result = runtime
-            .execute_no_conn(&RedisCommand::SET {
-                key: key.to_string(),
-                val: RedisType::bulk_string("temporary"),
-                ttl: Some(Duration::from_millis(100)),
-            })
-            .await;
-        assert_eq!(result, RedisType::simple_string("OK"));
+        tokio::time::sleep(Duration::from_millis(6)).await;
+        assert_eq!(runtime.active_expire_cycle().await, 2);
 
-        // Ensure the value is actually set
-        let value = runtime
-            .execute_no_conn(&RedisCommand::GET {
-                key: key.to_string(),
+        // One DEL per key, as Redis sends them
+        let mut deleted: Vec<_> = runtime
+            .take_propagated()
+            .into_iter()
+            .map(|command| match command {
+                RedisCommand::DEL { keys } => keys,
+                other => panic!("Expected DEL, got {:?}", other),
             })
-            .await;
-        assert_eq!(value, RedisType::bulk_string("temporary"));
+            .collect();
+        deleted.sort();
+        assert_eq!(
+            deleted,
+            vec![vec!["first".to_string()], vec!["second".to_string()]]
+        );
 
-        tokio::time::sleep(Duration::from_millis(101)).await;
+        // Nothing is left to delete, so nothing more is sent
+        assert_eq!(runtime.active_expire_cycle().await, 0);
+        assert!(runtime.take_propagated().is_empty());
+    }
 
-        // Ensure the value has expired
-        let value = runtime
-            .execute_no_conn(&RedisCommand::GET {
-                key: key.to_string(),
-            })
+    #[tokio::test]
+    async fn test_active_expire_cycle_skips_overwritten_keys() {
+        let runtime = RedisRuntime::default();
+        let set = |expiry| RedisCommand::SET {
+            key: "key".to_string(),
+            val: RedisType::bulk_string("value"),
+            expiry,
+            condition: None,
+        };
+
+        runtime
+            .execute_no_conn(&set(Some(ExpiryOption::After(Duration::from_millis(10)))))
             .await;
-        assert_eq!(value, RedisType::NullBulkString);
+        runtime.execute_no_conn(&set(None)).await;
+        assert!(runtime.expiry_index.lock().unwrap().is_empty());
+
+        tokio::time::sleep(Duration::from_millis(11)).await;
+        assert_eq!(runtime.active_expire_cycle().await, 0);
+        assert!(runtime.values.read().await.contains_key("key"));
     }
 
     #[tokio::test]
-    async fn test_get_command_existing_key() {
+    async fn test_active_expire_can_be_disabled() {
         let runtime = RedisRuntime::default();
-        runtime.values.write().await.insert(
-            "key1".to_string(),
-            ValueWithExpiry {
-                value: RedisType::bulk_string("value1"),
+        let set_active_expire = |enabled| RedisCommand::DEBUG {
+            arg: DebugArgs::SetActiveExpire(enabled),
+        };
+        let type_of = RedisCommand::TYPE {
+            key: "key".to_string(),
+        };
 
-                expiry: None,
-            },
+        assert_eq!(
+            runtime.execute_no_conn(&set_active_expire(false)).await,
+            RedisType::simple_string("OK")
         );
-
-        let result = runtime
-            .execute_no_conn(&RedisCommand::GET {
-                key: "key1".to_string(),
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "key".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: Some(ExpiryOption::After(Duration::from_millis(1))),
+                condition: None,
             })
             .await;
-        assert_eq!(result, RedisType::bulk_string("value1"));
+        tokio::time::sleep(Duration::from_millis(2)).await;
+
+        // The key stays until something deletes it lazily, but reads already miss it
+        assert_eq!(runtime.active_expire_cycle().await, 0);
+        assert_eq!(
+            runtime.execute_no_conn(&type_of).await,
+            RedisType::simple_string("none")
+        );
+        assert!(runtime.values.read().await.contains_key("key"));
+
+        runtime.execute_no_conn(&set_active_expire(true)).await;
+        assert_eq!(runtime.active_expire_cycle().await, 1);
+        assert!(!runtime.values.read().await.contains_key("key"));
     }
 
     #[tokio::test]
-    async fn test_replication_info() {
-        let runtime = RedisRuntime::default();
-        assert!(matches!(
-            runtime.replication_role,
-            ReplicationRole::Master { .. },
-        ));
-
-        let result = runtime
-            .execute_no_conn(&RedisCommand::INFO {
-                arg: "replication".to_string(),
+    async fn test_replica_does_not_expire_actively() {
+        let runtime = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6380".parse().unwrap()),
+            ..Default::default()
+        });
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "key".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: Some(ExpiryOption::After(Duration::from_millis(1))),
+                condition: None,
             })
             .await;
 
-        match result {
-            RedisType::BulkString { data } => {
-                assert!(data.contains("role:master"));
-                assert!(data.contains("master_replid:"));
-                assert!(data.contains("master_repl_offset:0"));
-            }
-            _ => panic!("Result was not a bulk string"),
-        }
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        assert_eq!(runtime.active_expire_cycle().await, 0);
+        assert!(runtime.values.read().await.contains_key("key"));
+    }
+
+    #[test]
+    fn test_normalize_range() {
+        assert_eq!(normalize_range(0, -1, 5), Some((0, 4)));
+        assert_eq!(normalize_range(1, 2, 5), Some((1, 2)));
+        assert_eq!(normalize_range(0, 100, 5), Some((0, 4)));
+        assert_eq!(normalize_range(-100, 1, 5), Some((0, 1)));
+        // Both negative
+        assert_eq!(normalize_range(-3, -1, 5), Some((2, 4)));
+        assert_eq!(normalize_range(-1, -3, 5), None);
+        assert_eq!(normalize_range(-100, -200, 5), None);
+        // Start beyond the end of the string
+        assert_eq!(normalize_range(5, 10, 5), None);
+        assert_eq!(normalize_range(3, 1, 5), None);
+        // Empty string
+        assert_eq!(normalize_range(0, -1, 0), None);
+        assert_eq!(normalize_range(0, 0, 0), None);
     }
 
     #[tokio::test]
-    async fn test_unknown_info() {
+    async fn test_getrange_command() {
         let runtime = RedisRuntime::default();
-
-        let result = runtime
-            .execute_no_conn(&RedisCommand::INFO {
-                arg: "anything".to_string(),
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "key".to_string(),
+                val: RedisType::bulk_string("This is a string"),
+                expiry: None,
+                condition: None,
             })
             .await;
+        let getrange = |key: &str, start, end| RedisCommand::GETRANGE {
+            key: key.to_string(),
+            start,
+            end,
+        };
+
+        for (start, end, expected) in [
+            (0, 3, "This"),
+            (-3, -1, "ing"),
+            (0, -1, "This is a string"),
+            (10, 100, "string"),
+            (100, 200, ""),
+            (-1, -5, ""),
+        ] {
+            assert_eq!(
+                runtime.execute_no_conn(&getrange("key", start, end)).await,
+                RedisType::bulk_string(expected)
+            );
+        }
         assert_eq!(
-            result,
-            RedisType::simple_error("Unknown arg for INFO: anything")
+            runtime.execute_no_conn(&getrange("missing", 0, -1)).await,
+            RedisType::bulk_string("")
+        );
+
+        // SUBSTR parses to the same command, so it shares the handler
+        let substr = RedisCommand::parse(&RedisType::list(vec![
+            RedisType::bulk_string("SUBSTR"),
+            RedisType::bulk_string("key"),
+            RedisType::bulk_string("-6"),
+            RedisType::bulk_string("-1"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            runtime.execute_no_conn(&substr).await,
+            RedisType::bulk_string("string")
         );
     }
 
     #[tokio::test]
-    async fn test_get_command_non_existing_key() {
+    async fn test_config_resetstat() {
         let runtime = RedisRuntime::default();
+        let info = |section: &str| RedisCommand::INFO {
+            sections: vec![section.to_string()],
+        };
+        let bulk_data = |result: RedisType| match result {
+            RedisType::BulkString { data } => data,
+            other => panic!("Result was not a bulk string: {:?}", other),
+        };
 
-        let result = runtime
+        runtime.execute_no_conn(&RedisCommand::PING).await;
+        runtime
             .execute_no_conn(&RedisCommand::GET {
-                key: "key1".to_string(),
+                key: "key".to_string(),
+            })
+            .await;
+        runtime
+            .execute_no_conn(&RedisCommand::FAILOVER {
+                target: None,
+                force: false,
+                abort: false,
+                timeout: None,
             })
             .await;
-        assert_eq!(result, RedisType::NullBulkString);
-    }
 
-    #[tokio::test]
-    async fn test_replconf() {
-        let runtime = RedisRuntime::default();
+        let commandstats = bulk_data(runtime.execute_no_conn(&info("commandstats")).await);
+        assert!(commandstats.contains("cmdstat_ping:calls=1,"));
+        assert!(commandstats.contains("cmdstat_get:calls=1,"));
+        assert!(commandstats.contains("cmdstat_failover:calls=1,"));
+        let stats = bulk_data(runtime.execute_no_conn(&info("stats")).await);
+        assert!(stats.contains("total_commands_processed:4"));
+        assert!(stats.contains("total_error_replies:1"));
 
         let result = runtime
-            .execute_no_conn(&RedisCommand::REPLCONF {
-                arg: ReplConfArgs::Port(1234),
+            .execute_no_conn(&RedisCommand::CONFIG {
+                arg: ConfigArgs::ResetStat,
             })
             .await;
         assert_eq!(result, RedisType::simple_string("OK"));
 
-        let result = runtime
-            .execute_no_conn(&RedisCommand::REPLCONF {
-                arg: ReplConfArgs::Capabilities(vec!["psync2".to_string()]),
+        // Only the RESETSTAT call itself, recorded after the reset, is left
+        let commandstats = bulk_data(runtime.execute_no_conn(&info("commandstats")).await);
+        assert_eq!(
+            commandstats
+                .lines()
+                .filter(|line| line.starts_with("cmdstat_"))
+                .count(),
+            1
+        );
+        assert!(commandstats.contains("cmdstat_config:calls=1,"));
+        let errorstats = bulk_data(runtime.execute_no_conn(&info("errorstats")).await);
+        assert_eq!(errorstats, "# Errorstats");
+    }
+
+    #[tokio::test]
+    async fn test_keyspace_hits_and_misses() {
+        let runtime = RedisRuntime::default();
+        let get = |key: &str| RedisCommand::GET {
+            key: key.to_string(),
+        };
+        runtime
+            .execute_no_conn(&RedisCommand::SET {
+                key: "key".to_string(),
+                val: RedisType::bulk_string("value"),
+                expiry: None,
+                condition: None,
             })
             .await;
-        assert_eq!(result, RedisType::simple_string("OK"));
+
+        runtime.execute_no_conn(&get("key")).await;
+        assert!(runtime
+            .stats()
+            .info_stats()
+            .contains("keyspace_hits:1\nkeyspace_misses:0"));
+
+        runtime.execute_no_conn(&get("missing")).await;
+        assert!(runtime
+            .stats()
+            .info_stats()
+            .contains("keyspace_hits:1\nkeyspace_misses:1"));
+    }
+
+    #[tokio::test]
+    async fn test_command_docs() {
+        let runtime = RedisRuntime::default();
+        let docs = |names: &[&str]| RedisCommand::COMMAND {
+            arg: CommandArgs::Docs(names.iter().map(|name| name.to_string()).collect()),
+        };
+
+        let list = |result: RedisType| match result {
+            RedisType::List { data } => data,
+            other => panic!("Expected a list, received: {:?}", other),
+        };
+
+        let data = list(runtime.execute_no_conn(&docs(&["SET"])).await);
+        assert_eq!(*data[0], RedisType::bulk_string("set"));
+        let fields = list(data[1].as_ref().clone());
+        let field = |name: &str| {
+            fields
+                .chunks(2)
+                .find(|pair| pair[0].extract_string() == Some(name))
+                .map(|pair| pair[1].as_ref().clone())
+        };
+
+        assert!(matches!(
+            field("summary"),
+            Some(RedisType::BulkString { .. })
+        ));
+        let arguments = list(field("arguments").expect("The docs of SET have no arguments"));
+        assert_eq!(
+            *arguments[0],
+            RedisType::list(vec![
+                RedisType::bulk_string("name"),
+                RedisType::bulk_string("key"),
+                RedisType::bulk_string("type"),
+                RedisType::bulk_string("key"),
+            ])
+        );
+
+        assert_eq!(
+            runtime.execute_no_conn(&docs(&["nope"])).await,
+            RedisType::list(vec![])
+        );
+        let data = list(runtime.execute_no_conn(&docs(&[])).await);
+        assert_eq!(data.len(), command_table::COMMAND_TABLE.len() * 2);
+    }
+
+    #[tokio::test]
+    async fn test_failover_errors() {
+        let failover =
+            |target: Option<(&str, u16)>, force, abort, timeout| RedisCommand::FAILOVER {
+                target: target.map(|(host, port)| (host.to_string(), port)),
+                force,
+                abort,
+                timeout,
+            };
+        let runtime = RedisRuntime::default();
+
+        assert_eq!(
+            runtime
+                .execute_no_conn(&failover(None, false, true, None))
+                .await,
+            RedisType::simple_error("ERR No failover in progress.")
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&failover(None, true, false, Some(100)))
+                .await,
+            RedisType::simple_error(
+                "ERR FAILOVER with force option requires both a timeout and target HOST and IP."
+            )
+        );
+        assert_eq!(
+            runtime
+                .execute_no_conn(&failover(None, false, false, None))
+                .await,
+            RedisType::simple_error("ERR FAILOVER requires connected replicas.")
+        );
+
+        let replica = RedisRuntime::new(ServerConfig {
+            replica_addr: Some("127.0.0.1:6380".parse().unwrap()),
+            ..Default::default()
+        });
+        assert_eq!(
+            replica
+                .execute_no_conn(&failover(None, false, false, None))
+                .await,
+            RedisType::simple_error("ERR FAILOVER is not valid when server is a replica.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_time_command() {
+        let runtime = RedisRuntime::default();
+        let result = runtime.execute_no_conn(&RedisCommand::TIME).await;
+
+        let parts: Vec<u64> = match result {
+            RedisType::List { data } => data
+                .iter()
+                .map(|part| part.extract_string().unwrap().parse().unwrap())
+                .collect(),
+            other => panic!("TIME did not reply with a list: {:?}", other),
+        };
+        assert_eq!(parts.len(), 2);
+        // Some time after 2020-01-01
+        assert!(parts[0] > 1_577_836_800);
+        assert!(parts[1] < 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_times_out_on_stalled_master() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let master_addr = listener.local_addr().unwrap();
+
+        // Answers the PING, then never replies to REPLCONF
+        let master = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut client = RedisClient::new_raw(stream);
+            client.accept_adicional_data().await.unwrap();
+            client
+                .buffer
+                .write_all(&RedisType::simple_string("PONG").write_as_protocol())
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let replica = RedisRuntime::new(ServerConfig {
+            replica_addr: Some(master_addr),
+            repl_timeout: Duration::from_millis(100),
+            ..Default::default()
+        });
+
+        let error = replica.perform_handshake().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Timeout waiting for the master during handshake step REPLCONF port"
+        );
+        master.abort();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_fails_when_master_closes() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let master_addr = listener.local_addr().unwrap();
+
+        // Hangs up before answering anything
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            drop(stream);
+        });
+
+        let replica = RedisRuntime::new(ServerConfig {
+            replica_addr: Some(master_addr),
+            repl_timeout: Duration::from_secs(5),
+            ..Default::default()
+        });
+
+        let error = replica.perform_handshake().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Handshake step PING failed: Connection closed by the server"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_loading_while_receiving_rdb() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let master_addr = listener.local_addr().unwrap();
+        let (send_rdb, rdb_released) = tokio::sync::oneshot::channel::<()>();
+
+        // Goes through the handshake, but holds the RDB back until told to send it
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut client = RedisClient::new_raw(stream);
+            for reply in [
+                "PONG",
+                "OK",
+                "OK",
+                "FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 0",
+            ] {
+                client.accept_adicional_data().await.unwrap();
+                client
+                    .buffer
+                    .write_all(&RedisType::simple_string(reply).write_as_protocol())
+                    .await
+                    .unwrap();
+            }
+
+            rdb_released.await.unwrap();
+            let rdb = RedisType::RDBFile {
+                file: rdb_file::get_empty_rdb_decoded(),
+            };
+            client
+                .buffer
+                .write_all(&rdb.write_as_protocol())
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let replica = Arc::new(RedisRuntime::new(ServerConfig {
+            replica_addr: Some(master_addr),
+            ..Default::default()
+        }));
+        let handshake = tokio::spawn({
+            let replica = Arc::clone(&replica);
+            async move {
+                replica
+                    .perform_handshake()
+                    .await
+                    .map(|stream| stream.is_some())
+            }
+        });
+
+        let get = RedisCommand::GET {
+            key: "key".to_string(),
+        };
+        while replica.loading_error(&get, false).is_none() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(
+            replica.loading_error(&get, false),
+            Some(RedisType::simple_error(
+                "LOADING Redis is loading the dataset in memory"
+            ))
+        );
+        assert!(replica.loading_error(&get, true).is_none());
+        assert!(replica
+            .loading_error(
+                &RedisCommand::INFO {
+                    sections: vec!["replication".to_string()],
+                },
+                false
+            )
+            .is_none());
+
+        send_rdb.send(()).unwrap();
+        assert!(handshake.await.unwrap().unwrap());
+        assert!(replica.loading_error(&get, false).is_none());
     }
 }
@@ -0,0 +1,577 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::Bound,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A stream entry id, `<milliseconds>-<sequence>`. Ids only ever grow within a stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// Parses `ms-seq`, or a bare `ms` that gets `missing_seq` as its sequence.
+    pub fn parse(id: &str, missing_seq: u64) -> Option<Self> {
+        match id.split_once('-') {
+            Some((ms, seq)) => Some(Self::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(Self::new(id.parse().ok()?, missing_seq)),
+        }
+    }
+
+    /// Parses an XRANGE bound. `-` and `+` are the smallest and largest ids, a bare `ms` covers
+    /// all its sequences, and a leading `(` makes the bound exclusive.
+    pub fn parse_range_bound(bound: &str, is_start: bool) -> Option<Self> {
+        let missing_seq = if is_start { 0 } else { u64::MAX };
+
+        match bound {
+            "-" => Some(Self::MIN),
+            "+" => Some(Self::MAX),
+            _ => match bound.strip_prefix('(') {
+                Some(id) if is_start => Self::parse(id, missing_seq)?.next(),
+                Some(id) => Self::parse(id, missing_seq)?.prev(),
+                None => Self::parse(bound, missing_seq),
+            },
+        }
+    }
+
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(Self::new(self.ms, seq)),
+            None => Some(Self::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    pub fn prev(self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(Self::new(self.ms, seq)),
+            None => Some(Self::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The id asked for in XADD: `*`, `<ms>-*`, or a full id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XAddId {
+    Auto,
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+impl XAddId {
+    pub fn parse(id: &str) -> Option<Self> {
+        if id == "*" {
+            return Some(Self::Auto);
+        }
+
+        match id.strip_suffix("-*") {
+            Some(ms) => Some(Self::AutoSeq(ms.parse().ok()?)),
+            None => Some(Self::Explicit(StreamId::parse(id, 0)?)),
+        }
+    }
+}
+
+impl fmt::Display for XAddId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XAddId::Auto => write!(f, "*"),
+            XAddId::AutoSeq(ms) => write!(f, "{}-*", ms),
+            XAddId::Explicit(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Where an XREAD starts reading a stream from: `$` for entries added after the call, otherwise
+/// entries past an id. XGROUP CREATE takes the same ids for where a group starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XReadId {
+    Last,
+    After(StreamId),
+}
+
+impl XReadId {
+    pub fn parse(id: &str) -> Option<Self> {
+        match id {
+            "$" => Some(Self::Last),
+            _ => Some(Self::After(StreamId::parse(id, 0)?)),
+        }
+    }
+}
+
+impl fmt::Display for XReadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XReadId::Last => write!(f, "$"),
+            XReadId::After(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Where an XREADGROUP reads a stream from: `>` for entries never delivered to the group,
+/// otherwise the consumer's own pending entries past an id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XReadGroupId {
+    New,
+    Pending(StreamId),
+}
+
+impl XReadGroupId {
+    pub fn parse(id: &str) -> Option<Self> {
+        match id {
+            ">" => Some(Self::New),
+            _ => Some(Self::Pending(StreamId::parse(id, 0)?)),
+        }
+    }
+}
+
+impl fmt::Display for XReadGroupId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XReadGroupId::New => write!(f, ">"),
+            XReadGroupId::Pending(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+pub type StreamFields = Vec<(String, String)>;
+
+// Per entry bookkeeping on top of the field and value bytes: the id, the map node and vec header
+const ENTRY_OVERHEAD: usize = 64;
+
+/// An append-only log of entries, each a list of field-value pairs, ordered by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, StreamFields>,
+    last_id: StreamId,
+    groups: BTreeMap<String, ConsumerGroup>,
+}
+
+/// A consumer group reading a stream: the last entry handed out to any of its consumers, and the
+/// entries each consumer was given but hasn't acknowledged yet, its pending entries list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    last_delivered: StreamId,
+    consumers: BTreeMap<String, BTreeSet<StreamId>>,
+}
+
+impl Stream {
+    /// Adds an entry, returning its id. Errors are the replies Redis gives for an id that isn't
+    /// past the last one.
+    pub fn add(&mut self, id: XAddId, fields: StreamFields) -> Result<StreamId, &'static str> {
+        let id = match id {
+            XAddId::Auto => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+
+                // A clock that went backwards keeps counting from the last id
+                if now > self.last_id.ms {
+                    StreamId::new(now, 0)
+                } else {
+                    self.last_id.next().ok_or(STREAM_EXHAUSTED_ERROR)?
+                }
+            }
+            XAddId::AutoSeq(ms) if ms == self.last_id.ms && !self.is_pristine() => {
+                let seq = self.last_id.seq.checked_add(1).ok_or(ID_TOO_SMALL_ERROR)?;
+                StreamId::new(ms, seq)
+            }
+            // 0-0 is never a valid id, so the first sequence of ms 0 is 1
+            XAddId::AutoSeq(ms) => StreamId::new(ms, (ms == 0) as u64),
+            XAddId::Explicit(id) => id,
+        };
+
+        if id == StreamId::MIN {
+            return Err("ERR The ID specified in XADD must be greater than 0-0");
+        }
+        if id <= self.last_id {
+            return Err(ID_TOO_SMALL_ERROR);
+        }
+
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn first_entry(&self) -> Option<(&StreamId, &StreamFields)> {
+        self.entries.first_key_value()
+    }
+
+    pub fn last_entry(&self) -> Option<(&StreamId, &StreamFields)> {
+        self.entries.last_key_value()
+    }
+
+    /// Entries with ids from `start` to `end`, both included, at most `count` of them.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Vec<(&StreamId, &StreamFields)> {
+        if start > end {
+            return Vec::new();
+        }
+
+        self.entries
+            .range((Bound::Included(start), Bound::Included(end)))
+            .take(count.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Entries with ids greater than `id`, at most `count` of them.
+    pub fn after(&self, id: StreamId, count: Option<usize>) -> Vec<(&StreamId, &StreamFields)> {
+        match id.next() {
+            Some(start) => self.range(start, StreamId::MAX, count),
+            None => Vec::new(),
+        }
+    }
+
+    /// Creates a group that will deliver the entries after `last_delivered`.
+    pub fn create_group(
+        &mut self,
+        name: &str,
+        last_delivered: StreamId,
+    ) -> Result<(), &'static str> {
+        if self.groups.contains_key(name) {
+            return Err("BUSYGROUP Consumer Group name already exists");
+        }
+
+        self.groups.insert(
+            name.to_string(),
+            ConsumerGroup {
+                last_delivered,
+                consumers: BTreeMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn has_group(&self, name: &str) -> bool {
+        self.groups.contains_key(name)
+    }
+
+    /// The consumer groups, by name.
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &ConsumerGroup)> {
+        self.groups
+            .iter()
+            .map(|(name, group)| (name.as_str(), group))
+    }
+
+    /// How many entries `group` went through. Entries are never removed, so those are all the
+    /// entries up to the last one delivered.
+    pub fn entries_read(&self, group: &ConsumerGroup) -> usize {
+        self.entries.range(..=group.last_delivered).count()
+    }
+
+    /// Reads entries for `consumer` on behalf of `group`, at most `count` of them. New entries
+    /// are added to the consumer's pending entries, while a `Pending` read only lists them again.
+    /// `None` if there's no such group.
+    pub fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        id: XReadGroupId,
+        count: Option<usize>,
+    ) -> Option<Vec<(&StreamId, &StreamFields)>> {
+        let group = self.groups.get_mut(group)?;
+        // Reading is enough to make a consumer known to the group
+        let pending = group.consumers.entry(consumer.to_string()).or_default();
+        let count = count.unwrap_or(usize::MAX);
+
+        match id {
+            XReadGroupId::New => {
+                let entries: Vec<_> = match group.last_delivered.next() {
+                    Some(start) => self.entries.range(start..).take(count).collect(),
+                    None => Vec::new(),
+                };
+
+                if let Some((id, _)) = entries.last() {
+                    group.last_delivered = **id;
+                }
+                pending.extend(entries.iter().map(|(id, _)| **id));
+
+                Some(entries)
+            }
+            XReadGroupId::Pending(after) => Some(
+                pending
+                    .range((Bound::Excluded(after), Bound::Unbounded))
+                    .filter_map(|id| self.entries.get_key_value(id))
+                    .take(count)
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Removes `ids` from the pending entries of `group`'s consumers, returning how many were
+    /// pending.
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> usize {
+        let Some(group) = self.groups.get_mut(group) else {
+            return 0;
+        };
+
+        ids.iter()
+            .filter(|id| {
+                group
+                    .consumers
+                    .values_mut()
+                    .any(|pending| pending.remove(id))
+            })
+            .count()
+    }
+
+    /// Approximate bytes held by the entries, for `MEMORY USAGE`.
+    pub fn memory_usage(&self) -> usize {
+        self.entries
+            .values()
+            .map(|fields| {
+                ENTRY_OVERHEAD
+                    + fields
+                        .iter()
+                        .map(|(field, value)| field.capacity() + value.capacity())
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+
+    // Nothing was ever added, so even 0-* may take the first id
+    fn is_pristine(&self) -> bool {
+        self.last_id == StreamId::MIN
+    }
+}
+
+impl ConsumerGroup {
+    pub fn last_delivered(&self) -> StreamId {
+        self.last_delivered
+    }
+
+    pub fn consumers(&self) -> usize {
+        self.consumers.len()
+    }
+
+    /// Entries delivered to any of the consumers and not acknowledged yet.
+    pub fn pending(&self) -> usize {
+        self.consumers.values().map(BTreeSet::len).sum()
+    }
+}
+
+const ID_TOO_SMALL_ERROR: &str =
+    "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+const STREAM_EXHAUSTED_ERROR: &str =
+    "ERR The stream has exhausted the last possible ID, unable to add more items";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> StreamFields {
+        pairs
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        assert_eq!(StreamId::parse("5-3", 0), Some(StreamId::new(5, 3)));
+        assert_eq!(StreamId::parse("5", 7), Some(StreamId::new(5, 7)));
+        assert_eq!(StreamId::parse("5-", 0), None);
+        assert_eq!(StreamId::parse("a-1", 0), None);
+
+        assert_eq!(XAddId::parse("*"), Some(XAddId::Auto));
+        assert_eq!(XAddId::parse("12-*"), Some(XAddId::AutoSeq(12)));
+        assert_eq!(
+            XAddId::parse("12-1"),
+            Some(XAddId::Explicit(StreamId::new(12, 1)))
+        );
+        assert_eq!(XAddId::parse("x-*"), None);
+
+        assert_eq!(StreamId::parse_range_bound("-", true), Some(StreamId::MIN));
+        assert_eq!(StreamId::parse_range_bound("+", false), Some(StreamId::MAX));
+        assert_eq!(
+            StreamId::parse_range_bound("5", false),
+            Some(StreamId::new(5, u64::MAX))
+        );
+        assert_eq!(
+            StreamId::parse_range_bound("(5-0", true),
+            Some(StreamId::new(5, 1))
+        );
+        assert_eq!(
+            StreamId::parse_range_bound("(5-0", false),
+            Some(StreamId::new(4, u64::MAX))
+        );
+        assert_eq!(StreamId::parse_range_bound("(0-0", false), None);
+    }
+
+    #[test]
+    fn test_add_explicit_ids() {
+        let mut stream = Stream::default();
+
+        assert_eq!(
+            stream.add(XAddId::Explicit(StreamId::new(1, 1)), fields(&[("a", "1")])),
+            Ok(StreamId::new(1, 1))
+        );
+        assert_eq!(
+            stream.add(XAddId::Explicit(StreamId::new(1, 1)), fields(&[("a", "2")])),
+            Err(ID_TOO_SMALL_ERROR)
+        );
+        assert_eq!(
+            stream.add(XAddId::Explicit(StreamId::new(0, 5)), fields(&[("a", "2")])),
+            Err(ID_TOO_SMALL_ERROR)
+        );
+        assert_eq!(
+            Stream::default().add(XAddId::Explicit(StreamId::MIN), fields(&[("a", "1")])),
+            Err("ERR The ID specified in XADD must be greater than 0-0")
+        );
+        assert_eq!(stream.len(), 1);
+    }
+
+    #[test]
+    fn test_add_generated_ids() {
+        let mut stream = Stream::default();
+
+        assert_eq!(
+            stream.add(XAddId::AutoSeq(0), fields(&[("a", "1")])),
+            Ok(StreamId::new(0, 1))
+        );
+        assert_eq!(
+            stream.add(XAddId::AutoSeq(0), fields(&[("a", "1")])),
+            Ok(StreamId::new(0, 2))
+        );
+        assert_eq!(
+            stream.add(XAddId::AutoSeq(5), fields(&[("a", "1")])),
+            Ok(StreamId::new(5, 0))
+        );
+
+        let mut previous = stream.last_id();
+        for _ in 0..100 {
+            let id = stream.add(XAddId::Auto, fields(&[("a", "1")])).unwrap();
+            assert!(id > previous);
+            previous = id;
+        }
+
+        // Ids generated from a clock behind the last one keep increasing
+        let mut future = Stream::default();
+        future
+            .add(XAddId::Explicit(StreamId::new(u64::MAX - 1, 0)), vec![])
+            .unwrap();
+        assert_eq!(
+            future.add(XAddId::Auto, vec![]),
+            Ok(StreamId::new(u64::MAX - 1, 1))
+        );
+        assert_eq!(
+            future.add(XAddId::AutoSeq(3), vec![]),
+            Err(ID_TOO_SMALL_ERROR)
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        let mut stream = Stream::default();
+        for ms in 1..=5 {
+            stream
+                .add(XAddId::AutoSeq(ms), fields(&[("n", &ms.to_string())]))
+                .unwrap();
+        }
+
+        let ids = |entries: Vec<(&StreamId, &StreamFields)>| -> Vec<u64> {
+            entries.into_iter().map(|(id, _)| id.ms).collect()
+        };
+        assert_eq!(
+            ids(stream.range(StreamId::new(2, 0), StreamId::new(4, u64::MAX), None)),
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            ids(stream.range(StreamId::MIN, StreamId::MAX, Some(2))),
+            vec![1, 2]
+        );
+        assert!(stream
+            .range(StreamId::new(4, 0), StreamId::new(2, 0), None)
+            .is_empty());
+
+        assert_eq!(ids(stream.after(StreamId::new(3, 0), None)), vec![4, 5]);
+        assert_eq!(ids(stream.after(StreamId::new(2, 5), Some(1))), vec![3]);
+        assert!(stream.after(StreamId::MAX, None).is_empty());
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let mut stream = Stream::default();
+        for ms in 1..=3 {
+            stream
+                .add(XAddId::AutoSeq(ms), fields(&[("n", "1")]))
+                .unwrap();
+        }
+
+        assert_eq!(stream.create_group("g", StreamId::new(1, 0)), Ok(()));
+        assert_eq!(
+            stream.create_group("g", StreamId::MIN),
+            Err("BUSYGROUP Consumer Group name already exists")
+        );
+        assert!(stream
+            .read_group("other", "c", XReadGroupId::New, None)
+            .is_none());
+
+        let ids = |entries: Option<Vec<(&StreamId, &StreamFields)>>| -> Vec<u64> {
+            entries.unwrap().into_iter().map(|(id, _)| id.ms).collect()
+        };
+        // New entries go to one consumer each, starting after the group's id
+        assert_eq!(
+            ids(stream.read_group("g", "alice", XReadGroupId::New, Some(1))),
+            vec![2]
+        );
+        assert_eq!(
+            ids(stream.read_group("g", "bob", XReadGroupId::New, None)),
+            vec![3]
+        );
+        assert!(ids(stream.read_group("g", "bob", XReadGroupId::New, None)).is_empty());
+
+        let history = XReadGroupId::Pending(StreamId::MIN);
+        assert_eq!(ids(stream.read_group("g", "alice", history, None)), vec![2]);
+        assert_eq!(ids(stream.read_group("g", "bob", history, None)), vec![3]);
+
+        assert_eq!(
+            stream.ack(
+                "g",
+                &[
+                    StreamId::new(2, 0),
+                    StreamId::new(1, 0),
+                    StreamId::new(2, 0)
+                ]
+            ),
+            1
+        );
+        assert_eq!(stream.ack("other", &[StreamId::new(3, 0)]), 0);
+        assert!(ids(stream.read_group("g", "alice", history, None)).is_empty());
+        assert_eq!(ids(stream.read_group("g", "bob", history, None)), vec![3]);
+    }
+}
@@ -5,22 +5,45 @@ use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
 
 use crate::RedisWritable;
 
+/// The longest line `parse` reads, like Redis' default `proto-inline-max-size`.
+pub const DEFAULT_MAX_LINE_LEN: usize = 64 * 1024;
+/// The longest bulk string `parse` reads, like Redis' default `proto-max-bulk-len`.
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// The most elements `parse` reads in an array, the limit Redis puts on multibulk requests.
+pub const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RedisType {
     List { data: Vec<Box<RedisType>> },
     BulkString { data: String },
+    // A bulk string that isn't valid UTF-8. Build it with `bulk_bytes`, which keeps valid UTF-8
+    // as a `BulkString` so the same bytes always compare equal
+    BulkBytes { data: Vec<u8> },
     SimpleString { data: String },
     NullBulkString,
+    NullArray,
     SimpleError { message: String },
+    Integer { data: i64 },
     RDBFile { file: Vec<u8> },
-    // Similar to the list, but it's not acctually a type
+    // Several top level frames sent back to back, with no array header, like PSYNC's
+    // FULLRESYNC line followed by the RDB file. Never parsed, the reader sees each frame on its own
     MultipleType { values: Vec<Box<RedisType>> },
 }
 
 impl RedisType {
-    #[async_recursion]
     pub async fn parse(
         reader: &mut BufReader<impl AsyncRead + Unpin + Send>,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        Self::parse_with_line_limit(reader, DEFAULT_MAX_LINE_LEN).await
+    }
+
+    /// Like `parse`, failing on any line longer than `max_line_len`, terminator aside. Simple
+    /// strings, errors, inline commands and length headers are lines, so a peer that never ends
+    /// one can't make the reader buffer without bound.
+    #[async_recursion]
+    pub async fn parse_with_line_limit(
+        reader: &mut BufReader<impl AsyncRead + Unpin + Send>,
+        max_line_len: usize,
     ) -> Result<Option<Self>, anyhow::Error> {
         let command_char = match reader.read_u8().await {
             Ok(byte) => Ok(byte as char),
@@ -34,47 +57,105 @@ impl RedisType {
 
         Ok(Some(match command_char {
             '*' => {
-                let len: u64 = Self::read_line(reader).await?.parse()?;
+                let header = Self::read_line(reader, max_line_len).await?;
+                let Some(len) = Self::parse_len(&header, MAX_MULTIBULK_LEN)
+                    .ok_or_else(|| anyhow::anyhow!("invalid multibulk length"))?
+                else {
+                    return Ok(Some(Self::NullArray));
+                };
                 let mut elements = Vec::new();
 
-                for _ in 0..len {
-                    if let Some(element) = Self::parse(reader).await? {
-                        elements.push(Box::new(element));
+                for i in 0..len {
+                    match Self::parse_with_line_limit(reader, max_line_len).await? {
+                        Some(element) => elements.push(Box::new(element)),
+                        None => {
+                            return Err(std::io::Error::new(
+                                ErrorKind::UnexpectedEof,
+                                format!("Input ended after {} of {} array elements", i, len),
+                            )
+                            .into())
+                        }
                     }
                 }
 
                 Self::List { data: elements }
             }
             '$' => {
-                let len: i64 = Self::read_line(reader).await?.parse()?;
-                if len == -1 {
-                    Self::NullBulkString
-                } else if len < 0 {
-                    return Err(anyhow::anyhow!("Invalid bulk string len ({})!", len));
-                } else {
-                    let len = len as usize;
-
-                    let mut buffer = vec![0; len + 2]; // +2 for CRLF
-                    reader.read_exact(&mut buffer).await?;
-
-                    let data = String::from_utf8(buffer[..len].to_vec())?;
-
-                    Self::BulkString { data }
+                let header = Self::read_line(reader, max_line_len).await?;
+                match Self::parse_len(&header, MAX_BULK_LEN)
+                    .ok_or_else(|| anyhow::anyhow!("invalid bulk length"))?
+                {
+                    None => Self::NullBulkString,
+                    Some(len) => {
+                        // The buffer grows with what actually arrives, not with the length the
+                        // peer claims
+                        let mut buffer = Vec::new();
+                        reader.take(len as u64).read_to_end(&mut buffer).await?;
+                        if buffer.len() < len {
+                            return Err(std::io::Error::new(
+                                ErrorKind::UnexpectedEof,
+                                format!("Input ended after {} of {} bytes", buffer.len(), len),
+                            )
+                            .into());
+                        }
+                        Self::read_terminator(reader).await?;
+
+                        Self::bulk_bytes_owned(buffer)
+                    }
                 }
             }
-            '+' => {
-                let mut line = String::new();
-                reader.read_line(&mut line).await?;
-                line.truncate(line.len() - 2); // Removing CRLF
-
-                Self::SimpleString { data: line }
-            }
-            character => Self::SimpleError {
-                message: format!("Unknown command {}", character),
+            '+' => Self::SimpleString {
+                data: Self::read_line(reader, max_line_len).await?,
+            },
+            '-' => Self::SimpleError {
+                message: Self::read_line(reader, max_line_len).await?,
             },
+            ':' => Self::Integer {
+                data: Self::read_line(reader, max_line_len).await?.parse()?,
+            },
+            character => {
+                // Inline command, like the ones sent by telnet: words separated by spaces
+                let line = format!(
+                    "{}{}",
+                    character,
+                    Self::read_line(reader, max_line_len).await?
+                );
+
+                Self::list(line.split_whitespace().map(Self::bulk_string).collect())
+            }
         }))
     }
 
+    /// Parses the next frame, waiting for it if needed, along with every following frame that is
+    /// already whole in the reader's buffer. Pipelined commands then reach the caller together
+    /// instead of costing an await each. `None` once the input ends before a new frame.
+    ///
+    /// A malformed frame ends the batch, as there's no telling where the next frame starts after
+    /// it. Like Redis, callers should reply with the error and close the connection.
+    pub async fn parse_batch(
+        reader: &mut BufReader<impl AsyncRead + Unpin + Send>,
+        max_line_len: usize,
+    ) -> Option<Vec<Result<Self, anyhow::Error>>> {
+        let mut batch = vec![Self::parse_with_line_limit(reader, max_line_len)
+            .await
+            .transpose()?];
+
+        // A partial frame is left for the next batch, so the parsed ones don't wait on it
+        while batch.last().is_some_and(Result::is_ok)
+            && Self::complete_frame_len(reader.buffer()).is_some()
+        {
+            match Self::parse_with_line_limit(reader, max_line_len)
+                .await
+                .transpose()
+            {
+                Some(frame) => batch.push(frame),
+                None => break,
+            }
+        }
+
+        Some(batch)
+    }
+
     pub fn extract_string(&self) -> Option<&str> {
         match self {
             RedisType::BulkString { data, .. } | RedisType::SimpleString { data, .. } => Some(data),
@@ -82,6 +163,21 @@ impl RedisType {
         }
     }
 
+    pub fn extract_integer(&self) -> Option<i64> {
+        match self {
+            RedisType::Integer { data } => Some(*data),
+            _ => None,
+        }
+    }
+
+    /// The raw bytes of any kind of string, UTF-8 or not.
+    pub fn extract_bytes(&self) -> Option<&[u8]> {
+        match self {
+            RedisType::BulkBytes { data } => Some(data),
+            other => other.extract_string().map(str::as_bytes),
+        }
+    }
+
     pub fn expect_string(
         &self,
         expected: &str,
@@ -110,6 +206,19 @@ impl RedisType {
         }
     }
 
+    pub fn bulk_bytes(data: &[u8]) -> Self {
+        Self::bulk_bytes_owned(data.to_vec())
+    }
+
+    pub fn bulk_bytes_owned(data: Vec<u8>) -> Self {
+        match String::from_utf8(data) {
+            Ok(data) => RedisType::BulkString { data },
+            Err(e) => RedisType::BulkBytes {
+                data: e.into_bytes(),
+            },
+        }
+    }
+
     pub fn list(data: Vec<Self>) -> Self {
         RedisType::List {
             data: data.into_iter().map(Box::new).collect(),
@@ -122,6 +231,10 @@ impl RedisType {
         }
     }
 
+    pub fn integer(data: i64) -> Self {
+        RedisType::Integer { data }
+    }
+
     pub fn simple_error(message: &str) -> Self {
         RedisType::SimpleError {
             message: message.to_string(),
@@ -136,13 +249,93 @@ impl RedisType {
         ])
     }
 
-    async fn read_line(
+    /// Reads a line, accepting both `\r\n` and a bare `\n` as the terminator. Stops reading
+    /// once the line is known to be longer than `max_len`.
+    pub(crate) async fn read_line(
         reader: &mut BufReader<impl AsyncRead + Unpin>,
+        max_len: usize,
     ) -> Result<String, anyhow::Error> {
-        let mut line = String::new();
-        reader.read_line(&mut line).await?;
+        // Room for the longest line allowed and its terminator
+        let limit = max_len.saturating_add(2);
+        let mut line = Vec::new();
+        let read = reader
+            .take(limit as u64)
+            .read_until(b'\n', &mut line)
+            .await?;
+
+        let terminated = line.ends_with(b"\n");
+        if terminated {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+        if line.len() > max_len || (!terminated && read == limit) {
+            return Err(anyhow::anyhow!("too big inline request"));
+        }
+
+        Ok(String::from_utf8(line)?)
+    }
 
-        Ok(line.trim_end().to_string())
+    /// Parses an array or bulk string length header. `Some(None)` for -1, the null value, and
+    /// `None` when it's not a length up to `max`.
+    pub(crate) fn parse_len(header: &str, max: usize) -> Option<Option<usize>> {
+        match header.parse::<i64>().ok()? {
+            -1 => Some(None),
+            len => usize::try_from(len)
+                .ok()
+                .filter(|len| *len <= max)
+                .map(Some),
+        }
+    }
+
+    async fn read_terminator(
+        reader: &mut BufReader<impl AsyncRead + Unpin>,
+    ) -> Result<(), anyhow::Error> {
+        match reader.read_u8().await? {
+            b'\n' => Ok(()),
+            b'\r' if reader.read_u8().await? == b'\n' => Ok(()),
+            _ => Err(anyhow::anyhow!(
+                "Expected line terminator after bulk string"
+            )),
+        }
+    }
+
+    /// The length of the frame at the start of `buf`, or `None` if it isn't all there yet.
+    /// Malformed headers count as complete, so that parsing gets to report them.
+    fn complete_frame_len(buf: &[u8]) -> Option<usize> {
+        let line_len = buf.iter().position(|&byte| byte == b'\n')? + 1;
+        let header = || -> Option<i64> {
+            std::str::from_utf8(&buf[1..line_len])
+                .ok()?
+                .trim_end()
+                .parse()
+                .ok()
+        };
+
+        match buf[0] {
+            b'*' => {
+                let mut len = line_len;
+                for _ in 0..header().unwrap_or(0).max(0) {
+                    len += Self::complete_frame_len(&buf[len..])?;
+                }
+
+                Some(len)
+            }
+            b'$' => {
+                let Some(data_len) = header().filter(|len| *len >= 0) else {
+                    return Some(line_len);
+                };
+                let data_end = line_len + data_len as usize;
+
+                match buf.get(data_end..)? {
+                    [] | [b'\r'] => None,
+                    [b'\r', b'\n', ..] => Some(data_end + 2),
+                    _ => Some(data_end + 1),
+                }
+            }
+            _ => Some(line_len),
+        }
     }
 
     fn write_rdb_file(file: &[u8]) -> Vec<u8> {
@@ -153,6 +346,13 @@ impl RedisType {
 
         response
     }
+
+    fn write_bulk(data: &[u8]) -> Vec<u8> {
+        let mut response = Self::write_rdb_file(data);
+        response.extend_from_slice(b"\r\n");
+
+        response
+    }
 }
 
 impl RedisWritable for RedisType {
@@ -171,12 +371,13 @@ impl RedisWritable for RedisType {
 
                 bytes
             }
-            RedisType::BulkString { data } => format!("${}\r\n{}\r\n", data.len(), data)
-                .as_bytes()
-                .to_vec(),
+            RedisType::BulkString { data } => RedisType::write_bulk(data.as_bytes()),
+            RedisType::BulkBytes { data } => RedisType::write_bulk(data),
             RedisType::NullBulkString => b"$-1\r\n".to_vec(),
+            RedisType::NullArray => b"*-1\r\n".to_vec(),
             RedisType::SimpleString { data } => format!("+{}\r\n", data).as_bytes().to_vec(),
             RedisType::SimpleError { message } => format!("-{}\r\n", message).as_bytes().to_vec(),
+            RedisType::Integer { data } => format!(":{}\r\n", data).as_bytes().to_vec(),
             RedisType::RDBFile { file } => RedisType::write_rdb_file(file),
             RedisType::MultipleType { values } => values
                 .iter()
@@ -188,10 +389,11 @@ impl RedisWritable for RedisType {
 
 #[cfg(test)]
 mod tests {
-    use crate::rdb_file;
+    use crate::{rdb_file, tests::parse_frames};
 
     use super::*;
     use std::io::Cursor;
+    use tokio::io::AsyncWriteExt;
 
     fn create_buf_reader<'a>(data: &'a str) -> BufReader<impl AsyncRead + Unpin + 'a> {
         BufReader::new(Cursor::new(data))
@@ -213,6 +415,21 @@ mod tests {
         assert_type_equals(input, expected).await
     }
 
+    #[tokio::test]
+    async fn test_integer_round_trip() {
+        let (frames, error) = parse_frames(b":-5\r\n:1000\n").await;
+        assert!(error.is_none());
+        assert_eq!(
+            frames,
+            vec![RedisType::integer(-5), RedisType::integer(1000)]
+        );
+        assert_eq!(frames[0].extract_integer(), Some(-5));
+        assert_eq!(frames[0].write_as_protocol(), b":-5\r\n");
+
+        let (_, error) = parse_frames(b":12a\r\n").await;
+        assert!(error.is_some());
+    }
+
     #[tokio::test]
     async fn test_parse_bulk_string() {
         let input = "$11\r\nHello\nWorld\r\n";
@@ -221,6 +438,72 @@ mod tests {
         assert_type_equals(input, expected).await
     }
 
+    #[tokio::test]
+    async fn test_parse_simple_string_bare_newline() {
+        let input = "+OK\n";
+        let expected = RedisType::simple_string("OK");
+
+        assert_type_equals(input, expected).await
+    }
+
+    #[tokio::test]
+    async fn test_parse_simple_string_keeps_inner_carriage_return() {
+        let input = "+a\rb\r\n";
+        let expected = RedisType::simple_string("a\rb");
+
+        assert_type_equals(input, expected).await
+    }
+
+    #[tokio::test]
+    async fn test_parse_inline_command() {
+        let expected = RedisType::list(vec![
+            RedisType::bulk_string("SET"),
+            RedisType::bulk_string("foo"),
+            RedisType::bulk_string("bar"),
+        ]);
+
+        assert_type_equals("SET foo bar\r\n", expected.clone()).await;
+        assert_type_equals("SET foo  bar\n", expected).await
+    }
+
+    #[tokio::test]
+    async fn test_parse_bulk_string_bare_newline() {
+        let input = "*2\n$4\nECHO\n$2\nhi\n";
+        let expected = RedisType::list(vec![
+            RedisType::bulk_string("ECHO"),
+            RedisType::bulk_string("hi"),
+        ]);
+
+        assert_type_equals(input, expected).await
+    }
+
+    #[tokio::test]
+    async fn test_binary_bulk_string_round_trip() {
+        let bytes = [b'a', 0x00, 0xFF, b'\r', b'\n', b'z'];
+        let value = RedisType::bulk_bytes(&bytes);
+        assert_eq!(
+            value,
+            RedisType::BulkBytes {
+                data: bytes.to_vec()
+            }
+        );
+
+        let encoded = value.write_as_protocol();
+        assert_eq!(encoded, b"$6\r\na\x00\xFF\r\nz\r\n");
+
+        let (frames, error) = parse_frames(&encoded).await;
+        assert!(error.is_none());
+        assert_eq!(frames, vec![value]);
+        assert_eq!(frames[0].extract_bytes(), Some(&bytes[..]));
+        assert_eq!(frames[0].extract_string(), None);
+
+        // Valid UTF-8, NUL bytes included, stays a regular bulk string
+        assert_eq!(
+            RedisType::bulk_bytes(b"a\x00b"),
+            RedisType::bulk_string("a\0b")
+        );
+    }
+
     #[tokio::test]
     async fn test_parse_null_bulk_string() {
         let input = "$-1\r\n";
@@ -229,6 +512,30 @@ mod tests {
         assert_type_equals(input, expected).await
     }
 
+    #[tokio::test]
+    async fn test_parse_null_array() {
+        assert_type_equals("*-1\r\n", RedisType::NullArray).await;
+
+        let (frames, error) = parse_frames(b"*-1\r\n+OK\r\n").await;
+        assert!(error.is_none());
+        assert_eq!(
+            frames,
+            vec![RedisType::NullArray, RedisType::simple_string("OK")]
+        );
+
+        let (_, error) = parse_frames(b"*-2\r\n").await;
+        assert!(error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_null_array_round_trip() {
+        let bytes = RedisType::NullArray.write_as_protocol();
+        assert_eq!(bytes, b"*-1\r\n");
+
+        let (frames, _) = parse_frames(&bytes).await;
+        assert_eq!(frames, vec![RedisType::NullArray]);
+    }
+
     #[tokio::test]
     async fn test_parse_list() {
         let input = "*2\r\n$3\r\nfoo\r\n$4\r\nbarr\r\n";
@@ -241,14 +548,253 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_parse_rdb_file() {
-        let empty_file = RedisType::write_rdb_file(&rdb_file::get_empty_rdb_decoded());
-        let expected = RedisType::RDBFile {
-            file: rdb_file::get_empty_rdb_decoded(),
+    async fn test_parse_truncated_list() {
+        let (frames, error) = parse_frames(b"*3\r\n$3\r\nfoo\r\n").await;
+
+        assert!(frames.is_empty());
+        assert!(error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_binary_value_looking_like_rdb_is_a_bulk_string() {
+        // Only the replica handshake reads RDB files, which lack the trailing CRLF. A client's
+        // value with the same prefix is an ordinary bulk string
+        let value = b"REDIS\xff\xfe";
+        let mut input = RedisType::bulk_bytes(value).write_as_protocol();
+        input.extend(b"+PING\r\n");
+
+        let (frames, error) = parse_frames(&input).await;
+        assert!(error.is_none());
+        assert_eq!(
+            frames,
+            vec![
+                RedisType::bulk_bytes(value),
+                RedisType::simple_string("PING")
+            ]
+        );
+        assert_eq!(
+            RedisType::complete_frame_len(&input),
+            Some(input.len() - b"+PING\r\n".len())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multiple_type_writes_frames_back_to_back() {
+        let file = rdb_file::get_empty_rdb_decoded();
+        let reply = RedisType::multiple(vec![
+            RedisType::simple_string("FULLRESYNC abc 0"),
+            RedisType::RDBFile { file: file.clone() },
+        ]);
+
+        let mut expected = b"+FULLRESYNC abc 0\r\n".to_vec();
+        expected.extend(format!("${}\r\n", file.len()).as_bytes());
+        expected.extend(&file);
+        assert_eq!(reply.write_as_protocol(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_parse_inline_then_pipelined_resp() {
+        let script = b"PING\r\n*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\nECHO hi\n";
+
+        let (frames, error) = parse_frames(script).await;
+        assert!(error.is_none());
+        assert_eq!(
+            frames,
+            vec![
+                RedisType::list(vec![RedisType::bulk_string("PING")]),
+                RedisType::list(vec![
+                    RedisType::bulk_string("SET"),
+                    RedisType::bulk_string("foo"),
+                    RedisType::bulk_string("bar"),
+                ]),
+                RedisType::list(vec![
+                    RedisType::bulk_string("ECHO"),
+                    RedisType::bulk_string("hi"),
+                ]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_batch_keeps_pipelined_order() {
+        let mut script = Vec::new();
+        for i in 0..1000 {
+            script.extend(
+                RedisType::list(vec![
+                    RedisType::bulk_string("ECHO"),
+                    RedisType::bulk_string(&i.to_string()),
+                ])
+                .write_as_protocol(),
+            );
+        }
+
+        let mut reader = BufReader::new(&script[..]);
+        let mut frames = Vec::new();
+        let mut batches = 0;
+        while let Some(batch) = RedisType::parse_batch(&mut reader, DEFAULT_MAX_LINE_LEN).await {
+            batches += 1;
+            frames.extend(batch.into_iter().map(|frame| frame.unwrap()));
+        }
+
+        assert_eq!(frames.len(), 1000);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(
+                frame,
+                &RedisType::list(vec![
+                    RedisType::bulk_string("ECHO"),
+                    RedisType::bulk_string(&i.to_string()),
+                ])
+            );
+        }
+        // Frames are drained a whole buffer at a time, not one per call
+        assert!(batches < 100);
+    }
+
+    #[tokio::test]
+    async fn test_parse_batch_does_not_wait_for_partial_frame() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        client
+            .write_all(b"+ONE\r\n*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\n$5\r\nab")
+            .await
+            .unwrap();
+
+        let mut reader = BufReader::new(server);
+        let batch = RedisType::parse_batch(&mut reader, DEFAULT_MAX_LINE_LEN)
+            .await
+            .unwrap();
+        let frames: Vec<_> = batch.into_iter().map(|frame| frame.unwrap()).collect();
+        assert_eq!(
+            frames,
+            vec![
+                RedisType::simple_string("ONE"),
+                RedisType::list(vec![
+                    RedisType::bulk_string("ECHO"),
+                    RedisType::bulk_string("hi"),
+                ]),
+            ]
+        );
+
+        client.write_all(b"cde\r\n").await.unwrap();
+        let batch = RedisType::parse_batch(&mut reader, DEFAULT_MAX_LINE_LEN)
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].as_ref().unwrap(), &RedisType::bulk_string("abcde"));
+    }
+
+    #[test]
+    fn test_complete_frame_len() {
+        assert_eq!(RedisType::complete_frame_len(b"+OK\r\n+NEXT"), Some(5));
+        assert_eq!(RedisType::complete_frame_len(b"+OK"), None);
+        assert_eq!(RedisType::complete_frame_len(b"$2\nhi\n"), Some(6));
+        assert_eq!(RedisType::complete_frame_len(b"$2\r\nhi\r"), None);
+        assert_eq!(RedisType::complete_frame_len(b"$-1\r\n"), Some(5));
+        assert_eq!(RedisType::complete_frame_len(b"*2\r\n+a\r\n"), None);
+        assert_eq!(
+            RedisType::complete_frame_len(b"*2\r\n+a\r\n+b\r\n"),
+            Some(12)
+        );
+        assert_eq!(RedisType::complete_frame_len(b"$abc\r\n"), Some(6));
+    }
+
+    #[tokio::test]
+    async fn test_parse_partial_frame_is_an_error() {
+        let (frames, error) = parse_frames(b"+OK\r\n$5\r\nab").await;
+
+        assert_eq!(frames, vec![RedisType::simple_string("OK")]);
+        assert!(error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_parse_batch_ends_at_protocol_error() {
+        let mut reader = BufReader::new(&b"+OK\r\n$abc\r\n*1\r\n$4\r\nPING\r\n"[..]);
+
+        let batch = RedisType::parse_batch(&mut reader, DEFAULT_MAX_LINE_LEN)
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].as_ref().unwrap(), &RedisType::simple_string("OK"));
+        assert!(batch[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_line_limit() {
+        let parse = |input: &'static [u8]| async move {
+            RedisType::parse_with_line_limit(&mut BufReader::new(input), 4).await
+        };
+
+        assert_eq!(
+            parse(b"+PONG\r\n").await.unwrap(),
+            Some(RedisType::simple_string("PONG"))
+        );
+        assert_eq!(
+            parse(b"-ERR!\n").await.unwrap(),
+            Some(RedisType::simple_error("ERR!"))
+        );
+        for too_long in [
+            &b"+PONGS\r\n"[..],
+            b"-ERROR\n",
+            b"PING a\r\n",
+            b"*00001\r\n",
+        ] {
+            let error = parse(too_long).await.unwrap_err();
+            assert_eq!(error.to_string(), "too big inline request");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_length_limits() {
+        let parse = |input: String| async move {
+            RedisType::parse(&mut BufReader::new(input.as_bytes())).await
         };
 
-        let parsed = RedisType::parse(&mut BufReader::new(Cursor::new(empty_file))).await;
+        // Refused from the header alone, before reading or allocating anything for the data
+        for header in [
+            format!("${}", MAX_BULK_LEN + 1),
+            "$-2".to_string(),
+            "$abc".to_string(),
+        ] {
+            let error = parse(format!("{}\r\n", header)).await.unwrap_err();
+            assert_eq!(error.to_string(), "invalid bulk length", "{:?}", header);
+        }
+        for header in [format!("*{}", MAX_MULTIBULK_LEN + 1), "*-2".to_string()] {
+            let error = parse(format!("{}\r\n", header)).await.unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "invalid multibulk length",
+                "{:?}",
+                header
+            );
+        }
+
+        // A length within the limit is only trusted as far as the data goes
+        let error = parse(format!("${}\r\nabc", MAX_BULK_LEN))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast::<std::io::Error>().unwrap().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            parse("$3\r\nabc\r\n".to_string()).await.unwrap(),
+            Some(RedisType::bulk_string("abc"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_unterminated_line_is_not_buffered() {
+        // A peer sending a line that never ends
+        let mut reader = BufReader::new(tokio::io::repeat(b'a'));
+        let error = RedisType::parse(&mut reader).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "too big inline request");
+    }
+
+    #[tokio::test]
+    async fn test_parse_protocol_error_stops_parsing() {
+        let (frames, error) = parse_frames(b"+OK\r\n$abc\r\n+NEXT\r\n").await;
 
-        assert_eq!(parsed.unwrap().unwrap(), expected);
+        assert_eq!(frames, vec![RedisType::simple_string("OK")]);
+        assert!(error.is_some());
     }
 }
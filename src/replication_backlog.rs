@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+
+/// Circular buffer holding the most recent bytes of the replication stream, so a
+/// replica that briefly disconnects can be caught up without a full resync.
+#[derive(Debug)]
+pub struct ReplicationBacklog {
+    data: VecDeque<u8>,
+    size: usize,
+    active: bool,
+    offset: u64,
+}
+
+impl ReplicationBacklog {
+    pub fn new(size: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            size,
+            active: false,
+            offset: 0,
+        }
+    }
+
+    /// The backlog only starts recording once the first replica attaches, as in Redis.
+    pub fn activate(&mut self) {
+        self.active = true;
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        if !self.active {
+            return;
+        }
+
+        self.offset += bytes.len() as u64;
+
+        // Only the tail of a write bigger than the whole backlog can ever be kept
+        let bytes = &bytes[bytes.len().saturating_sub(self.size)..];
+        let overflow = (self.data.len() + bytes.len()).saturating_sub(self.size);
+        self.data.drain(..overflow);
+        self.data.extend(bytes);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Offset of the last byte written to the replication stream.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn histlen(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn first_byte_offset(&self) -> u64 {
+        self.offset - self.data.len() as u64 + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inactive_backlog_ignores_writes() {
+        let mut backlog = ReplicationBacklog::new(10);
+        backlog.feed(b"hello");
+
+        assert!(!backlog.is_active());
+        assert_eq!(backlog.histlen(), 0);
+        assert_eq!(backlog.offset(), 0);
+    }
+
+    #[test]
+    fn test_backlog_evicts_oldest_bytes() {
+        let mut backlog = ReplicationBacklog::new(8);
+        backlog.activate();
+
+        backlog.feed(b"hello");
+        assert_eq!(backlog.histlen(), 5);
+        assert_eq!(backlog.first_byte_offset(), 1);
+
+        backlog.feed(b"world");
+        assert_eq!(backlog.offset(), 10);
+        assert_eq!(backlog.histlen(), 8);
+        assert_eq!(backlog.first_byte_offset(), 3);
+        assert_eq!(backlog.data, b"lloworld".to_vec());
+
+        backlog.feed(b"0123456789");
+        assert_eq!(backlog.offset(), 20);
+        assert_eq!(backlog.data, b"23456789".to_vec());
+    }
+}
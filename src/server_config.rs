@@ -1,9 +1,29 @@
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::{
+    fs, io,
+    net::{SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::redis_type::DEFAULT_MAX_LINE_LEN;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct ServerConfig {
     pub port: u16,
     pub replica_addr: Option<SocketAddr>,
+    pub repl_backlog_size: usize,
+    // Worker threads for the tokio runtime. Tokio's default, one per core, when not set
+    pub io_threads: Option<usize>,
+    pub maxclients: usize,
+    // Verify the CRC64 of RDB files received from a master
+    pub rdbchecksum: bool,
+    // How long a replica waits on each step of the handshake with its master
+    pub repl_timeout: Duration,
+    // Whether a replica rejects writes from its own clients
+    pub replica_read_only: bool,
+    // The longest line a client can send, like an inline command or a simple string
+    pub proto_inline_max_size: usize,
 }
 
 impl Default for ServerConfig {
@@ -11,46 +31,142 @@ impl Default for ServerConfig {
         Self {
             port: 6379,
             replica_addr: None,
+            repl_backlog_size: 1024 * 1024,
+            io_threads: None,
+            maxclients: 10000,
+            rdbchecksum: true,
+            repl_timeout: Duration::from_secs(60),
+            replica_read_only: true,
+            proto_inline_max_size: DEFAULT_MAX_LINE_LEN,
         }
     }
 }
 
 impl ServerConfig {
+    /// Parses the arguments after the program name. Like redis-server, a config file path can be
+    /// given as the first argument, and any `--option value` flags override what it sets.
     pub fn parse_command_line_args(args: &[String]) -> Self {
         let mut initial_config = Self::default();
-        let mut args_iter = args.iter();
+        let mut args_iter = args.iter().peekable();
+
+        if let Some(path) = args_iter.next_if(|arg| !arg.starts_with("--")) {
+            initial_config.load_config_file(path);
+        }
 
         while let Some(arg) = args_iter.next() {
-            if arg == "--port" {
-                if let Some(p) = args_iter.next() {
-                    initial_config.port = p
-                        .parse()
-                        .unwrap_or_else(|_| panic!("Invalid port number provided: {}", p));
-                } else {
-                    panic!("Please provide a port value");
+            if let Some(name) = arg.strip_prefix("--") {
+                match args_iter.next() {
+                    Some(value) => initial_config.apply_option(name, value),
+                    None => panic!("Please provide a value for {}", arg),
                 }
-            } else if arg == "--replicaof" {
-                if let Some(addr) = args_iter.next() {
-                    match addr.replace(' ', ":").to_socket_addrs() {
-                        Ok(mut addrs) => {
-                            if let Some(address) = addrs.next() {
-                                initial_config.replica_addr = Some(address);
-                            } else {
-                                panic!("No valid addresses found for the provided replica address");
-                            }
-                        }
-                        Err(_) => panic!("Invalid address specified: {}", addr),
+            }
+        }
+
+        initial_config
+    }
+
+    fn load_config_file(&mut self, path: &str) {
+        let contents = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Could not read config file {}: {}", path, e));
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim().trim_matches('"');
+            self.apply_option(&name.to_lowercase(), value);
+        }
+    }
+
+    fn apply_option(&mut self, name: &str, value: &str) {
+        match name {
+            "port" => {
+                self.port = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid port number provided: {}", value));
+            }
+            "replicaof" | "slaveof" => match value.replace(' ', ":").to_socket_addrs() {
+                Ok(mut addrs) => {
+                    if let Some(address) = addrs.next() {
+                        self.replica_addr = Some(address);
+                    } else {
+                        panic!("No valid addresses found for the provided replica address");
                     }
-                } else {
-                    panic!("Please provide a master address");
                 }
+                Err(_) => panic!("Invalid address specified: {}", value),
+            },
+            "repl-backlog-size" => {
+                self.repl_backlog_size = parse_memory(value)
+                    .unwrap_or_else(|| panic!("Invalid backlog size provided: {}", value));
+            }
+            "io-threads" => match value.parse() {
+                Ok(threads) if threads > 0 => self.io_threads = Some(threads),
+                _ => panic!("Invalid number of io threads provided: {}", value),
+            },
+            "maxclients" => match value.parse() {
+                Ok(maxclients) if maxclients > 0 => self.maxclients = maxclients,
+                _ => panic!("Invalid maxclients provided: {}", value),
+            },
+            "rdbchecksum" => self.rdbchecksum = parse_yes_no(name, value),
+            "repl-timeout" => match value.parse() {
+                Ok(seconds) if seconds > 0 => self.repl_timeout = Duration::from_secs(seconds),
+                _ => panic!("Invalid repl-timeout provided: {}", value),
+            },
+            "replica-read-only" | "slave-read-only" => {
+                self.replica_read_only = parse_yes_no(name, value)
             }
+            "proto-inline-max-size" => match parse_memory(value) {
+                Some(size) if size > 0 => self.proto_inline_max_size = size,
+                _ => panic!("Invalid proto-inline-max-size provided: {}", value),
+            },
+            unknown => println!("Ignoring unsupported config option: {}", unknown),
         }
+    }
 
-        initial_config
+    /// Builds the multi-threaded tokio runtime the server runs on. All connections share the
+    /// same keyspace, so extra threads add parallelism for I/O and parsing, not data sharding.
+    pub fn build_runtime(&self) -> io::Result<Runtime> {
+        let mut builder = Builder::new_multi_thread();
+        if let Some(threads) = self.io_threads {
+            builder.worker_threads(threads);
+        }
+
+        builder.enable_all().build()
     }
 }
 
+fn parse_yes_no(name: &str, value: &str) -> bool {
+    match value.to_lowercase().as_str() {
+        "yes" => true,
+        "no" => false,
+        _ => panic!("Invalid {} provided, expected yes or no: {}", name, value),
+    }
+}
+
+/// Parses a redis.conf style memory amount, like `1000`, `10k` (10 * 1000) or `10kb` (10 * 1024).
+fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_lowercase();
+    let digits_end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(digits_end);
+
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    amount.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,7 +179,8 @@ mod tests {
             config,
             ServerConfig {
                 port: 6379,
-                replica_addr: None
+                replica_addr: None,
+                ..Default::default()
             }
         );
     }
@@ -76,7 +193,8 @@ mod tests {
             config,
             ServerConfig {
                 port: 8080,
-                replica_addr: None
+                replica_addr: None,
+                ..Default::default()
             }
         );
     }
@@ -97,7 +215,8 @@ mod tests {
             config,
             ServerConfig {
                 port: 6379,
-                replica_addr: Some(expected_addr)
+                replica_addr: Some(expected_addr),
+                ..Default::default()
             }
         );
     }
@@ -116,8 +235,137 @@ mod tests {
             config,
             ServerConfig {
                 port: 8333,
-                replica_addr: Some(expected_addr)
+                replica_addr: Some(expected_addr),
+                ..Default::default()
             }
         );
     }
+
+    #[test]
+    fn test_parse_repl_backlog_size() {
+        let args = vec!["--repl-backlog-size".to_string(), "2048".to_string()];
+        let config = ServerConfig::parse_command_line_args(&args);
+        assert_eq!(config.repl_backlog_size, 2048);
+    }
+
+    #[test]
+    fn test_parse_io_threads() {
+        let args = vec!["--io-threads".to_string(), "4".to_string()];
+        let config = ServerConfig::parse_command_line_args(&args);
+        assert_eq!(config.io_threads, Some(4));
+    }
+
+    #[test]
+    fn test_parse_maxclients() {
+        let args = vec!["--maxclients".to_string(), "2".to_string()];
+        let config = ServerConfig::parse_command_line_args(&args);
+        assert_eq!(config.maxclients, 2);
+        assert_eq!(ServerConfig::default().maxclients, 10000);
+    }
+
+    #[test]
+    fn test_parse_proto_inline_max_size() {
+        let args = vec!["--proto-inline-max-size".to_string(), "1kb".to_string()];
+        let config = ServerConfig::parse_command_line_args(&args);
+        assert_eq!(config.proto_inline_max_size, 1024);
+        assert_eq!(ServerConfig::default().proto_inline_max_size, 64 * 1024);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid number of io threads provided: 0")]
+    fn test_parse_zero_io_threads() {
+        let args = vec!["--io-threads".to_string(), "0".to_string()];
+        let _config = ServerConfig::parse_command_line_args(&args);
+    }
+
+    #[test]
+    fn test_build_runtime_with_io_threads() {
+        let threads = 3;
+        let config = ServerConfig {
+            io_threads: Some(threads),
+            ..Default::default()
+        };
+        let runtime = config.build_runtime().unwrap();
+
+        // Only completes if the runtime has at least `threads` workers to block at once
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(threads));
+        runtime.block_on(async {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    let barrier = std::sync::Arc::clone(&barrier);
+                    tokio::spawn(async move {
+                        barrier.wait();
+                    })
+                })
+                .collect();
+
+            for handle in handles {
+                handle.await.unwrap();
+            }
+        });
+    }
+
+    fn write_config_file(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.conf", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let path = write_config_file(
+            "test_parse_config_file",
+            "# A comment\n\nport 7000\nreplicaof 127.0.0.1 6000\nrepl-backlog-size 2mb\nio-threads \"2\"\nsave 900 1\nrdbchecksum no\nreplica-read-only no\n",
+        );
+
+        let config = ServerConfig::parse_command_line_args(std::slice::from_ref(&path));
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            config,
+            ServerConfig {
+                port: 7000,
+                replica_addr: Some("127.0.0.1:6000".parse().unwrap()),
+                repl_backlog_size: 2 * 1024 * 1024,
+                io_threads: Some(2),
+                maxclients: 10000,
+                rdbchecksum: false,
+                repl_timeout: Duration::from_secs(60),
+                replica_read_only: false,
+                proto_inline_max_size: DEFAULT_MAX_LINE_LEN,
+            }
+        );
+    }
+
+    #[test]
+    fn test_command_line_overrides_config_file() {
+        let path = write_config_file(
+            "test_command_line_overrides_config_file",
+            "port 7000\nrepl-backlog-size 1000\n",
+        );
+
+        let args = vec![path.clone(), "--port".to_string(), "7001".to_string()];
+        let config = ServerConfig::parse_command_line_args(&args);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.repl_backlog_size, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Could not read config file")]
+    fn test_missing_config_file() {
+        let _config = ServerConfig::parse_command_line_args(&["/does/not/exist.conf".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1k"), Some(1000));
+        assert_eq!(parse_memory("1KB"), Some(1024));
+        assert_eq!(parse_memory("3mb"), Some(3 * 1024 * 1024));
+        assert_eq!(parse_memory("1g"), Some(1000 * 1000 * 1000));
+        assert_eq!(parse_memory("mb"), None);
+        assert_eq!(parse_memory("10tb"), None);
+    }
 }
@@ -0,0 +1,172 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Counters behind INFO stats, commandstats and errorstats. They only grow until
+/// `CONFIG RESETSTAT` zeroes them.
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    total_commands_processed: AtomicU64,
+    total_error_replies: AtomicU64,
+    keyspace_hits: AtomicU64,
+    keyspace_misses: AtomicU64,
+    // Sorted so INFO lists commands and errors in a stable order
+    commands: Mutex<BTreeMap<&'static str, CommandStats>>,
+    errors: Mutex<BTreeMap<String, u64>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    pub failed_calls: u64,
+}
+
+impl ServerStats {
+    /// Records a call to `command` that took `elapsed`. `error` is the reply's error message,
+    /// when the command failed.
+    pub fn record_call(&self, command: &'static str, elapsed: Duration, error: Option<&str>) {
+        self.total_commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+
+        let mut commands = self.commands.lock().unwrap();
+        let stats = commands.entry(command).or_default();
+        stats.calls += 1;
+        stats.usec += elapsed.as_micros() as u64;
+
+        if let Some(error) = error {
+            stats.failed_calls += 1;
+            drop(commands);
+            self.record_error(error);
+        }
+    }
+
+    /// Counts an error reply under its prefix, the first word of the message, as Redis does.
+    pub fn record_error(&self, message: &str) {
+        self.total_error_replies.fetch_add(1, Ordering::Relaxed);
+
+        let prefix = message.split_whitespace().next().unwrap_or("ERR");
+        *self
+            .errors
+            .lock()
+            .unwrap()
+            .entry(prefix.to_string())
+            .or_default() += 1;
+    }
+
+    /// Counts a read command's key lookup as a hit or a miss.
+    pub fn record_keyspace_lookup(&self, found: bool) {
+        let counter = if found {
+            &self.keyspace_hits
+        } else {
+            &self.keyspace_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.total_commands_processed.store(0, Ordering::Relaxed);
+        self.total_error_replies.store(0, Ordering::Relaxed);
+        self.keyspace_hits.store(0, Ordering::Relaxed);
+        self.keyspace_misses.store(0, Ordering::Relaxed);
+        self.commands.lock().unwrap().clear();
+        self.errors.lock().unwrap().clear();
+    }
+
+    pub fn info_stats(&self) -> String {
+        format!(
+            "# Stats\ntotal_commands_processed:{}\nkeyspace_hits:{}\nkeyspace_misses:{}\ntotal_error_replies:{}",
+            self.total_commands_processed.load(Ordering::Relaxed),
+            self.keyspace_hits.load(Ordering::Relaxed),
+            self.keyspace_misses.load(Ordering::Relaxed),
+            self.total_error_replies.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn info_commandstats(&self) -> String {
+        let mut info = "# Commandstats".to_string();
+
+        for (command, stats) in self.commands.lock().unwrap().iter() {
+            info.push_str(&format!(
+                "\ncmdstat_{}:calls={},usec={},usec_per_call={:.2},rejected_calls=0,failed_calls={}",
+                command,
+                stats.calls,
+                stats.usec,
+                stats.usec as f64 / stats.calls as f64,
+                stats.failed_calls,
+            ));
+        }
+
+        info
+    }
+
+    pub fn info_errorstats(&self) -> String {
+        let mut info = "# Errorstats".to_string();
+
+        for (prefix, count) in self.errors.lock().unwrap().iter() {
+            info.push_str(&format!("\nerrorstat_{}:count={}", prefix, count));
+        }
+
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_call() {
+        let stats = ServerStats::default();
+        stats.record_call("get", Duration::from_micros(10), None);
+        stats.record_call("get", Duration::from_micros(20), None);
+        stats.record_call(
+            "append",
+            Duration::from_micros(5),
+            Some("WRONGTYPE Operation"),
+        );
+
+        assert!(stats.info_stats().contains("total_commands_processed:3\n"));
+        assert!(stats.info_stats().contains("total_error_replies:1"));
+        assert_eq!(
+            stats.info_commandstats(),
+            "# Commandstats\n\
+             cmdstat_append:calls=1,usec=5,usec_per_call=5.00,rejected_calls=0,failed_calls=1\n\
+             cmdstat_get:calls=2,usec=30,usec_per_call=15.00,rejected_calls=0,failed_calls=0"
+        );
+        assert_eq!(
+            stats.info_errorstats(),
+            "# Errorstats\nerrorstat_WRONGTYPE:count=1"
+        );
+    }
+
+    #[test]
+    fn test_keyspace_lookups() {
+        let stats = ServerStats::default();
+        stats.record_keyspace_lookup(true);
+        stats.record_keyspace_lookup(true);
+        stats.record_keyspace_lookup(false);
+
+        assert!(stats
+            .info_stats()
+            .contains("keyspace_hits:2\nkeyspace_misses:1"));
+    }
+
+    #[test]
+    fn test_reset() {
+        let stats = ServerStats::default();
+        stats.record_call("get", Duration::from_micros(10), Some("ERR boom"));
+        stats.record_keyspace_lookup(true);
+        stats.reset();
+
+        assert!(stats.info_stats().contains("total_commands_processed:0"));
+        assert!(stats.info_stats().contains("keyspace_hits:0"));
+        assert_eq!(stats.info_commandstats(), "# Commandstats");
+        assert_eq!(stats.info_errorstats(), "# Errorstats");
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+};
+
+// Per member bookkeeping on top of the member bytes: the map entry, the tree node and the score
+const MEMBER_OVERHEAD: usize = 64;
+
+/// A sorted set score. Scores are never NaN, so ordering them with `total_cmp` is the usual
+/// numeric order.
+#[derive(Debug, Clone, Copy)]
+pub struct Score(pub f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Which end of a sorted set to pop members from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopEnd {
+    Min,
+    Max,
+}
+
+/// Members with a score each, ordered by score and then by member, like a Redis zset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortedSet {
+    scores: HashMap<String, Score>,
+    ordered: BTreeSet<(Score, String)>,
+}
+
+impl SortedSet {
+    /// Sets the score of `member`, returning whether it's a new member.
+    pub fn insert(&mut self, member: &str, score: f64) -> bool {
+        let score = Score(score);
+
+        match self.scores.insert(member.to_string(), score) {
+            Some(previous) => {
+                if previous != score {
+                    self.ordered.remove(&(previous, member.to_string()));
+                    self.ordered.insert((score, member.to_string()));
+                }
+                false
+            }
+            None => {
+                self.ordered.insert((score, member.to_string()));
+                true
+            }
+        }
+    }
+
+    /// Removes the member with the lowest or highest score, returning it with its score.
+    pub fn pop(&mut self, end: PopEnd) -> Option<(String, f64)> {
+        let (score, member) = match end {
+            PopEnd::Min => self.ordered.pop_first()?,
+            PopEnd::Max => self.ordered.pop_last()?,
+        };
+        self.scores.remove(&member);
+
+        Some((member, score.0))
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).map(|score| score.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// The members and their scores, lowest score first.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.ordered
+            .iter()
+            .map(|(score, member)| (member.as_str(), score.0))
+    }
+
+    /// Approximate bytes held by the members, for `MEMORY USAGE`. Each member is stored twice.
+    pub fn memory_usage(&self) -> usize {
+        self.scores
+            .keys()
+            .map(|member| MEMBER_OVERHEAD + 2 * member.capacity())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_order() {
+        let mut set = SortedSet::default();
+
+        assert!(set.insert("b", 2.0));
+        assert!(set.insert("a", 2.0));
+        assert!(set.insert("c", -1.5));
+        assert!(!set.insert("c", 3.0));
+
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.score("c"), Some(3.0));
+        assert_eq!(set.score("missing"), None);
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            vec![("a", 2.0), ("b", 2.0), ("c", 3.0)]
+        );
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut set = SortedSet::default();
+        set.insert("a", 1.0);
+        set.insert("b", 2.0);
+        set.insert("c", 3.0);
+
+        assert_eq!(set.pop(PopEnd::Max), Some(("c".to_string(), 3.0)));
+        assert_eq!(set.pop(PopEnd::Min), Some(("a".to_string(), 1.0)));
+        assert_eq!(set.score("a"), None);
+        assert_eq!(set.pop(PopEnd::Min), Some(("b".to_string(), 2.0)));
+        assert_eq!(set.pop(PopEnd::Max), None);
+        assert!(set.is_empty());
+    }
+}